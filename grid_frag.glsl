@@ -0,0 +1,55 @@
+#version 330 core
+
+in vec3 near_point;
+in vec3 far_point;
+
+out vec4 frag_color;
+
+uniform mat4 view_projection;
+uniform vec3 camera_position;
+uniform float cell_size;
+uniform float fade_distance;
+uniform vec4 grid_color;
+
+// Coverage of lines every `spacing` units, one pixel wide whatever the
+// distance thanks to the screen-space derivative.
+float grid_lines(vec2 coord, float spacing)
+{
+    vec2 scaled = coord / spacing;
+    vec2 derivative = fwidth(scaled);
+    vec2 line_distance = abs(fract(scaled - 0.5) - 0.5) / derivative;
+    // Lines closer together than a few pixels would only alias.
+    float density_fade = 1.0 - smoothstep(0.1, 0.3, max(derivative.x, derivative.y));
+    return (1.0 - min(min(line_distance.x, line_distance.y), 1.0)) * density_fade;
+}
+
+void main()
+{
+    // Intersect the view ray with the y = 0 plane.
+    float t = -near_point.y / (far_point.y - near_point.y);
+    if (t <= 0.0 || t > 1.0) {
+        discard;
+    }
+    vec3 pos = near_point + t * (far_point - near_point);
+
+    vec4 clip = view_projection * vec4(pos, 1.0);
+    gl_FragDepth = clip.z / clip.w * 0.5 + 0.5;
+
+    float minor = grid_lines(pos.xz, cell_size);
+    float major = grid_lines(pos.xz, cell_size * 10.0);
+    vec4 color = vec4(grid_color.rgb, grid_color.a * max(minor * 0.5, major));
+
+    // World X and Z axes, in the colors of the axes gizmo.
+    vec2 axis_width = fwidth(pos.xz);
+    float x_axis = 1.0 - min(abs(pos.z) / axis_width.y, 1.0);
+    float z_axis = 1.0 - min(abs(pos.x) / axis_width.x, 1.0);
+    color = mix(color, vec4(1.0, 0.2, 0.2, 1.0), x_axis);
+    color = mix(color, vec4(0.2, 0.2, 1.0, 1.0), z_axis);
+
+    float distance_to_camera = distance(pos, camera_position);
+    color.a *= 1.0 - smoothstep(fade_distance * 0.5, fade_distance, distance_to_camera);
+    if (color.a <= 0.0) {
+        discard;
+    }
+    frag_color = color;
+}
@@ -0,0 +1,22 @@
+#version 330 core
+
+// Full-screen triangle, each corner unprojected onto the near and far
+// planes so that the fragment shader can cast a ray against the ground.
+out vec3 near_point;
+out vec3 far_point;
+
+uniform mat4 inverse_view_projection;
+
+vec3 unproject(vec2 ndc, float z)
+{
+    vec4 point = inverse_view_projection * vec4(ndc, z, 1.0);
+    return point.xyz / point.w;
+}
+
+void main()
+{
+    vec2 pos = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2) * 2.0 - 1.0;
+    near_point = unproject(pos, -1.0);
+    far_point = unproject(pos, 1.0);
+    gl_Position = vec4(pos, 0.0, 1.0);
+}
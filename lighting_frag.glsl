@@ -1,16 +1,42 @@
 #version 330 core
 
-out vec4 frag_color;
+layout(location = 0) out vec4 frag_color;
+// only written to with weighted_oit, see oit.rs
+layout(location = 1) out float frag_revealage;
 
 struct Material
 {
     vec3 ambient;
     vec3 diffuse;
     vec3 specular;
+    vec3 emissive;
     float shininess;
 
+    // MTL d (or 1 - Tr)
+    float dissolve;
+    // MTL illum: 0 is color only, 1 has no specular
+    int illumination_model;
+    // fragments with less alpha are discarded, 0 when not alpha tested
+    float alpha_cutoff;
+
     sampler2D diffuse_tex;
     sampler2D specular_tex;
+    sampler2D ambient_tex;
+    sampler2D emissive_tex;
+    sampler2D alpha_tex;
+
+    bool has_diffuse_tex;
+    bool has_specular_tex;
+    bool has_ambient_tex;
+    bool has_emissive_tex;
+    bool has_alpha_tex;
+
+    // MTL -s and -o map options as (scale.xy, offset.xy)
+    vec4 diffuse_uv;
+    vec4 specular_uv;
+    vec4 ambient_uv;
+    vec4 emissive_uv;
+    vec4 alpha_uv;
 };
 
 struct Light 
@@ -27,31 +53,100 @@ struct Light
 in vec3 frag_pos; 
 in vec3 normal;
 in vec2 tex_coords;
+in vec4 instance_color;
 
 uniform vec3 view_pos;
 
 uniform Material material;
 uniform Light light;
+uniform bool weighted_oit;
 
 
-void main() 
+vec2 map_coords(vec4 uv)
 {
-    vec3 ambient = light.ambient * material.ambient * texture(material.diffuse_tex, tex_coords).rgb;
+    return tex_coords * uv.xy + uv.zw;
+}
 
 
+// Color maps are sampled from sRGB textures, so MTL colors are decoded the
+// same way and lighting happens in linear space.
+vec3 srgb_to_linear(vec3 color)
+{
+    return mix(color / 12.92, pow((color + 0.055) / 1.055, vec3(2.4)),
+               step(vec3(0.04045), color));
+}
 
-    vec3 norm = normalize(normal);
-    vec3 light_dir = normalize(light.position - frag_pos);
-    float diff = max(dot(norm, light_dir), 0.0);
-    vec3 diffuse = diff * light.diffuse * material.diffuse * texture(material.diffuse_tex, tex_coords).rgb;
+// The default framebuffer is not sRGB, so results are encoded here.
+vec3 linear_to_srgb(vec3 color)
+{
+    return mix(color * 12.92, 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055,
+               step(vec3(0.0031308), color));
+}
 
 
-    vec3 view_dir = normalize(view_pos - frag_pos);
-    vec3 reflect_dir = reflect(-light_dir, norm);
-    float spec = pow(max(dot(view_dir, reflect_dir), 0.0), material.shininess);
+void write_color(vec3 color, float alpha)
+{
+    color = linear_to_srgb(max(color, vec3(0.0)));
+    if (weighted_oit) {
+        // McGuire & Bavoil 2013, equation 10
+        float weight = clamp(pow(min(1.0, alpha * 10.0) + 0.01, 3.0) * 1e8
+                             * pow(1.0 - gl_FragCoord.z * 0.9, 3.0), 1e-2, 3e3);
+        frag_color = vec4(color * alpha, alpha) * weight;
+        frag_revealage = alpha;
+    } else {
+        frag_color = vec4(color, alpha);
+    }
+}
+
 
-    vec3 specular = spec * light.specular * material.specular * texture(material.specular_tex, tex_coords).rgb;
+void main() 
+{
+    vec4 diffuse_sample = material.has_diffuse_tex
+        ? texture(material.diffuse_tex, map_coords(material.diffuse_uv))
+        : vec4(1.0);
+    diffuse_sample *= instance_color;
+
+    float alpha = diffuse_sample.a * material.dissolve;
+    if (material.has_alpha_tex) {
+        alpha *= texture(material.alpha_tex, map_coords(material.alpha_uv)).r;
+    }
+    if (alpha < material.alpha_cutoff) {
+        discard;
+    }
+
+    vec3 ambient_map = material.has_ambient_tex
+        ? texture(material.ambient_tex, map_coords(material.ambient_uv)).rgb
+        : diffuse_sample.rgb;
+    vec3 ambient = light.ambient * srgb_to_linear(material.ambient) * ambient_map;
+
+    vec3 emissive = srgb_to_linear(material.emissive);
+    if (material.has_emissive_tex) {
+        emissive *= texture(material.emissive_tex, map_coords(material.emissive_uv)).rgb;
+    }
+
+    if (material.illumination_model == 0) {
+        write_color(srgb_to_linear(material.diffuse) * diffuse_sample.rgb + emissive, alpha);
+        return;
+    }
 
-    vec3 result = ambient + diffuse + specular;
-    frag_color = vec4(result, 1.0);
+
+    vec3 norm = normalize(normal);
+    vec3 light_dir = normalize(light.position - frag_pos);
+    float diff = max(dot(norm, light_dir), 0.0);
+    vec3 diffuse = diff * light.diffuse * srgb_to_linear(material.diffuse) * diffuse_sample.rgb;
+
+    vec3 specular = vec3(0.0);
+    if (material.illumination_model != 1) {
+        vec3 view_dir = normalize(view_pos - frag_pos);
+        vec3 reflect_dir = reflect(-light_dir, norm);
+        float spec = pow(max(dot(view_dir, reflect_dir), 0.0), material.shininess);
+
+        vec3 specular_map = material.has_specular_tex
+            ? texture(material.specular_tex, map_coords(material.specular_uv)).rgb
+            : vec3(1.0);
+        specular = spec * light.specular * material.specular * specular_map;
+    }
+
+    vec3 result = ambient + diffuse + specular + emissive;
+    write_color(result, alpha);
 }
@@ -3,19 +3,69 @@
 layout(location = 0) in vec3 a_pos;
 layout(location = 1) in vec3 a_normal;
 layout(location = 2) in vec2 a_tex_coords;
+// per instance, see instancing.rs
+layout(location = 3) in mat4 a_instance_model;
+layout(location = 7) in vec4 a_instance_color;
+// skinned meshes only, see MeshBuffers::new
+layout(location = 8) in uvec4 a_joints;
+layout(location = 9) in vec4 a_weights;
+
+// model::MAX_MORPH_TARGETS
+const int MAX_MORPH_TARGETS = 64;
 
 out vec3 normal;
 out vec3 frag_pos;
 out vec2 tex_coords;
+out vec4 instance_color;
 
 uniform mat4 model;
 uniform mat4 view;
 uniform mat4 projection;
+uniform bool instanced;
+uniform bool skinned;
+// four columns per joint, see JointBuffer
+uniform samplerBuffer joint_matrices;
+// position and normal delta per vertex, see Mesh::set_morph_targets
+uniform samplerBuffer morph_deltas;
+uniform int morph_target_count;
+uniform int morph_vertex_count;
+// first vertex of the mesh in a batched buffer, included in gl_VertexID
+uniform int morph_base_vertex;
+uniform float morph_weights[MAX_MORPH_TARGETS];
+
+mat4 joint_matrix(uint joint)
+{
+    int texel = int(joint) * 4;
+    return mat4(texelFetch(joint_matrices, texel),
+                texelFetch(joint_matrices, texel + 1),
+                texelFetch(joint_matrices, texel + 2),
+                texelFetch(joint_matrices, texel + 3));
+}
 
 void main() 
 {
-    normal = mat3(transpose(inverse(model))) * a_normal;
-    frag_pos = vec3(model * vec4(a_pos, 1.0));
+    mat4 model_matrix = instanced ? a_instance_model : model;
+    if (skinned) {
+        model_matrix *= a_weights.x * joint_matrix(a_joints.x)
+                      + a_weights.y * joint_matrix(a_joints.y)
+                      + a_weights.z * joint_matrix(a_joints.z)
+                      + a_weights.w * joint_matrix(a_joints.w);
+    }
+    instance_color = instanced ? a_instance_color : vec4(1.0);
+
+    vec3 pos = a_pos;
+    vec3 vertex_normal = a_normal;
+    for (int i = 0; i < morph_target_count; i++) {
+        if (morph_weights[i] == 0.0) {
+            continue;
+        }
+        int texel = (i * morph_vertex_count + gl_VertexID - morph_base_vertex) * 2;
+        pos += morph_weights[i] * texelFetch(morph_deltas, texel).xyz;
+        vertex_normal += morph_weights[i] * texelFetch(morph_deltas, texel + 1).xyz;
+    }
+
+    normal = mat3(transpose(inverse(model_matrix))) * vertex_normal;
+    frag_pos = vec3(model_matrix * vec4(pos, 1.0));
     tex_coords = a_tex_coords;
     gl_Position = projection * view  * vec4(frag_pos, 1.0);
 }
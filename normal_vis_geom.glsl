@@ -0,0 +1,27 @@
+#version 330 core
+
+layout(triangles) in;
+layout(line_strip, max_vertices = 6) out;
+
+in VS_OUT {
+    vec3 normal;
+} gs_in[];
+
+uniform mat4 projection;
+uniform float normal_length;
+
+void emit_normal(int index)
+{
+    gl_Position = projection * gl_in[index].gl_Position;
+    EmitVertex();
+    gl_Position = projection * (gl_in[index].gl_Position + vec4(gs_in[index].normal, 0.0) * normal_length);
+    EmitVertex();
+    EndPrimitive();
+}
+
+void main() 
+{
+    emit_normal(0);
+    emit_normal(1);
+    emit_normal(2);
+}
@@ -0,0 +1,18 @@
+#version 330 core
+
+layout(location = 0) in vec3 a_pos;
+layout(location = 1) in vec3 a_normal;
+
+out VS_OUT {
+    vec3 normal;
+} vs_out;
+
+uniform mat4 model;
+uniform mat4 view;
+
+void main() 
+{
+    mat3 normal_matrix = mat3(transpose(inverse(view * model)));
+    vs_out.normal = normalize(normal_matrix * a_normal);
+    gl_Position = view * model * vec4(a_pos, 1.0);
+}
@@ -0,0 +1,22 @@
+#version 330 core
+
+out vec4 frag_color;
+
+uniform sampler2D accum_tex;
+uniform sampler2D revealage_tex;
+
+void main()
+{
+    ivec2 coords = ivec2(gl_FragCoord.xy);
+
+    float revealage = texelFetch(revealage_tex, coords, 0).r;
+    if (revealage >= 1.0) {
+        // nothing transparent covers this pixel
+        discard;
+    }
+
+    vec4 accum = texelFetch(accum_tex, coords, 0);
+    vec3 average_color = accum.rgb / clamp(accum.a, 1e-4, 5e4);
+
+    frag_color = vec4(average_color, 1.0 - revealage);
+}
@@ -0,0 +1,138 @@
+//! Offline converter from PNG/JPG/... to BCn compressed DDS or KTX2.
+//!
+//! usage: texconv <input> <output.dds|output.ktx2> [--format bc1|bc1a|bc3|bc4|bc5]
+//!                [--srgb] [--no-mips] [--no-flip]
+//!
+//! Without `--format` the format is picked from the image: BC3 when it has an
+//! alpha channel, BC4 for grayscale and BC1 otherwise. Rows are flipped
+//! bottom-up like `Texture::from_path` does unless `--no-flip` is given.
+
+use image::imageops::FilterType;
+use image::{io::Reader as ImageReader, ColorType};
+use std::error::Error;
+use std::path::Path;
+use std::process;
+
+#[path = "../graphics/compressed.rs"]
+mod compressed;
+
+use compressed::{BlockFormat, CompressedImage};
+
+struct Args {
+    input: String,
+    output: String,
+    format: Option<BlockFormat>,
+    srgb: bool,
+    mips: bool,
+    flip: bool,
+}
+
+fn parse_args() -> Result<Args, Box<dyn Error>> {
+    let mut positional = Vec::new();
+    let mut format = None;
+    let mut srgb = false;
+    let mut mips = true;
+    let mut flip = true;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                let name = args.next().ok_or("--format needs a value")?;
+                format = Some(
+                    BlockFormat::from_name(&name)
+                        .ok_or_else(|| format!("Unknown format {}", name))?,
+                );
+            }
+            "--srgb" => srgb = true,
+            "--no-mips" => mips = false,
+            "--no-flip" => flip = false,
+            _ => positional.push(arg),
+        }
+    }
+
+    if positional.len() != 2 {
+        return Err("usage: texconv <input> <output.dds|output.ktx2> [--format bc1|bc1a|bc3|bc4|bc5] [--srgb] [--no-mips] [--no-flip]".into());
+    }
+    let output = positional.pop().unwrap_or_default();
+    let input = positional.pop().unwrap_or_default();
+
+    Ok(Args {
+        input,
+        output,
+        format,
+        srgb,
+        mips,
+        flip,
+    })
+}
+
+fn run(args: &Args) -> Result<(), Box<dyn Error>> {
+    let mut img = ImageReader::open(&args.input)?.decode()?;
+    if args.flip {
+        img = img.flipv();
+    }
+
+    let format = args.format.unwrap_or(match img.color() {
+        ColorType::L8 | ColorType::L16 => BlockFormat::Bc4,
+        color if color.has_alpha() => BlockFormat::Bc3,
+        _ => BlockFormat::Bc1,
+    });
+
+    let mut rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+
+    let mut levels = Vec::new();
+    loop {
+        let (w, h) = rgba.dimensions();
+        levels.push(compressed::encode(format, rgba.as_raw(), w, h)?);
+        if !args.mips || (w == 1 && h == 1) {
+            break;
+        }
+        rgba = image::imageops::resize(
+            &rgba,
+            (w / 2).max(1),
+            (h / 2).max(1),
+            FilterType::Triangle,
+        );
+    }
+
+    let image = CompressedImage {
+        format,
+        srgb: args.srgb && format.has_srgb(),
+        width,
+        height,
+        levels,
+    };
+
+    let extension = Path::new(&args.output)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+    let data = match extension.as_deref() {
+        Some("dds") => compressed::write_dds(&image)?,
+        Some("ktx2") => compressed::write_ktx2(&image)?,
+        _ => return Err("Output must end in .dds or .ktx2".into()),
+    };
+    std::fs::write(&args.output, data)?;
+
+    println!(
+        "{} -> {} ({:?}, {}x{}, {} levels, {} bytes)",
+        args.input,
+        args.output,
+        format,
+        width,
+        height,
+        image.levels.len(),
+        image.size_bytes()
+    );
+    Ok(())
+}
+
+fn main() {
+    let result = parse_args().and_then(|args| run(&args));
+    if let Err(e) = result {
+        eprintln!("texconv: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,536 @@
+use glam::*;
+
+/// Joints a skin may have. The 16-bit joint attributes index the joints of
+/// the whole model, so this also bounds all skins and rigid animated nodes
+/// together; the GPU may allow fewer, see `Model::from_data`.
+pub const MAX_JOINTS: usize = u16::MAX as usize + 1;
+
+/// A decomposed local transform, which is what animation channels target.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform {
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+        }
+    }
+}
+
+impl Transform {
+    pub fn to_mat4(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+/// A node of the scene hierarchy. Joints are nodes, but so are any
+/// intermediate nodes between them that still contribute a transform.
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub rest: Transform,
+}
+
+/// A node that deforms vertices, with the matrix that takes model-space
+/// vertices into the joint's space at bind time.
+#[derive(Clone, Debug)]
+pub struct Joint {
+    pub node: usize,
+    pub inverse_bind: Mat4,
+}
+
+/// The node hierarchy of a model and the joints its skinned meshes refer to.
+///
+/// Nodes are stored parents first, so a single forward pass computes every
+/// global transform.
+#[derive(Clone, Debug, Default)]
+pub struct Skeleton {
+    pub nodes: Vec<Node>,
+    pub joints: Vec<Joint>,
+}
+
+#[allow(dead_code)]
+impl Skeleton {
+    /// Local transforms of the bind pose.
+    pub fn rest_pose(&self) -> Vec<Transform> {
+        self.nodes.iter().map(|node| node.rest).collect()
+    }
+
+    /// Model-space transform of every node for the local transforms `pose`.
+    pub fn global_transforms(&self, pose: &[Transform]) -> Vec<Mat4> {
+        let mut globals: Vec<Mat4> = Vec::with_capacity(self.nodes.len());
+        for (node, local) in self.nodes.iter().zip(pose) {
+            let local = local.to_mat4();
+            let global = match node.parent {
+                Some(parent) => globals[parent] * local,
+                None => local,
+            };
+            globals.push(global);
+        }
+        globals
+    }
+
+    /// The matrices uploaded to `joint_matrices` in the vertex shader.
+    pub fn joint_matrices(&self, pose: &[Transform]) -> Vec<Mat4> {
+        let globals = self.global_transforms(pose);
+        self.joints
+            .iter()
+            .map(|joint| globals[joint.node] * joint.inverse_bind)
+            .collect()
+    }
+
+    pub fn find_node(&self, name: &str) -> Option<usize> {
+        self.nodes.iter().position(|node| node.name == name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    Step,
+    /// LERP for translation and scale, SLERP for rotation.
+    Linear,
+    /// Hermite spline with an in-tangent, value and out-tangent per key.
+    CubicSpline,
+}
+
+#[derive(Clone, Debug)]
+pub enum Keyframes {
+    Translation(Vec<Vec3>),
+    Rotation(Vec<Quat>),
+    Scale(Vec<Vec3>),
+    /// Morph target weights of the node's mesh, all targets of a key stored
+    /// next to each other.
+    Weights(Vec<f32>),
+}
+
+/// Keyframes for one property of one node.
+#[derive(Clone, Debug)]
+pub struct Channel {
+    pub node: usize,
+    pub interpolation: Interpolation,
+    /// Key times in seconds, ascending.
+    pub times: Vec<f32>,
+    /// One value per key, or three (in-tangent, value, out-tangent) for
+    /// `CubicSpline`.
+    pub keyframes: Keyframes,
+}
+
+/// Hermite basis, with tangents already scaled by the key interval.
+fn hermite<T>(v0: T, out_tangent: T, v1: T, in_tangent: T, t: f32) -> T
+where
+    T: std::ops::Mul<f32, Output = T> + std::ops::Add<Output = T>,
+{
+    let t2 = t * t;
+    let t3 = t2 * t;
+    v0 * (2.0 * t3 - 3.0 * t2 + 1.0)
+        + out_tangent * (t3 - 2.0 * t2 + t)
+        + v1 * (-2.0 * t3 + 3.0 * t2)
+        + in_tangent * (t3 - t2)
+}
+
+fn slerp_shortest(a: Quat, b: Quat, t: f32) -> Quat {
+    let b = if a.dot(b) < 0.0 { -b } else { b };
+    a.slerp(b, t).normalize()
+}
+
+impl Channel {
+    /// Values stored per key and morph target, see `keyframes`.
+    fn values_per_key(&self) -> usize {
+        match self.interpolation {
+            Interpolation::CubicSpline => 3,
+            Interpolation::Step | Interpolation::Linear => 1,
+        }
+    }
+
+    /// Whether `keyframes` holds the values of exactly `times.len()` keys,
+    /// which sampling relies on.
+    pub fn keys_match(&self) -> bool {
+        let expected = self.times.len() * self.values_per_key();
+        match &self.keyframes {
+            Keyframes::Translation(values) | Keyframes::Scale(values) => values.len() == expected,
+            Keyframes::Rotation(values) => values.len() == expected,
+            Keyframes::Weights(values) if expected == 0 => values.is_empty(),
+            Keyframes::Weights(values) => values.len() % expected == 0,
+        }
+    }
+
+    /// The key before `time`, the key after it and how far between them
+    /// `time` is. Times outside the keys clamp to the first or last key.
+    fn locate(&self, time: f32) -> (usize, usize, f32, f32) {
+        let last = self.times.len() - 1;
+        if time <= self.times[0] {
+            return (0, 0, 0.0, 0.0);
+        }
+        if time >= self.times[last] {
+            return (last, last, 0.0, 0.0);
+        }
+
+        let next = self.times.partition_point(|&key| key <= time);
+        let previous = next - 1;
+        let interval = self.times[next] - self.times[previous];
+        let t = if interval > 0.0 {
+            (time - self.times[previous]) / interval
+        } else {
+            0.0
+        };
+        (previous, next, t, interval)
+    }
+
+    fn sample_vec3(&self, values: &[Vec3], time: f32) -> Vec3 {
+        let (previous, next, t, interval) = self.locate(time);
+        match self.interpolation {
+            Interpolation::Step => values[previous],
+            Interpolation::Linear => values[previous].lerp(values[next], t),
+            Interpolation::CubicSpline => {
+                if previous == next {
+                    return values[previous * 3 + 1];
+                }
+                hermite(
+                    values[previous * 3 + 1],
+                    values[previous * 3 + 2] * interval,
+                    values[next * 3 + 1],
+                    values[next * 3] * interval,
+                    t,
+                )
+            }
+        }
+    }
+
+    fn sample_quat(&self, values: &[Quat], time: f32) -> Quat {
+        let (previous, next, t, interval) = self.locate(time);
+        match self.interpolation {
+            Interpolation::Step => values[previous],
+            Interpolation::Linear => slerp_shortest(values[previous], values[next], t),
+            Interpolation::CubicSpline => {
+                if previous == next {
+                    return values[previous * 3 + 1].normalize();
+                }
+                // Interpolated as a 4D vector and renormalized, as glTF
+                // specifies.
+                let v = hermite(
+                    Vec4::from(values[previous * 3 + 1]),
+                    Vec4::from(values[previous * 3 + 2]) * interval,
+                    Vec4::from(values[next * 3 + 1]),
+                    Vec4::from(values[next * 3]) * interval,
+                    t,
+                );
+                Quat::from_vec4(v).normalize()
+            }
+        }
+    }
+
+    /// Writes the channel's value at `time` into `pose`.
+    pub fn apply(&self, time: f32, pose: &mut [Transform]) {
+        if self.times.is_empty() {
+            return;
+        }
+        let transform = &mut pose[self.node];
+        match &self.keyframes {
+            Keyframes::Translation(values) => {
+                transform.translation = self.sample_vec3(values, time)
+            }
+            Keyframes::Rotation(values) => transform.rotation = self.sample_quat(values, time),
+            Keyframes::Scale(values) => transform.scale = self.sample_vec3(values, time),
+            Keyframes::Weights(_) => {}
+        }
+    }
+
+    /// Morph target weights at `time`, for `Weights` channels.
+    pub fn sample_weights(&self, time: f32) -> Option<Vec<f32>> {
+        let values = match &self.keyframes {
+            Keyframes::Weights(values) if !self.times.is_empty() => values,
+            _ => return None,
+        };
+
+        let values_per_key = self.values_per_key();
+        let target_count = values.len() / (self.times.len() * values_per_key);
+        // Weights of one key, or one of its tangents for cubic splines.
+        let key = |index: usize, element: usize| {
+            let start = (index * values_per_key + element) * target_count;
+            &values[start..start + target_count]
+        };
+
+        let (previous, next, t, interval) = self.locate(time);
+        let weights = match self.interpolation {
+            Interpolation::Step => key(previous, 0).to_vec(),
+            Interpolation::Linear => key(previous, 0)
+                .iter()
+                .zip(key(next, 0))
+                .map(|(a, b)| a + (b - a) * t)
+                .collect(),
+            Interpolation::CubicSpline if previous == next => key(previous, 1).to_vec(),
+            Interpolation::CubicSpline => (0..target_count)
+                .map(|i| {
+                    hermite(
+                        key(previous, 1)[i],
+                        key(previous, 2)[i] * interval,
+                        key(next, 1)[i],
+                        key(next, 0)[i] * interval,
+                        t,
+                    )
+                })
+                .collect(),
+        };
+        Some(weights)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AnimationClip {
+    pub name: String,
+    /// Time of the last key of any channel, in seconds.
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+
+impl AnimationClip {
+    /// Poses `pose` at `time`. Nodes without channels keep their transform,
+    /// so start from `Skeleton::rest_pose`.
+    pub fn sample(&self, time: f32, pose: &mut [Transform]) {
+        for channel in &self.channels {
+            channel.apply(time, pose);
+        }
+    }
+
+    /// Morph target weights of `node`'s mesh at `time`, if the clip
+    /// animates them.
+    pub fn sample_weights(&self, time: f32, node: usize) -> Option<Vec<f32>> {
+        self.channels
+            .iter()
+            .filter(|channel| channel.node == node)
+            .find_map(|channel| channel.sample_weights(time))
+    }
+}
+
+/// Playback state of one clip.
+#[derive(Clone, Copy, Debug)]
+pub struct Playback {
+    pub clip: usize,
+    pub time: f32,
+    pub speed: f32,
+    pub playing: bool,
+    pub looping: bool,
+}
+
+impl Default for Playback {
+    fn default() -> Playback {
+        Playback {
+            clip: 0,
+            time: 0.0,
+            speed: 1.0,
+            playing: true,
+            looping: true,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl Playback {
+    pub fn new(clip: usize) -> Playback {
+        Playback {
+            clip,
+            ..Default::default()
+        }
+    }
+
+    /// Advances the time by `delta_time`, wrapping or stopping at the end of
+    /// a clip lasting `duration`.
+    pub fn advance(&mut self, delta_time: f32, duration: f32) {
+        if !self.playing {
+            return;
+        }
+
+        self.time += delta_time * self.speed;
+        if duration <= 0.0 {
+            self.time = 0.0;
+        } else if self.looping {
+            self.time = self.time.rem_euclid(duration);
+        } else if self.time >= duration || self.time <= 0.0 {
+            self.time = self.time.clamp(0.0, duration);
+            self.playing = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    fn channel(interpolation: Interpolation, times: &[f32], keyframes: Keyframes) -> Channel {
+        Channel {
+            node: 0,
+            interpolation,
+            times: times.to_vec(),
+            keyframes,
+        }
+    }
+
+    fn translation_at(channel: &Channel, time: f32) -> Vec3 {
+        let mut pose = [Transform::default()];
+        channel.apply(time, &mut pose);
+        pose[0].translation
+    }
+
+    fn rotation_at(channel: &Channel, time: f32) -> Quat {
+        let mut pose = [Transform::default()];
+        channel.apply(time, &mut pose);
+        pose[0].rotation
+    }
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!(a.abs_diff_eq(b, 1e-5), "{} != {}", a, b);
+    }
+
+    #[test]
+    fn linear_keys_at_and_between() {
+        let channel = channel(
+            Interpolation::Linear,
+            &[0.0, 1.0, 3.0],
+            Keyframes::Translation(vec![Vec3::ZERO, Vec3::X, Vec3::X * 3.0]),
+        );
+        assert_near(translation_at(&channel, 0.0), Vec3::ZERO);
+        assert_near(translation_at(&channel, 0.5), Vec3::X * 0.5);
+        assert_near(translation_at(&channel, 1.0), Vec3::X);
+        assert_near(translation_at(&channel, 2.0), Vec3::X * 2.0);
+        assert_near(translation_at(&channel, 3.0), Vec3::X * 3.0);
+    }
+
+    #[test]
+    fn times_outside_the_keys_clamp() {
+        let channel = channel(
+            Interpolation::Linear,
+            &[1.0, 2.0],
+            Keyframes::Translation(vec![Vec3::X, Vec3::Y]),
+        );
+        assert_near(translation_at(&channel, -1.0), Vec3::X);
+        assert_near(translation_at(&channel, 5.0), Vec3::Y);
+    }
+
+    #[test]
+    fn step_holds_the_previous_key() {
+        let channel = channel(
+            Interpolation::Step,
+            &[0.0, 1.0],
+            Keyframes::Translation(vec![Vec3::X, Vec3::Y]),
+        );
+        assert_near(translation_at(&channel, 0.99), Vec3::X);
+        assert_near(translation_at(&channel, 1.0), Vec3::Y);
+    }
+
+    #[test]
+    fn linear_rotation_takes_the_shortest_path() {
+        // The same rotation with the opposite sign, which a naive slerp
+        // would reach the long way round.
+        let end = -Quat::from_rotation_y(FRAC_PI_2);
+        let channel = channel(
+            Interpolation::Linear,
+            &[0.0, 1.0],
+            Keyframes::Rotation(vec![Quat::IDENTITY, end]),
+        );
+        let halfway = rotation_at(&channel, 0.5);
+        assert!(halfway.angle_between(Quat::from_rotation_y(FRAC_PI_2 / 2.0)) < 1e-4);
+    }
+
+    #[test]
+    fn cubic_spline_uses_out_and_in_tangents() {
+        // (in-tangent, value, out-tangent) per key.
+        let spline = |out_tangent: Vec3, in_tangent: Vec3| {
+            channel(
+                Interpolation::CubicSpline,
+                &[0.0, 2.0],
+                Keyframes::Translation(vec![
+                    Vec3::Z,
+                    Vec3::ZERO,
+                    out_tangent,
+                    in_tangent,
+                    Vec3::ZERO,
+                    Vec3::Z,
+                ]),
+            )
+        };
+
+        // Tangents are per second and scaled by the two second interval.
+        let leaving = spline(Vec3::X, Vec3::ZERO);
+        assert_near(translation_at(&leaving, 1.0), Vec3::X * 0.25);
+        let arriving = spline(Vec3::ZERO, Vec3::X);
+        assert_near(translation_at(&arriving, 1.0), Vec3::X * -0.25);
+
+        // Keys and clamped times return the value, not a tangent.
+        assert_near(translation_at(&leaving, 0.0), Vec3::ZERO);
+        assert_near(translation_at(&leaving, 3.0), Vec3::ZERO);
+    }
+
+    #[test]
+    fn weights_interpolate_per_target() {
+        let channel = channel(
+            Interpolation::Linear,
+            &[0.0, 1.0],
+            Keyframes::Weights(vec![0.0, 1.0, 1.0, 0.0]),
+        );
+        assert_eq!(channel.sample_weights(0.25), Some(vec![0.25, 0.75]));
+        assert_eq!(channel.sample_weights(2.0), Some(vec![1.0, 0.0]));
+    }
+
+    #[test]
+    fn keys_match_counts_values_per_key() {
+        let translations = |interpolation, count| {
+            channel(
+                interpolation,
+                &[0.0, 1.0],
+                Keyframes::Translation(vec![Vec3::ZERO; count]),
+            )
+        };
+        assert!(translations(Interpolation::Linear, 2).keys_match());
+        assert!(!translations(Interpolation::Linear, 3).keys_match());
+        assert!(translations(Interpolation::CubicSpline, 6).keys_match());
+        assert!(!translations(Interpolation::CubicSpline, 2).keys_match());
+
+        let weights = |count| {
+            channel(
+                Interpolation::Step,
+                &[0.0, 1.0],
+                Keyframes::Weights(vec![0.0; count]),
+            )
+        };
+        assert!(weights(6).keys_match());
+        assert!(!weights(5).keys_match());
+        assert!(channel(Interpolation::Step, &[], Keyframes::Weights(Vec::new())).keys_match());
+    }
+
+    #[test]
+    fn looping_playback_wraps() {
+        let mut playback = Playback {
+            time: 0.9,
+            ..Playback::default()
+        };
+        playback.advance(0.3, 1.0);
+        assert!((playback.time - 0.2).abs() < 1e-5);
+        assert!(playback.playing);
+
+        playback.speed = -1.0;
+        playback.advance(0.5, 1.0);
+        assert!((playback.time - 0.7).abs() < 1e-5);
+    }
+
+    #[test]
+    fn playback_without_looping_stops_at_the_end() {
+        let mut playback = Playback {
+            looping: false,
+            ..Playback::default()
+        };
+        playback.advance(1.5, 1.0);
+        assert_eq!(playback.time, 1.0);
+        assert!(!playback.playing);
+
+        playback.advance(1.0, 1.0);
+        assert_eq!(playback.time, 1.0);
+    }
+}
@@ -0,0 +1,150 @@
+use gl::types::*;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use super::model::Model;
+use super::shader::Shader;
+use super::shader_cache::ProgramCache;
+use super::texture::{Texture, TextureOptions};
+
+/// Loads textures, shaders and models once per canonical path and hands out
+/// shared references to them.
+///
+/// The manager keeps every asset alive until `collect_garbage` is called,
+/// which drops whatever is no longer referenced outside of it.
+#[derive(Default)]
+pub struct AssetManager {
+    textures: HashMap<(PathBuf, TextureOptions), Rc<Texture>>,
+    shaders: HashMap<Vec<(GLenum, PathBuf)>, Rc<Shader>>,
+    models: HashMap<PathBuf, Rc<Model>>,
+    program_cache: Option<ProgramCache>,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MemoryUsage {
+    pub texture_count: usize,
+    pub texture_bytes: usize,
+    pub shader_count: usize,
+    pub model_count: usize,
+    pub mesh_bytes: usize,
+}
+
+fn canonical_path<P: AsRef<Path>>(path: P) -> Result<PathBuf, Box<dyn Error>> {
+    let path = path.as_ref();
+    fs::canonicalize(path).map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+#[allow(dead_code)]
+impl AssetManager {
+    pub fn new() -> AssetManager {
+        AssetManager::default()
+    }
+
+    /// Shaders loaded through the manager go through `cache`.
+    pub fn with_program_cache(cache: ProgramCache) -> AssetManager {
+        AssetManager {
+            program_cache: Some(cache),
+            ..Default::default()
+        }
+    }
+
+    pub fn texture<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        options: &TextureOptions,
+    ) -> Result<Rc<Texture>, Box<dyn Error>> {
+        let key = (canonical_path(path)?, *options);
+        if let Some(texture) = self.textures.get(&key) {
+            return Ok(texture.clone());
+        }
+
+        let path = key.0.to_str().ok_or("Texture path is not valid UTF-8")?;
+        let texture = Rc::new(Texture::from_path_with_options(path, options)?);
+        self.textures.insert(key, texture.clone());
+        Ok(texture)
+    }
+
+    /// Returns the texture if it has already been loaded with `options`.
+    pub fn cached_texture<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &TextureOptions,
+    ) -> Option<Rc<Texture>> {
+        let key = (canonical_path(path).ok()?, *options);
+        self.textures.get(&key).cloned()
+    }
+
+    /// Registers a texture that was loaded elsewhere, e.g. uploaded after a
+    /// background decode, so later requests for `path` share it.
+    pub fn insert_texture<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        options: &TextureOptions,
+        texture: Rc<Texture>,
+    ) -> Result<(), Box<dyn Error>> {
+        let key = (canonical_path(path)?, *options);
+        self.textures.insert(key, texture);
+        Ok(())
+    }
+
+    /// Builds a program from `(stage, path)` pairs, e.g.
+    /// `[(gl::VERTEX_SHADER, "lighting_vert.glsl"), (gl::FRAGMENT_SHADER, "lighting_frag.glsl")]`.
+    pub fn shader<P: AsRef<Path>>(
+        &mut self,
+        stages: &[(GLenum, P)],
+    ) -> Result<Rc<Shader>, Box<dyn Error>> {
+        let mut key = Vec::with_capacity(stages.len());
+        for (shader_type, path) in stages {
+            key.push((*shader_type, canonical_path(path)?));
+        }
+        if let Some(shader) = self.shaders.get(&key) {
+            return Ok(shader.clone());
+        }
+
+        let mut builder = Shader::builder();
+        if let Some(cache) = &self.program_cache {
+            builder = builder.cache(cache);
+        }
+        for (shader_type, path) in &key {
+            builder = builder.stage(*shader_type, &fs::read_to_string(path)?);
+        }
+
+        let shader = Rc::new(builder.build()?);
+        self.shaders.insert(key, shader.clone());
+        Ok(shader)
+    }
+
+    pub fn model<P: AsRef<Path>>(&mut self, path: P) -> Result<Rc<Model>, Box<dyn Error>> {
+        let key = canonical_path(path)?;
+        if let Some(model) = self.models.get(&key) {
+            return Ok(model.clone());
+        }
+
+        let path = key.to_str().ok_or("Model path is not valid UTF-8")?;
+        let model = Rc::new(Model::load(path, self)?);
+        self.models.insert(key, model.clone());
+        Ok(model)
+    }
+
+    /// Drops every asset that is only referenced by the manager itself.
+    pub fn collect_garbage(&mut self) {
+        // Models first, they may hold the last outside reference to a texture.
+        self.models.retain(|_, model| Rc::strong_count(model) > 1);
+        self.shaders.retain(|_, shader| Rc::strong_count(shader) > 1);
+        self.textures.retain(|_, texture| Rc::strong_count(texture) > 1);
+    }
+
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            texture_count: self.textures.len(),
+            texture_bytes: self.textures.values().map(|t| t.size_bytes()).sum(),
+            shader_count: self.shaders.len(),
+            model_count: self.models.len(),
+            mesh_bytes: self.models.values().map(|m| m.size_bytes()).sum(),
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use glam::*;
+
+/// Axis-aligned bounding box. An empty box has `min > max` so that any
+/// union with it yields the other operand.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Default for Aabb {
+    fn default() -> Aabb {
+        Aabb::EMPTY
+    }
+}
+
+#[allow(dead_code)]
+impl Aabb {
+    pub const EMPTY: Aabb = Aabb {
+        min: const_vec3!([f32::MAX, f32::MAX, f32::MAX]),
+        max: const_vec3!([f32::MIN, f32::MIN, f32::MIN]),
+    };
+
+    pub fn new(min: Vec3, max: Vec3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    pub fn from_points<I: IntoIterator<Item = Vec3>>(points: I) -> Aabb {
+        points.into_iter().fold(Aabb::EMPTY, |aabb, point| Aabb {
+            min: aabb.min.min(point),
+            max: aabb.max.max(point),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.cmpgt(self.max).any()
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Half the size along each axis.
+    pub fn extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Vec3::new(min.x, min.y, min.z),
+            Vec3::new(max.x, min.y, min.z),
+            Vec3::new(min.x, max.y, min.z),
+            Vec3::new(max.x, max.y, min.z),
+            Vec3::new(min.x, min.y, max.z),
+            Vec3::new(max.x, min.y, max.z),
+            Vec3::new(min.x, max.y, max.z),
+            Vec3::new(max.x, max.y, max.z),
+        ]
+    }
+
+    /// The box enclosing this one after an affine `transform` (Arvo 1990).
+    pub fn transform(&self, transform: &Mat4) -> Aabb {
+        if self.is_empty() {
+            return *self;
+        }
+
+        let center = transform.transform_point3(self.center());
+        let extents = self.extents();
+        let abs_extents = transform.x_axis.truncate().abs() * extents.x
+            + transform.y_axis.truncate().abs() * extents.y
+            + transform.z_axis.truncate().abs() * extents.z;
+
+        Aabb {
+            min: center - abs_extents,
+            max: center + abs_extents,
+        }
+    }
+
+    /// The box enclosing this one with every point moved by `weight` times
+    /// an offset within `offsets`, such as the deltas of a morph target.
+    pub fn displace(&self, offsets: &Aabb, weight: f32) -> Aabb {
+        if self.is_empty() || offsets.is_empty() {
+            return *self;
+        }
+
+        let (a, b) = (offsets.min * weight, offsets.max * weight);
+        Aabb {
+            min: self.min + a.min(b),
+            max: self.max + a.max(b),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+#[allow(dead_code)]
+impl BoundingSphere {
+    pub fn new(center: Vec3, radius: f32) -> BoundingSphere {
+        BoundingSphere { center, radius }
+    }
+
+    /// Centered on the points' bounding box, which is not minimal but is
+    /// never worse than the box's own circumscribed sphere.
+    pub fn from_points<I>(points: I) -> BoundingSphere
+    where
+        I: IntoIterator<Item = Vec3>,
+        I::IntoIter: Clone,
+    {
+        let points = points.into_iter();
+        let aabb = Aabb::from_points(points.clone());
+        if aabb.is_empty() {
+            return BoundingSphere::default();
+        }
+
+        let center = aabb.center();
+        let radius_squared = points.fold(0.0f32, |radius_squared, point| {
+            radius_squared.max(point.distance_squared(center))
+        });
+
+        BoundingSphere {
+            center,
+            radius: radius_squared.sqrt(),
+        }
+    }
+
+    /// The sphere enclosing this one after an affine `transform`, scaled by
+    /// the largest axis scale.
+    pub fn transform(&self, transform: &Mat4) -> BoundingSphere {
+        let scale = transform
+            .x_axis
+            .truncate()
+            .length()
+            .max(transform.y_axis.truncate().length())
+            .max(transform.z_axis.truncate().length());
+
+        BoundingSphere {
+            center: transform.transform_point3(self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
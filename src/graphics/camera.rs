@@ -1,5 +1,7 @@
 use glam::*;
 
+use super::bounds::BoundingSphere;
+
 pub enum Direction {
     FORWARD,
     BACKWARD,
@@ -88,6 +90,28 @@ impl Camera {
         }
     }
 
+    /// Moves the camera back along its view direction until `sphere` fits
+    /// the narrower of the two fields of view. `fov_y` is in radians.
+    pub fn frame(&mut self, sphere: &BoundingSphere, fov_y: f32, aspect: f32) {
+        let fov_x = 2.0 * f32::atan(f32::tan(fov_y * 0.5) * aspect);
+        let half_fov = fov_y.min(fov_x) * 0.5;
+        let distance = sphere.radius / f32::sin(half_fov);
+
+        self.update();
+        self.position = sphere.center - self.direction * distance;
+        self.update();
+    }
+
+    /// Rotation about the Y axis in degrees, 0 looking down +X.
+    pub fn yaw(&self) -> f32 {
+        self.yaw
+    }
+
+    /// Elevation in degrees, within (-90, 90).
+    pub fn pitch(&self) -> f32 {
+        self.pitch
+    }
+
     pub fn lock(&mut self, cond: bool) {
         self.locked = cond;
     }
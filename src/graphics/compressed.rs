@@ -0,0 +1,828 @@
+//! Block-compressed (BCn) images and the DDS / KTX2 containers they travel in.
+//!
+//! Nothing in here touches GL so the offline converter in `src/bin/texconv.rs`
+//! can share it. By convention the rows are stored bottom-up, i.e. already
+//! flipped the way `Texture::from_path` flips decoded images, because
+//! compressed blocks cannot cheaply be flipped at load time.
+
+// Each binary only uses half of this module: the viewer reads, texconv writes.
+#![allow(dead_code)]
+
+use std::error::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BlockFormat {
+    /// BC1 without alpha.
+    Bc1,
+    /// BC1 with 1-bit punch-through alpha.
+    Bc1a,
+    Bc2,
+    Bc3,
+    Bc4,
+    Bc4Signed,
+    Bc5,
+    Bc5Signed,
+    Bc6h,
+    Bc6hSigned,
+    Bc7,
+}
+
+impl BlockFormat {
+    pub fn block_bytes(self) -> usize {
+        match self {
+            BlockFormat::Bc1 | BlockFormat::Bc1a | BlockFormat::Bc4 | BlockFormat::Bc4Signed => 8,
+            _ => 16,
+        }
+    }
+
+    /// Whether an sRGB variant exists for this format.
+    pub fn has_srgb(self) -> bool {
+        matches!(
+            self,
+            BlockFormat::Bc1
+                | BlockFormat::Bc1a
+                | BlockFormat::Bc2
+                | BlockFormat::Bc3
+                | BlockFormat::Bc7
+        )
+    }
+
+    /// Whether blocks can encode alpha. BC7 may or may not use it per
+    /// block, so it is treated as having alpha.
+    pub fn has_alpha(self) -> bool {
+        matches!(
+            self,
+            BlockFormat::Bc1a | BlockFormat::Bc2 | BlockFormat::Bc3 | BlockFormat::Bc7
+        )
+    }
+
+    /// Bytes of a mip level, saturating for dimensions no file can hold.
+    pub fn level_size(self, width: u32, height: u32) -> usize {
+        let blocks_x = width.div_ceil(4).max(1) as usize;
+        let blocks_y = height.div_ceil(4).max(1) as usize;
+        blocks_x
+            .saturating_mul(blocks_y)
+            .saturating_mul(self.block_bytes())
+    }
+
+    pub fn from_name(name: &str) -> Option<BlockFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "bc1" => Some(BlockFormat::Bc1),
+            "bc1a" => Some(BlockFormat::Bc1a),
+            "bc2" => Some(BlockFormat::Bc2),
+            "bc3" => Some(BlockFormat::Bc3),
+            "bc4" => Some(BlockFormat::Bc4),
+            "bc5" => Some(BlockFormat::Bc5),
+            "bc6h" => Some(BlockFormat::Bc6h),
+            "bc7" => Some(BlockFormat::Bc7),
+            _ => None,
+        }
+    }
+}
+
+/// A compressed 2D texture with its full mip chain, level 0 first.
+pub struct CompressedImage {
+    pub format: BlockFormat,
+    pub srgb: bool,
+    pub width: u32,
+    pub height: u32,
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl CompressedImage {
+    pub fn level_dimensions(&self, level: usize) -> (u32, u32) {
+        (
+            mip_dimension(self.width, level as u32),
+            mip_dimension(self.height, level as u32),
+        )
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.levels.iter().map(|level| level.len()).sum()
+    }
+
+    fn check_levels(&self) -> Result<(), Box<dyn Error>> {
+        if self.levels.is_empty() {
+            return Err("Compressed image has no mip levels".into());
+        }
+        for (i, level) in self.levels.iter().enumerate() {
+            let (width, height) = self.level_dimensions(i);
+            if level.len() < self.format.level_size(width, height) {
+                return Err(format!("Mip level {} is truncated", i).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Size of `dimension` at mip `level`, at least one.
+fn mip_dimension(dimension: u32, level: u32) -> u32 {
+    dimension.checked_shr(level).unwrap_or(0).max(1)
+}
+
+/// Length of a full mip chain, down to 1x1.
+fn max_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+fn check_dimensions(width: u32, height: u32, levels: u32) -> Result<(), Box<dyn Error>> {
+    if width == 0 || height == 0 {
+        return Err(format!("Invalid image size {}x{}", width, height).into());
+    }
+    if levels > max_level_count(width, height) {
+        return Err(format!(
+            "{} mip levels are too many for a {}x{} image",
+            levels, width, height
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, Box<dyn Error>> {
+    data.get(offset..offset.saturating_add(4))
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| "Unexpected end of file".into())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, Box<dyn Error>> {
+    let low = read_u32(data, offset)? as u64;
+    let high = read_u32(data, offset + 4)? as u64;
+    Ok(low | (high << 32))
+}
+
+fn fourcc(code: &[u8; 4]) -> u32 {
+    u32::from_le_bytes(*code)
+}
+
+// DDS
+
+const DDS_MAGIC: u32 = 0x2053_4444;
+const DDS_HEADER_SIZE: usize = 124;
+const DDSD_CAPS: u32 = 0x1;
+const DDSD_HEIGHT: u32 = 0x2;
+const DDSD_WIDTH: u32 = 0x4;
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDSD_LINEARSIZE: u32 = 0x80000;
+const DDPF_FOURCC: u32 = 0x4;
+const DDSCAPS_COMPLEX: u32 = 0x8;
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+const DDSCAPS_MIPMAP: u32 = 0x40_0000;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_VOLUME: u32 = 0x20_0000;
+const D3D10_RESOURCE_DIMENSION_TEXTURE2D: u32 = 3;
+
+fn format_from_dxgi(dxgi: u32) -> Option<(BlockFormat, bool)> {
+    match dxgi {
+        71 => Some((BlockFormat::Bc1a, false)),
+        72 => Some((BlockFormat::Bc1a, true)),
+        74 => Some((BlockFormat::Bc2, false)),
+        75 => Some((BlockFormat::Bc2, true)),
+        77 => Some((BlockFormat::Bc3, false)),
+        78 => Some((BlockFormat::Bc3, true)),
+        80 => Some((BlockFormat::Bc4, false)),
+        81 => Some((BlockFormat::Bc4Signed, false)),
+        83 => Some((BlockFormat::Bc5, false)),
+        84 => Some((BlockFormat::Bc5Signed, false)),
+        95 => Some((BlockFormat::Bc6h, false)),
+        96 => Some((BlockFormat::Bc6hSigned, false)),
+        98 => Some((BlockFormat::Bc7, false)),
+        99 => Some((BlockFormat::Bc7, true)),
+        _ => None,
+    }
+}
+
+fn format_to_dxgi(format: BlockFormat, srgb: bool) -> u32 {
+    let srgb = srgb as u32;
+    match format {
+        BlockFormat::Bc1 | BlockFormat::Bc1a => 71 + srgb,
+        BlockFormat::Bc2 => 74 + srgb,
+        BlockFormat::Bc3 => 77 + srgb,
+        BlockFormat::Bc4 => 80,
+        BlockFormat::Bc4Signed => 81,
+        BlockFormat::Bc5 => 83,
+        BlockFormat::Bc5Signed => 84,
+        BlockFormat::Bc6h => 95,
+        BlockFormat::Bc6hSigned => 96,
+        BlockFormat::Bc7 => 98 + srgb,
+    }
+}
+
+pub fn is_dds(data: &[u8]) -> bool {
+    read_u32(data, 0).map_or(false, |magic| magic == DDS_MAGIC)
+}
+
+pub fn parse_dds(data: &[u8]) -> Result<CompressedImage, Box<dyn Error>> {
+    if !is_dds(data) {
+        return Err("Not a DDS file".into());
+    }
+    let header = |field: usize| read_u32(data, 4 + field * 4);
+
+    if header(0)? as usize != DDS_HEADER_SIZE {
+        return Err("Invalid DDS header size".into());
+    }
+    let flags = header(1)?;
+    let height = header(2)?;
+    let width = header(3)?;
+    let mip_count = if flags & DDSD_MIPMAPCOUNT != 0 {
+        header(6)?.max(1)
+    } else {
+        1
+    };
+    let pf_flags = header(19)?;
+    let pf_fourcc = header(20)?;
+    let caps2 = header(27)?;
+
+    if caps2 & (DDSCAPS2_CUBEMAP | DDSCAPS2_VOLUME) != 0 {
+        return Err("Cube map and volume DDS files are not supported".into());
+    }
+    if pf_flags & DDPF_FOURCC == 0 {
+        return Err("Uncompressed DDS files are not supported".into());
+    }
+
+    let mut data_offset = 4 + DDS_HEADER_SIZE;
+    let (format, srgb) = match &pf_fourcc.to_le_bytes() {
+        b"DXT1" => (BlockFormat::Bc1a, false),
+        b"DXT2" | b"DXT3" => (BlockFormat::Bc2, false),
+        b"DXT4" | b"DXT5" => (BlockFormat::Bc3, false),
+        b"ATI1" | b"BC4U" => (BlockFormat::Bc4, false),
+        b"BC4S" => (BlockFormat::Bc4Signed, false),
+        b"ATI2" | b"BC5U" => (BlockFormat::Bc5, false),
+        b"BC5S" => (BlockFormat::Bc5Signed, false),
+        b"DX10" => {
+            let dxgi = read_u32(data, data_offset)?;
+            let dimension = read_u32(data, data_offset + 4)?;
+            let array_size = read_u32(data, data_offset + 12)?;
+            if dimension != D3D10_RESOURCE_DIMENSION_TEXTURE2D || array_size > 1 {
+                return Err("Only single 2D DDS textures are supported".into());
+            }
+            data_offset += 20;
+            format_from_dxgi(dxgi).ok_or_else(|| format!("Unsupported DXGI format {}", dxgi))?
+        }
+        code => {
+            return Err(
+                format!("Unsupported DDS FourCC {:?}", String::from_utf8_lossy(code)).into(),
+            )
+        }
+    };
+
+    check_dimensions(width, height, mip_count)?;
+
+    let mut levels = Vec::with_capacity(mip_count as usize);
+    let mut offset = data_offset;
+    for level in 0..mip_count {
+        let size = format.level_size(mip_dimension(width, level), mip_dimension(height, level));
+        let bytes = offset
+            .checked_add(size)
+            .and_then(|end| data.get(offset..end))
+            .ok_or_else(|| format!("DDS mip level {} is truncated", level))?;
+        levels.push(bytes.to_vec());
+        offset += size;
+    }
+
+    Ok(CompressedImage {
+        format,
+        srgb,
+        width,
+        height,
+        levels,
+    })
+}
+
+pub fn write_dds(image: &CompressedImage) -> Result<Vec<u8>, Box<dyn Error>> {
+    image.check_levels()?;
+
+    let mut header = [0u32; DDS_HEADER_SIZE / 4];
+    header[0] = DDS_HEADER_SIZE as u32;
+    header[1] = DDSD_CAPS
+        | DDSD_HEIGHT
+        | DDSD_WIDTH
+        | DDSD_PIXELFORMAT
+        | DDSD_MIPMAPCOUNT
+        | DDSD_LINEARSIZE;
+    header[2] = image.height;
+    header[3] = image.width;
+    header[4] = image.levels[0].len() as u32;
+    header[6] = image.levels.len() as u32;
+    header[18] = 32;
+    header[19] = DDPF_FOURCC;
+    header[20] = fourcc(b"DX10");
+    header[26] = DDSCAPS_TEXTURE;
+    if image.levels.len() > 1 {
+        header[26] |= DDSCAPS_COMPLEX | DDSCAPS_MIPMAP;
+    }
+
+    let dx10 = [
+        format_to_dxgi(image.format, image.srgb),
+        D3D10_RESOURCE_DIMENSION_TEXTURE2D,
+        0,
+        1,
+        0,
+    ];
+
+    let mut out = Vec::with_capacity(4 + DDS_HEADER_SIZE + 20 + image.size_bytes());
+    out.extend_from_slice(&DDS_MAGIC.to_le_bytes());
+    for word in header.iter().chain(dx10.iter()) {
+        out.extend_from_slice(&word.to_le_bytes());
+    }
+    for level in &image.levels {
+        out.extend_from_slice(level);
+    }
+    Ok(out)
+}
+
+// KTX2
+
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+const KTX2_HEADER_SIZE: usize = 80;
+const KTX2_LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+fn format_from_vk(vk_format: u32) -> Option<(BlockFormat, bool)> {
+    match vk_format {
+        131 => Some((BlockFormat::Bc1, false)),
+        132 => Some((BlockFormat::Bc1, true)),
+        133 => Some((BlockFormat::Bc1a, false)),
+        134 => Some((BlockFormat::Bc1a, true)),
+        135 => Some((BlockFormat::Bc2, false)),
+        136 => Some((BlockFormat::Bc2, true)),
+        137 => Some((BlockFormat::Bc3, false)),
+        138 => Some((BlockFormat::Bc3, true)),
+        139 => Some((BlockFormat::Bc4, false)),
+        140 => Some((BlockFormat::Bc4Signed, false)),
+        141 => Some((BlockFormat::Bc5, false)),
+        142 => Some((BlockFormat::Bc5Signed, false)),
+        143 => Some((BlockFormat::Bc6h, false)),
+        144 => Some((BlockFormat::Bc6hSigned, false)),
+        145 => Some((BlockFormat::Bc7, false)),
+        146 => Some((BlockFormat::Bc7, true)),
+        _ => None,
+    }
+}
+
+fn format_to_vk(format: BlockFormat, srgb: bool) -> u32 {
+    let srgb = srgb as u32;
+    match format {
+        BlockFormat::Bc1 => 131 + srgb,
+        BlockFormat::Bc1a => 133 + srgb,
+        BlockFormat::Bc2 => 135 + srgb,
+        BlockFormat::Bc3 => 137 + srgb,
+        BlockFormat::Bc4 => 139,
+        BlockFormat::Bc4Signed => 140,
+        BlockFormat::Bc5 => 141,
+        BlockFormat::Bc5Signed => 142,
+        BlockFormat::Bc6h => 143,
+        BlockFormat::Bc6hSigned => 144,
+        BlockFormat::Bc7 => 145 + srgb,
+    }
+}
+
+pub fn is_ktx2(data: &[u8]) -> bool {
+    data.starts_with(&KTX2_IDENTIFIER)
+}
+
+pub fn parse_ktx2(data: &[u8]) -> Result<CompressedImage, Box<dyn Error>> {
+    if !is_ktx2(data) {
+        return Err("Not a KTX2 file".into());
+    }
+
+    let vk_format = read_u32(data, 12)?;
+    let width = read_u32(data, 20)?;
+    let height = read_u32(data, 24)?;
+    let depth = read_u32(data, 28)?;
+    let layer_count = read_u32(data, 32)?;
+    let face_count = read_u32(data, 36)?;
+    let level_count = read_u32(data, 40)?.max(1);
+    let supercompression = read_u32(data, 44)?;
+
+    let (format, srgb) = format_from_vk(vk_format)
+        .ok_or_else(|| format!("Unsupported KTX2 vkFormat {}", vk_format))?;
+    if depth > 0 || layer_count > 0 || face_count != 1 {
+        return Err("Only single 2D KTX2 textures are supported".into());
+    }
+    if supercompression != 0 {
+        return Err("Supercompressed KTX2 files are not supported".into());
+    }
+
+    check_dimensions(width, height, level_count)?;
+
+    let mut levels = Vec::with_capacity(level_count as usize);
+    for level in 0..level_count as usize {
+        let entry = KTX2_HEADER_SIZE + level * KTX2_LEVEL_INDEX_ENTRY_SIZE;
+        let offset = read_u64(data, entry)?;
+        let length = read_u64(data, entry + 8)?;
+        let bytes = offset
+            .checked_add(length)
+            .and_then(|end| data.get(usize::try_from(offset).ok()?..usize::try_from(end).ok()?))
+            .ok_or_else(|| format!("KTX2 mip level {} is truncated", level))?;
+        levels.push(bytes.to_vec());
+    }
+
+    let image = CompressedImage {
+        format,
+        srgb,
+        width,
+        height,
+        levels,
+    };
+    image.check_levels()?;
+    Ok(image)
+}
+
+/// Basic data format descriptor for the BCn formats we write.
+fn ktx2_dfd(format: BlockFormat, srgb: bool) -> Result<Vec<u8>, Box<dyn Error>> {
+    const CHANNEL_COLOR: u8 = 0;
+    const CHANNEL_GREEN: u8 = 1;
+    const CHANNEL_BC1A_ALPHA: u8 = 1;
+    const CHANNEL_ALPHA: u8 = 15;
+    const SAMPLE_SIGNED: u8 = 0x40;
+
+    let (color_model, samples): (u8, Vec<(u16, u8)>) = match format {
+        BlockFormat::Bc1 => (128, vec![(0, CHANNEL_COLOR)]),
+        BlockFormat::Bc1a => (128, vec![(0, CHANNEL_BC1A_ALPHA)]),
+        BlockFormat::Bc2 => (129, vec![(0, CHANNEL_ALPHA), (64, CHANNEL_COLOR)]),
+        BlockFormat::Bc3 => (130, vec![(0, CHANNEL_ALPHA), (64, CHANNEL_COLOR)]),
+        BlockFormat::Bc4 => (131, vec![(0, CHANNEL_COLOR)]),
+        BlockFormat::Bc4Signed => (131, vec![(0, CHANNEL_COLOR | SAMPLE_SIGNED)]),
+        BlockFormat::Bc5 => (132, vec![(0, CHANNEL_COLOR), (64, CHANNEL_GREEN)]),
+        BlockFormat::Bc5Signed => (
+            132,
+            vec![
+                (0, CHANNEL_COLOR | SAMPLE_SIGNED),
+                (64, CHANNEL_GREEN | SAMPLE_SIGNED),
+            ],
+        ),
+        _ => return Err(format!("Writing {:?} to KTX2 is not supported", format).into()),
+    };
+
+    let block_size = 24 + 16 * samples.len();
+    let mut dfd = Vec::with_capacity(4 + block_size);
+    dfd.extend_from_slice(&((4 + block_size) as u32).to_le_bytes());
+    dfd.extend_from_slice(&0u32.to_le_bytes());
+    dfd.extend_from_slice(&(2u32 | ((block_size as u32) << 16)).to_le_bytes());
+    dfd.push(color_model);
+    dfd.push(1);
+    dfd.push(if srgb { 2 } else { 1 });
+    dfd.push(0);
+    dfd.extend_from_slice(&[3, 3, 0, 0]);
+    dfd.push(format.block_bytes() as u8);
+    dfd.extend_from_slice(&[0; 7]);
+
+    for (bit_offset, channel) in samples {
+        let signed = channel & SAMPLE_SIGNED != 0;
+        dfd.extend_from_slice(&bit_offset.to_le_bytes());
+        dfd.push(63);
+        dfd.push(channel);
+        dfd.extend_from_slice(&[0; 4]);
+        let (lower, upper) = if signed {
+            (0x8000_0000u32, 0x7FFF_FFFFu32)
+        } else {
+            (0, u32::MAX)
+        };
+        dfd.extend_from_slice(&lower.to_le_bytes());
+        dfd.extend_from_slice(&upper.to_le_bytes());
+    }
+
+    Ok(dfd)
+}
+
+pub fn write_ktx2(image: &CompressedImage) -> Result<Vec<u8>, Box<dyn Error>> {
+    image.check_levels()?;
+
+    let level_count = image.levels.len();
+    let dfd = ktx2_dfd(image.format, image.srgb)?;
+    let dfd_offset = KTX2_HEADER_SIZE + level_count * KTX2_LEVEL_INDEX_ENTRY_SIZE;
+
+    let align = image.format.block_bytes();
+    let align_up = |n: usize| (n + align - 1) / align * align;
+
+    // Level data is stored smallest mip first.
+    let mut level_offsets = vec![0usize; level_count];
+    let mut offset = align_up(dfd_offset + dfd.len());
+    for level in (0..level_count).rev() {
+        level_offsets[level] = offset;
+        offset = align_up(offset + image.levels[level].len());
+    }
+
+    let mut out = Vec::with_capacity(offset);
+    out.extend_from_slice(&KTX2_IDENTIFIER);
+    for word in [
+        format_to_vk(image.format, image.srgb),
+        1,
+        image.width,
+        image.height,
+        0,
+        0,
+        1,
+        level_count as u32,
+        0,
+        dfd_offset as u32,
+        dfd.len() as u32,
+        0,
+        0,
+    ] {
+        out.extend_from_slice(&word.to_le_bytes());
+    }
+    out.extend_from_slice(&0u64.to_le_bytes());
+    out.extend_from_slice(&0u64.to_le_bytes());
+
+    for (level, data) in image.levels.iter().enumerate() {
+        out.extend_from_slice(&(level_offsets[level] as u64).to_le_bytes());
+        out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    }
+    out.extend_from_slice(&dfd);
+
+    for level in (0..level_count).rev() {
+        out.resize(level_offsets[level], 0);
+        out.extend_from_slice(&image.levels[level]);
+    }
+
+    Ok(out)
+}
+
+// Encoding
+
+/// Compresses tightly packed RGBA8 pixels. Only the formats that a simple
+/// range-fit encoder handles well are supported; BC2, BC6H and BC7 need a
+/// dedicated encoder.
+pub fn encode(
+    format: BlockFormat,
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let size = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(4))
+        .ok_or("Image dimensions are too large")?;
+    if rgba.len() < size {
+        return Err("Pixel buffer is smaller than the image".into());
+    }
+
+    let mut out = Vec::with_capacity(format.level_size(width, height));
+    for block_y in 0..height.div_ceil(4) {
+        for block_x in 0..width.div_ceil(4) {
+            let block = fetch_block(rgba, width, height, block_x * 4, block_y * 4);
+            match format {
+                BlockFormat::Bc1 => encode_bc1(&block, false, &mut out),
+                BlockFormat::Bc1a => encode_bc1(&block, true, &mut out),
+                BlockFormat::Bc3 => {
+                    encode_bc4(&channel(&block, 3), &mut out);
+                    encode_bc1(&block, false, &mut out);
+                }
+                BlockFormat::Bc4 => encode_bc4(&channel(&block, 0), &mut out),
+                BlockFormat::Bc5 => {
+                    encode_bc4(&channel(&block, 0), &mut out);
+                    encode_bc4(&channel(&block, 1), &mut out);
+                }
+                _ => return Err(format!("Encoding {:?} is not supported", format).into()),
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Reads a 4x4 block, clamping at the image edges.
+fn fetch_block(rgba: &[u8], width: u32, height: u32, x0: u32, y0: u32) -> [[u8; 4]; 16] {
+    let mut block = [[0u8; 4]; 16];
+    for y in 0..4 {
+        for x in 0..4 {
+            let px = (x0 + x).min(width - 1) as usize;
+            let py = (y0 + y).min(height - 1) as usize;
+            let i = (py * width as usize + px) * 4;
+            block[(y * 4 + x) as usize].copy_from_slice(&rgba[i..i + 4]);
+        }
+    }
+    block
+}
+
+fn channel(block: &[[u8; 4]; 16], c: usize) -> [u8; 16] {
+    let mut values = [0u8; 16];
+    for (value, pixel) in values.iter_mut().zip(block.iter()) {
+        *value = pixel[c];
+    }
+    values
+}
+
+fn to_565(c: [f32; 3]) -> u16 {
+    let r = (c[0].clamp(0.0, 255.0) * 31.0 / 255.0).round() as u16;
+    let g = (c[1].clamp(0.0, 255.0) * 63.0 / 255.0).round() as u16;
+    let b = (c[2].clamp(0.0, 255.0) * 31.0 / 255.0).round() as u16;
+    (r << 11) | (g << 5) | b
+}
+
+fn from_565(c: u16) -> [f32; 3] {
+    let r = ((c >> 11) & 31) as f32;
+    let g = ((c >> 5) & 63) as f32;
+    let b = (c & 31) as f32;
+    [r * 255.0 / 31.0, g * 255.0 / 63.0, b * 255.0 / 31.0]
+}
+
+fn distance_sq(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (0..3).map(|i| (a[i] - b[i]) * (a[i] - b[i])).sum()
+}
+
+/// BC1 range fit: endpoints are the extreme pixels along the bounding-box
+/// diagonal. With `punch_through` fully transparent pixels use the 3-color
+/// mode's transparent index.
+fn encode_bc1(block: &[[u8; 4]; 16], punch_through: bool, out: &mut Vec<u8>) {
+    let transparent = |p: &[u8; 4]| punch_through && p[3] < 128;
+    let opaque: Vec<[f32; 3]> = block
+        .iter()
+        .filter(|p| !transparent(p))
+        .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32])
+        .collect();
+    let has_transparent = opaque.len() < 16;
+
+    let (mut low, mut high) = ([255.0f32; 3], [0.0f32; 3]);
+    for p in &opaque {
+        for i in 0..3 {
+            low[i] = low[i].min(p[i]);
+            high[i] = high[i].max(p[i]);
+        }
+    }
+    if opaque.is_empty() {
+        low = [0.0; 3];
+        high = [0.0; 3];
+    }
+
+    let axis = [high[0] - low[0], high[1] - low[1], high[2] - low[2]];
+    let project = |p: &[f32; 3]| p[0] * axis[0] + p[1] * axis[1] + p[2] * axis[2];
+    let mut min_p = low;
+    let mut max_p = high;
+    if let (Some(min), Some(max)) = (
+        opaque
+            .iter()
+            .min_by(|a, b| project(a).total_cmp(&project(b))),
+        opaque
+            .iter()
+            .max_by(|a, b| project(a).total_cmp(&project(b))),
+    ) {
+        min_p = *min;
+        max_p = *max;
+    }
+
+    let mut c0 = to_565(max_p);
+    let mut c1 = to_565(min_p);
+
+    // Four-color mode needs c0 > c1, three-color (with transparency) c0 <= c1.
+    if has_transparent {
+        if c0 > c1 {
+            std::mem::swap(&mut c0, &mut c1);
+        }
+    } else if c0 < c1 {
+        std::mem::swap(&mut c0, &mut c1);
+    }
+
+    let e0 = from_565(c0);
+    let e1 = from_565(c1);
+    let lerp = |a: [f32; 3], b: [f32; 3], t: f32| {
+        [
+            a[0] + (b[0] - a[0]) * t,
+            a[1] + (b[1] - a[1]) * t,
+            a[2] + (b[2] - a[2]) * t,
+        ]
+    };
+    let palette: Vec<[f32; 3]> = if c0 > c1 {
+        vec![e0, e1, lerp(e0, e1, 1.0 / 3.0), lerp(e0, e1, 2.0 / 3.0)]
+    } else {
+        vec![e0, e1, lerp(e0, e1, 0.5)]
+    };
+
+    let mut indices = 0u32;
+    for (i, p) in block.iter().enumerate() {
+        let index = if transparent(p) {
+            3
+        } else if c0 == c1 {
+            0
+        } else {
+            let color = [p[0] as f32, p[1] as f32, p[2] as f32];
+            (0..palette.len())
+                .min_by(|&a, &b| {
+                    distance_sq(color, palette[a]).total_cmp(&distance_sq(color, palette[b]))
+                })
+                .unwrap_or(0) as u32
+        };
+        indices |= index << (i * 2);
+    }
+
+    out.extend_from_slice(&c0.to_le_bytes());
+    out.extend_from_slice(&c1.to_le_bytes());
+    out.extend_from_slice(&indices.to_le_bytes());
+}
+
+/// BC4 in eight-value mode with the block's min and max as endpoints.
+fn encode_bc4(values: &[u8; 16], out: &mut Vec<u8>) {
+    let max = *values.iter().max().unwrap_or(&0);
+    let min = *values.iter().min().unwrap_or(&0);
+
+    let mut indices = 0u64;
+    if max != min {
+        let range = (max - min) as f32;
+        for (i, &v) in values.iter().enumerate() {
+            let step = (((max - v) as f32 / range) * 7.0).round() as u64;
+            let index = match step {
+                0 => 0,
+                7 => 1,
+                s => s + 1,
+            };
+            indices |= index << (i * 3);
+        }
+    }
+
+    out.push(max);
+    out.push(min);
+    out.extend_from_slice(&indices.to_le_bytes()[..6]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An 8x4 BC1 image with its full mip chain of four levels.
+    fn test_image() -> CompressedImage {
+        let levels = [16, 8, 8, 8]
+            .iter()
+            .enumerate()
+            .map(|(level, &size)| (0..size).map(|i| (level * 31 + i) as u8).collect())
+            .collect();
+        CompressedImage {
+            format: BlockFormat::Bc1,
+            srgb: true,
+            width: 8,
+            height: 4,
+            levels,
+        }
+    }
+
+    fn assert_same(parsed: &CompressedImage, image: &CompressedImage) {
+        assert_eq!(parsed.srgb, image.srgb);
+        assert_eq!((parsed.width, parsed.height), (image.width, image.height));
+        assert_eq!(parsed.levels, image.levels);
+    }
+
+    fn set_u32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    #[test]
+    fn dds_round_trip() {
+        let image = test_image();
+        let parsed = parse_dds(&write_dds(&image).unwrap()).unwrap();
+        // DX10 headers have no separate BC1 without alpha.
+        assert_eq!(parsed.format, BlockFormat::Bc1a);
+        assert_same(&parsed, &image);
+    }
+
+    #[test]
+    fn ktx2_round_trip() {
+        let image = test_image();
+        let parsed = parse_ktx2(&write_ktx2(&image).unwrap()).unwrap();
+        assert_eq!(parsed.format, image.format);
+        assert_same(&parsed, &image);
+    }
+
+    #[test]
+    fn truncated_files_are_rejected() {
+        let dds = write_dds(&test_image()).unwrap();
+        let ktx2 = write_ktx2(&test_image()).unwrap();
+        for len in [4, 64, dds.len() - 1] {
+            assert!(parse_dds(&dds[..len]).is_err());
+        }
+        for len in [12, 64, ktx2.len() - 1] {
+            assert!(parse_ktx2(&ktx2[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn encode_rejects_overflowing_sizes() {
+        assert!(encode(BlockFormat::Bc1, &[], u32::MAX, u32::MAX).is_err());
+        assert!(encode(BlockFormat::Bc1, &[0; 16], 2, 3).is_err());
+        assert_eq!(encode(BlockFormat::Bc1, &[0; 24], 2, 3).unwrap().len(), 8);
+    }
+
+    #[test]
+    fn oversized_headers_are_rejected() {
+        let dds = write_dds(&test_image()).unwrap();
+        let mut too_many_levels = dds.clone();
+        set_u32(&mut too_many_levels, 4 + 6 * 4, 5);
+        assert!(parse_dds(&too_many_levels).is_err());
+        let mut huge = dds.clone();
+        set_u32(&mut huge, 4 + 3 * 4, u32::MAX);
+        set_u32(&mut huge, 4 + 6 * 4, 40);
+        assert!(parse_dds(&huge).is_err());
+        set_u32(&mut huge, 4 + 6 * 4, 32);
+        assert!(parse_dds(&huge).is_err());
+
+        let ktx2 = write_ktx2(&test_image()).unwrap();
+        let mut too_many_levels = ktx2.clone();
+        set_u32(&mut too_many_levels, 40, u32::MAX);
+        assert!(parse_ktx2(&too_many_levels).is_err());
+        let mut past_end = ktx2.clone();
+        past_end[KTX2_HEADER_SIZE..KTX2_HEADER_SIZE + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(parse_ktx2(&past_end).is_err());
+    }
+}
@@ -0,0 +1,284 @@
+use gl::types::*;
+use glam::*;
+use std::f32::consts::TAU;
+use std::rc::Rc;
+
+use super::bounds::Aabb;
+use super::dynamic_buffer::{DynamicBuffer, StreamMode};
+use super::leak_tracker::{self, GlObject};
+use super::shader::Shader;
+
+/// Line segments per circle of `DebugDraw::sphere` and `circle`.
+const CIRCLE_SEGMENTS: usize = 32;
+
+/// Whether debug lines are hidden behind scene geometry.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepthMode {
+    /// Depth tested against what was drawn before the flush.
+    Tested,
+    /// Drawn on top of everything.
+    Overlay,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct DebugVertex {
+    position: Vec3,
+    color: [f32; 4],
+}
+
+/// Immediate-mode line drawing for visualizing positions, directions and
+/// volumes.
+///
+/// Shapes are collected during the frame in world space and drawn by
+/// `flush`, which uploads all of them at once and issues one draw per
+/// `DepthMode` in use. Shapes take the mode set last with
+/// `set_depth_mode`, and are placed in world space by the matrix set last
+/// with `set_transform`.
+pub struct DebugDraw {
+    /// `debug_vert.glsl` / `debug_frag.glsl`
+    shader: Rc<Shader>,
+    vao: GLuint,
+    buffer: DynamicBuffer,
+    /// The buffer the vertex array points at, which changes when it grows.
+    attached: GLuint,
+    tested: Vec<DebugVertex>,
+    overlay: Vec<DebugVertex>,
+    depth_mode: DepthMode,
+    transform: Mat4,
+}
+
+#[allow(dead_code)]
+impl DebugDraw {
+    pub fn new(shader: Rc<Shader>) -> DebugDraw {
+        let mut vao = 0;
+        unsafe { gl::GenVertexArrays(1, &mut vao) };
+        leak_tracker::created(GlObject::VertexArray, vao);
+
+        DebugDraw {
+            shader,
+            vao,
+            buffer: DynamicBuffer::new(StreamMode::PersistentRing),
+            attached: 0,
+            tested: Vec::new(),
+            overlay: Vec::new(),
+            depth_mode: DepthMode::Tested,
+            transform: Mat4::IDENTITY,
+        }
+    }
+
+    pub fn depth_mode(&self) -> DepthMode {
+        self.depth_mode
+    }
+
+    pub fn set_depth_mode(&mut self, depth_mode: DepthMode) {
+        self.depth_mode = depth_mode;
+    }
+
+    pub fn transform(&self) -> Mat4 {
+        self.transform
+    }
+
+    /// Applied to the points of shapes added from now on. May be
+    /// projective, points are divided by w.
+    pub fn set_transform(&mut self, transform: Mat4) {
+        self.transform = transform;
+    }
+
+    /// Number of lines waiting for `flush`.
+    pub fn line_count(&self) -> usize {
+        (self.tested.len() + self.overlay.len()) / 2
+    }
+
+    pub fn line(&mut self, from: Vec3, to: Vec3, color: Vec4) {
+        let lines = match self.depth_mode {
+            DepthMode::Tested => &mut self.tested,
+            DepthMode::Overlay => &mut self.overlay,
+        };
+        let color = color.to_array();
+        lines.push(DebugVertex {
+            position: self.transform.project_point3(from),
+            color,
+        });
+        lines.push(DebugVertex {
+            position: self.transform.project_point3(to),
+            color,
+        });
+    }
+
+    /// The twelve edges of `aabb`, nothing if it is empty.
+    pub fn aabb(&mut self, aabb: &Aabb, color: Vec4) {
+        if aabb.is_empty() {
+            return;
+        }
+
+        // Corner indices differ in one bit per axis, see `Aabb::corners`.
+        let corners = aabb.corners();
+        for i in 0..8 {
+            for axis in [1, 2, 4] {
+                if i & axis == 0 {
+                    self.line(corners[i], corners[i | axis], color);
+                }
+            }
+        }
+    }
+
+    pub fn circle(&mut self, center: Vec3, normal: Vec3, radius: f32, color: Vec4) {
+        let (u, v) = normal.normalize().any_orthonormal_pair();
+        let point = |i: usize| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * TAU;
+            center + (u * angle.cos() + v * angle.sin()) * radius
+        };
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    /// Three great circles, one around each axis.
+    pub fn sphere(&mut self, center: Vec3, radius: f32, color: Vec4) {
+        self.circle(center, Vec3::X, radius, color);
+        self.circle(center, Vec3::Y, radius, color);
+        self.circle(center, Vec3::Z, radius, color);
+    }
+
+    /// A line from `from` to `to` with a four-sided head at `to`.
+    pub fn arrow(&mut self, from: Vec3, to: Vec3, color: Vec4) {
+        self.line(from, to, color);
+
+        let direction = to - from;
+        let length = direction.length();
+        if length <= 0.0 {
+            return;
+        }
+        let direction = direction / length;
+        let (u, v) = direction.any_orthonormal_pair();
+        let head_length = length * 0.2;
+        let base = to - direction * head_length;
+        for side in [u, -u, v, -v] {
+            self.line(to, base + side * head_length * 0.4, color);
+        }
+    }
+
+    /// X, Y and Z of `transform` as red, green and blue arrows of `size`.
+    pub fn axes(&mut self, transform: &Mat4, size: f32) {
+        let origin = transform.transform_point3(Vec3::ZERO);
+        let axes = [
+            (Vec3::X, Vec4::new(1.0, 0.0, 0.0, 1.0)),
+            (Vec3::Y, Vec4::new(0.0, 1.0, 0.0, 1.0)),
+            (Vec3::Z, Vec4::new(0.0, 0.0, 1.0, 1.0)),
+        ];
+        for (axis, color) in axes {
+            let tip = transform.transform_point3(axis * size);
+            self.arrow(origin, tip, color);
+        }
+    }
+
+    /// The edges of the volume seen through `view_projection`.
+    pub fn frustum(&mut self, view_projection: &Mat4, color: Vec4) {
+        let inverse = view_projection.inverse();
+        // Same corner order as `Aabb::corners`, in normalized device space.
+        let ndc = Aabb::new(Vec3::splat(-1.0), Vec3::ONE).corners();
+        let corners = ndc.map(|corner| inverse.project_point3(corner));
+        for i in 0..8 {
+            for axis in [1, 2, 4] {
+                if i & axis == 0 {
+                    self.line(corners[i], corners[i | axis], color);
+                }
+            }
+        }
+    }
+
+    /// A square grid of `size` in the XZ plane through `center`, with
+    /// `divisions` cells along each side.
+    pub fn grid(&mut self, center: Vec3, size: f32, divisions: u32, color: Vec4) {
+        let divisions = divisions.max(1);
+        let half = size * 0.5;
+        for i in 0..=divisions {
+            let offset = i as f32 / divisions as f32 * size - half;
+            self.line(
+                center + Vec3::new(offset, 0.0, -half),
+                center + Vec3::new(offset, 0.0, half),
+                color,
+            );
+            self.line(
+                center + Vec3::new(-half, 0.0, offset),
+                center + Vec3::new(half, 0.0, offset),
+                color,
+            );
+        }
+    }
+
+    /// Draws and forgets everything collected since the last flush.
+    pub fn flush(&mut self, view_projection: &Mat4) {
+        if self.tested.is_empty() && self.overlay.is_empty() {
+            return;
+        }
+
+        let tested_count = self.tested.len();
+        let overlay_count = self.overlay.len();
+        self.tested.append(&mut self.overlay);
+        let offset = self.buffer.write(&self.tested);
+        let first = (offset / std::mem::size_of::<DebugVertex>()) as GLint;
+        self.tested.clear();
+
+        self.attach_buffer();
+        self.shader.use_shader();
+        self.shader.set_mat4v("view_projection", view_projection);
+
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+
+            if tested_count > 0 {
+                gl::DrawArrays(gl::LINES, first, tested_count as GLsizei);
+            }
+            if overlay_count > 0 {
+                gl::Disable(gl::DEPTH_TEST);
+                gl::DrawArrays(
+                    gl::LINES,
+                    first + tested_count as GLint,
+                    overlay_count as GLsizei,
+                );
+                gl::Enable(gl::DEPTH_TEST);
+            }
+
+            gl::Disable(gl::BLEND);
+            gl::BindVertexArray(0);
+        }
+    }
+
+    fn attach_buffer(&mut self) {
+        let buffer = self.buffer.buffer();
+        if buffer == self.attached {
+            return;
+        }
+
+        let stride = std::mem::size_of::<DebugVertex>() as GLsizei;
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, buffer);
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, 0 as *const GLvoid);
+            gl::EnableVertexAttribArray(1);
+            gl::VertexAttribPointer(
+                1,
+                4,
+                gl::FLOAT,
+                gl::FALSE,
+                stride,
+                std::mem::size_of::<Vec3>() as *const GLvoid,
+            );
+            gl::BindVertexArray(0);
+        }
+        self.attached = buffer;
+    }
+}
+
+impl Drop for DebugDraw {
+    fn drop(&mut self) {
+        leak_tracker::deleted(GlObject::VertexArray, self.vao);
+        unsafe { gl::DeleteVertexArrays(1, &self.vao) };
+    }
+}
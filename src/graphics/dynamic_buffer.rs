@@ -0,0 +1,260 @@
+use gl::types::*;
+use std::ptr;
+
+use super::leak_tracker::{self, GlObject};
+use super::{gl_has_extension, gl_version_at_least};
+
+/// Ring slots of a `StreamMode::PersistentRing` buffer, enough for the CPU
+/// to write one while the GPU still reads the previous two.
+const RING_SLOTS: usize = 3;
+/// Smallest allocation, so that small buffers do not grow on every update.
+const MIN_CAPACITY: usize = 4096;
+
+/// How a `DynamicBuffer` gets new contents to the GPU.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamMode {
+    /// `BufferSubData` into a `DYNAMIC_DRAW` buffer. Suits data that changes
+    /// now and then; the driver may stall if the buffer is still in use.
+    SubData,
+    /// Orphans the `STREAM_DRAW` storage before every write, so the driver
+    /// can hand out fresh memory instead of waiting for earlier draws.
+    Orphan,
+    /// Persistently mapped storage split into `RING_SLOTS` slots that are
+    /// written in turn, each guarded by a fence placed after the draws that
+    /// read it. Needs OpenGL 4.4 or `ARB_buffer_storage`, `Orphan` is used
+    /// otherwise or if the buffer cannot be mapped.
+    PersistentRing,
+}
+
+pub fn persistent_mapping_supported() -> bool {
+    gl_version_at_least(4, 4) || gl_has_extension("GL_ARB_buffer_storage")
+}
+
+/// A buffer object whose contents are replaced as a whole, typically every
+/// frame.
+///
+/// `write` returns the byte offset the data landed at, always zero unless
+/// ring buffered. Growing a ring buffer replaces the buffer object, so users
+/// that attach it to a vertex array compare `buffer()` after each write.
+pub struct DynamicBuffer {
+    buffer: GLuint,
+    mode: StreamMode,
+    /// Bytes available to one write, i.e. per slot for ring buffers.
+    capacity: usize,
+    mapping: *mut u8,
+    /// Slot of the last write, whose fence is placed by the next one.
+    slot: usize,
+    written: bool,
+    fences: [GLsync; RING_SLOTS],
+}
+
+fn round_up(value: usize, multiple: usize) -> usize {
+    value.div_ceil(multiple) * multiple
+}
+
+#[allow(dead_code)]
+impl DynamicBuffer {
+    pub fn new(mode: StreamMode) -> DynamicBuffer {
+        let mode = if mode == StreamMode::PersistentRing && !persistent_mapping_supported() {
+            StreamMode::Orphan
+        } else {
+            mode
+        };
+
+        let mut buffer = DynamicBuffer {
+            buffer: 0,
+            mode,
+            capacity: 0,
+            mapping: ptr::null_mut(),
+            slot: 0,
+            written: false,
+            fences: [ptr::null(); RING_SLOTS],
+        };
+        buffer.allocate(MIN_CAPACITY);
+        buffer
+    }
+
+    /// The mode in use, which may differ from the requested one.
+    pub fn mode(&self) -> StreamMode {
+        self.mode
+    }
+
+    pub fn buffer(&self) -> GLuint {
+        self.buffer
+    }
+
+    /// Bytes available to a single write without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Bytes of GPU memory held, all slots included.
+    pub fn size_bytes(&self) -> usize {
+        match self.mode {
+            StreamMode::PersistentRing => self.capacity * RING_SLOTS,
+            _ => self.capacity,
+        }
+    }
+
+    /// Replaces the contents with `data` and returns its byte offset in the
+    /// buffer, a multiple of the size of `T`.
+    pub fn write<T: Copy>(&mut self, data: &[T]) -> usize {
+        let bytes = std::mem::size_of_val(data);
+        let element_size = std::mem::size_of::<T>().max(1);
+
+        if self.mode == StreamMode::PersistentRing && self.written {
+            self.fence_slot(self.slot);
+        }
+        if bytes > self.capacity || self.capacity % element_size != 0 {
+            let capacity = bytes.max(self.capacity * 2).max(MIN_CAPACITY);
+            self.allocate(round_up(capacity, element_size));
+        }
+
+        let offset = match self.mode {
+            StreamMode::SubData | StreamMode::Orphan => unsafe {
+                gl::BindBuffer(gl::COPY_WRITE_BUFFER, self.buffer);
+                if self.mode == StreamMode::Orphan {
+                    gl::BufferData(
+                        gl::COPY_WRITE_BUFFER,
+                        self.capacity as GLsizeiptr,
+                        ptr::null(),
+                        gl::STREAM_DRAW,
+                    );
+                }
+                gl::BufferSubData(
+                    gl::COPY_WRITE_BUFFER,
+                    0,
+                    bytes as GLsizeiptr,
+                    data.as_ptr() as *const GLvoid,
+                );
+                gl::BindBuffer(gl::COPY_WRITE_BUFFER, 0);
+                0
+            },
+            StreamMode::PersistentRing => {
+                self.slot = (self.slot + 1) % RING_SLOTS;
+                self.wait_slot(self.slot);
+                let offset = self.slot * self.capacity;
+                unsafe {
+                    ptr::copy_nonoverlapping(
+                        data.as_ptr() as *const u8,
+                        self.mapping.add(offset),
+                        bytes,
+                    );
+                }
+                offset
+            }
+        };
+
+        self.written = true;
+        offset
+    }
+
+    /// Makes room for `capacity` bytes per write, discarding the contents.
+    fn allocate(&mut self, capacity: usize) {
+        self.capacity = capacity;
+
+        unsafe {
+            match self.mode {
+                StreamMode::SubData | StreamMode::Orphan => {
+                    if self.buffer == 0 {
+                        gl::GenBuffers(1, &mut self.buffer);
+                        leak_tracker::created(GlObject::Buffer, self.buffer);
+                    }
+                    let usage = if self.mode == StreamMode::SubData {
+                        gl::DYNAMIC_DRAW
+                    } else {
+                        gl::STREAM_DRAW
+                    };
+                    gl::BindBuffer(gl::COPY_WRITE_BUFFER, self.buffer);
+                    gl::BufferData(
+                        gl::COPY_WRITE_BUFFER,
+                        capacity as GLsizeiptr,
+                        ptr::null(),
+                        usage,
+                    );
+                }
+                StreamMode::PersistentRing => {
+                    // Immutable storage cannot be resized, the buffer is
+                    // replaced once the GPU is done with all of it.
+                    for slot in 0..RING_SLOTS {
+                        self.wait_slot(slot);
+                    }
+                    self.delete_buffer();
+                    self.written = false;
+
+                    let flags = gl::MAP_WRITE_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT;
+                    let size = (capacity * RING_SLOTS) as GLsizeiptr;
+                    gl::GenBuffers(1, &mut self.buffer);
+                    leak_tracker::created(GlObject::Buffer, self.buffer);
+                    gl::BindBuffer(gl::COPY_WRITE_BUFFER, self.buffer);
+                    gl::BufferStorage(gl::COPY_WRITE_BUFFER, size, ptr::null(), flags);
+                    self.mapping =
+                        gl::MapBufferRange(gl::COPY_WRITE_BUFFER, 0, size, flags) as *mut u8;
+
+                    // Drivers may refuse the mapping, e.g. when out of
+                    // address space, so stream by orphaning instead.
+                    if self.mapping.is_null() {
+                        eprintln!("dynamic buffer : persistent mapping failed, orphaning instead");
+                        gl::BindBuffer(gl::COPY_WRITE_BUFFER, 0);
+                        self.delete_buffer();
+                        self.mode = StreamMode::Orphan;
+                        self.allocate(capacity);
+                        return;
+                    }
+                }
+            }
+            gl::BindBuffer(gl::COPY_WRITE_BUFFER, 0);
+        }
+    }
+
+    /// Marks the end of the commands reading `slot`.
+    fn fence_slot(&mut self, slot: usize) {
+        unsafe {
+            if !self.fences[slot].is_null() {
+                gl::DeleteSync(self.fences[slot]);
+            }
+            self.fences[slot] = gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+        }
+    }
+
+    /// Blocks until the GPU has finished reading `slot`.
+    fn wait_slot(&mut self, slot: usize) {
+        let fence = std::mem::replace(&mut self.fences[slot], ptr::null());
+        if fence.is_null() {
+            return;
+        }
+
+        unsafe {
+            loop {
+                let result = gl::ClientWaitSync(fence, gl::SYNC_FLUSH_COMMANDS_BIT, 1_000_000);
+                if result != gl::TIMEOUT_EXPIRED {
+                    break;
+                }
+            }
+            gl::DeleteSync(fence);
+        }
+    }
+
+    fn delete_buffer(&mut self) {
+        if self.buffer == 0 {
+            return;
+        }
+        leak_tracker::deleted(GlObject::Buffer, self.buffer);
+        // Deleting a mapped buffer unmaps it.
+        unsafe { gl::DeleteBuffers(1, &self.buffer) };
+        self.buffer = 0;
+        self.mapping = ptr::null_mut();
+    }
+}
+
+impl Drop for DynamicBuffer {
+    fn drop(&mut self) {
+        for fence in self.fences {
+            if !fence.is_null() {
+                unsafe { gl::DeleteSync(fence) };
+            }
+        }
+        self.delete_buffer();
+    }
+}
@@ -0,0 +1,143 @@
+use gl::types::*;
+
+use super::bounds::Aabb;
+use super::dynamic_buffer::{DynamicBuffer, StreamMode};
+use super::leak_tracker::{self, GlObject};
+use super::material::Material;
+use super::model::{self, Vertex, JOINT_MATRICES_UNIT, MORPH_DELTAS_UNIT};
+use super::shader::Shader;
+
+/// A mesh whose geometry is replaced from the CPU, e.g. every frame for
+/// procedural geometry or particles. Drawn like a `Mesh`, without skinning
+/// or morph targets.
+pub struct DynamicMesh {
+    vao: GLuint,
+    vertices: DynamicBuffer,
+    indices: DynamicBuffer,
+    /// Vertex and index buffers the vertex array points at, which change
+    /// when a ring buffer grows.
+    attached: (GLuint, GLuint),
+    vertex_count: usize,
+    index_count: usize,
+    index_type: GLenum,
+    base_vertex: GLint,
+    index_offset: usize,
+    aabb: Aabb,
+}
+
+#[allow(dead_code)]
+impl DynamicMesh {
+    pub fn new(mode: StreamMode) -> DynamicMesh {
+        let mut vao = 0;
+        unsafe { gl::GenVertexArrays(1, &mut vao) };
+        leak_tracker::created(GlObject::VertexArray, vao);
+
+        DynamicMesh {
+            vao,
+            vertices: DynamicBuffer::new(mode),
+            indices: DynamicBuffer::new(mode),
+            attached: (0, 0),
+            vertex_count: 0,
+            index_count: 0,
+            index_type: gl::UNSIGNED_SHORT,
+            base_vertex: 0,
+            index_offset: 0,
+            aabb: Aabb::EMPTY,
+        }
+    }
+
+    /// The mode in use, which may differ from the requested one.
+    pub fn mode(&self) -> StreamMode {
+        self.vertices.mode()
+    }
+
+    /// Replaces the geometry. Indices are stored as 16-bit when the vertex
+    /// count allows.
+    pub fn update(&mut self, vertices: &[Vertex], indices: &[GLuint]) {
+        self.index_type = model::index_type(vertices.len());
+        self.index_offset = if self.index_type == gl::UNSIGNED_SHORT {
+            let indices: Vec<u16> = indices.iter().map(|&i| i as u16).collect();
+            self.indices.write(&indices)
+        } else {
+            self.indices.write(indices)
+        };
+        self.index_count = indices.len();
+
+        self.update_vertices(vertices);
+    }
+
+    /// Replaces the vertices and keeps the indices, which must still be in
+    /// bounds.
+    pub fn update_vertices(&mut self, vertices: &[Vertex]) {
+        let offset = self.vertices.write(vertices);
+        self.base_vertex = (offset / std::mem::size_of::<Vertex>()) as GLint;
+        self.vertex_count = vertices.len();
+        self.aabb = Aabb::from_points(vertices.iter().map(|vertex| vertex.position));
+        self.attach_buffers();
+    }
+
+    fn attach_buffers(&mut self) {
+        let buffers = (self.vertices.buffer(), self.indices.buffer());
+        if buffers == self.attached {
+            return;
+        }
+
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, buffers.0);
+            model::set_vertex_attributes();
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, buffers.1);
+            gl::BindVertexArray(0);
+        }
+        self.attached = buffers;
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertex_count
+    }
+
+    pub fn index_count(&self) -> usize {
+        self.index_count
+    }
+
+    /// Bounds of the current vertices in model space.
+    pub fn aabb(&self) -> &Aabb {
+        &self.aabb
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.vertices.size_bytes() + self.indices.size_bytes()
+    }
+
+    pub fn draw(&self, shader: &Shader, material: &Material) {
+        if self.index_count == 0 {
+            return;
+        }
+
+        shader.use_shader();
+
+        material.apply(shader);
+        shader.set_i32("skinned", 0);
+        shader.set_i32("joint_matrices", JOINT_MATRICES_UNIT as i32);
+        shader.set_i32("morph_deltas", MORPH_DELTAS_UNIT as i32);
+        shader.set_i32("morph_target_count", 0);
+
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::DrawElementsBaseVertex(
+                gl::TRIANGLES,
+                self.index_count as GLsizei,
+                self.index_type,
+                self.index_offset as *const GLvoid,
+                self.base_vertex,
+            );
+        }
+    }
+}
+
+impl Drop for DynamicMesh {
+    fn drop(&mut self) {
+        leak_tracker::deleted(GlObject::VertexArray, self.vao);
+        unsafe { gl::DeleteVertexArrays(1, &self.vao) };
+    }
+}
@@ -0,0 +1,816 @@
+//! Writes models back out as OBJ+MTL or glTF 2.0, so that import-time
+//! processing can be baked into asset files.
+//!
+//! Both formats get the full level of detail. Neither exporter writes
+//! skeletons or animations: skinned meshes are written in their current
+//! pose with their morph targets applied, while other meshes keep their
+//! morph targets in glTF. Textures are referenced by path, relative to the
+//! output file where possible, not copied. DDS and KTX2 textures, which
+//! core glTF cannot reference, are written through the `MSFT_texture_dds`
+//! and `KHR_texture_basisu` extensions.
+//!
+//! The writers take an `ExportData`, the CPU side of a model, so they run
+//! without a GL context.
+
+use gl::types::*;
+use glam::*;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+
+use super::material::{BlendMode, Material, MaterialData, MaterialTexture, ALPHA_CUTOFF};
+use super::model::{Mesh, MeshData, Model, Vertex};
+use super::mtl::TextureMap;
+
+/// What the exporters write: a model's materials and full level of detail.
+pub struct ExportData {
+    pub materials: Vec<MaterialData>,
+    /// Of each material. Loaded models decide it from their textures' alpha
+    /// channel, see `Material::blend_mode`.
+    pub blend_modes: Vec<BlendMode>,
+    pub meshes: Vec<MeshData>,
+}
+
+impl ExportData {
+    /// `model` in its current pose. Skinned meshes are baked, losing their
+    /// skin and morph targets.
+    pub fn from_model(model: &Model) -> ExportData {
+        let meshes = model
+            .meshes()
+            .iter()
+            .map(|mesh| {
+                let baked = mesh.is_skinned() && !model.joint_matrices().is_empty();
+                MeshData {
+                    vertices: posed_vertices(model, mesh).into_owned(),
+                    indices: mesh.indices().to_vec(),
+                    material: mesh.material(),
+                    skin: None,
+                    morph_targets: if baked {
+                        Vec::new()
+                    } else {
+                        mesh.morph_targets().to_vec()
+                    },
+                    morph_weights: if baked {
+                        Vec::new()
+                    } else {
+                        mesh.morph_weights().to_vec()
+                    },
+                    node: mesh.node(),
+                }
+            })
+            .collect();
+
+        ExportData {
+            materials: model.materials().iter().map(material_data).collect(),
+            blend_modes: model.materials().iter().map(Material::blend_mode).collect(),
+            meshes,
+        }
+    }
+}
+
+fn material_data(material: &Material) -> MaterialData {
+    let map =
+        |texture: &Option<MaterialTexture>| texture.as_ref().map(|texture| texture.map.clone());
+    MaterialData {
+        name: material.name.clone(),
+        ambient_color: material.ambient_color,
+        diffuse_color: material.diffuse_color,
+        specular_color: material.specular_color,
+        emissive_color: material.emissive_color,
+        ambient_texture: map(&material.ambient_texture),
+        diffuse_texture: map(&material.diffuse_texture),
+        specular_texture: map(&material.specular_texture),
+        emissive_texture: map(&material.emissive_texture),
+        alpha_texture: map(&material.alpha_texture),
+        shininess: material.shininess,
+        dissolve: material.dissolve,
+        optical_density: material.optical_density,
+        illumination_model: material.illumination_model,
+        unknown_params: material.unknown_params.clone(),
+    }
+}
+
+/// `target` relative to the directory `base`, or `target` itself if they
+/// do not share a root.
+fn relative_path(target: &Path, base: &Path) -> PathBuf {
+    let (target_abs, base_abs) = match (fs::canonicalize(target), fs::canonicalize(base)) {
+        (Ok(target), Ok(base)) => (target, base),
+        _ => return target.to_path_buf(),
+    };
+
+    let target_components: Vec<Component> = target_abs.components().collect();
+    let base_components: Vec<Component> = base_abs.components().collect();
+    let common = target_components
+        .iter()
+        .zip(&base_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 {
+        return target_abs;
+    }
+
+    let mut relative = PathBuf::new();
+    for _ in common..base_components.len() {
+        relative.push("..");
+    }
+    for component in &target_components[common..] {
+        relative.push(component);
+    }
+    relative
+}
+
+fn output_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Forward slashes, as both formats expect.
+fn texture_reference(map: &TextureMap, dir: &Path) -> String {
+    relative_path(&map.path, dir)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+/// The vertices as drawn: skinned meshes deformed by the model's current
+/// pose after applying their morph targets, others as stored.
+fn posed_vertices<'a>(model: &Model, mesh: &'a Mesh) -> Cow<'a, [Vertex]> {
+    let joint_matrices = model.joint_matrices();
+    if !mesh.is_skinned() || joint_matrices.is_empty() {
+        return Cow::Borrowed(mesh.vertices());
+    }
+
+    let vertices = mesh
+        .vertices()
+        .iter()
+        .zip(mesh.skin())
+        .enumerate()
+        .map(|(i, (vertex, skin))| {
+            let mut position = vertex.position;
+            let mut normal = vertex.normal;
+            for (target, &weight) in mesh.morph_targets().iter().zip(mesh.morph_weights()) {
+                position += target.positions[i] * weight;
+                normal += target.normals.get(i).copied().unwrap_or(Vec3::ZERO) * weight;
+            }
+
+            let skin_matrix = skin
+                .joints
+                .iter()
+                .zip(skin.weights)
+                .fold(Mat4::ZERO, |sum, (&joint, weight)| {
+                    sum + joint_matrices[joint as usize] * weight
+                });
+            let normal_matrix = Mat3::from_mat4(skin_matrix).inverse().transpose();
+
+            Vertex {
+                position: skin_matrix.transform_point3(position),
+                normal: (normal_matrix * normal).normalize_or_zero(),
+                tex_coords: vertex.tex_coords,
+            }
+        })
+        .collect();
+    Cow::Owned(vertices)
+}
+
+fn write_mtl_map(
+    out: &mut impl Write,
+    statement: &str,
+    map: &TextureMap,
+    dir: &Path,
+) -> io::Result<()> {
+    write!(out, "{}", statement)?;
+    if map.offset != Vec3::ZERO {
+        write!(
+            out,
+            " -o {} {} {}",
+            map.offset.x, map.offset.y, map.offset.z
+        )?;
+    }
+    if map.scale != Vec3::ONE {
+        write!(out, " -s {} {} {}", map.scale.x, map.scale.y, map.scale.z)?;
+    }
+    if map.bump_multiplier != 1.0 {
+        write!(out, " -bm {}", map.bump_multiplier)?;
+    }
+    writeln!(out, " {}", texture_reference(map, dir))
+}
+
+fn write_mtl_material(out: &mut impl Write, material: &MaterialData, dir: &Path) -> io::Result<()> {
+    let color = |out: &mut dyn Write, statement: &str, color: Vec3| {
+        writeln!(out, "{} {} {} {}", statement, color.x, color.y, color.z)
+    };
+
+    writeln!(out, "newmtl {}", material.name)?;
+    color(out, "Ka", material.ambient_color)?;
+    color(out, "Kd", material.diffuse_color)?;
+    color(out, "Ks", material.specular_color)?;
+    if material.emissive_color != Vec3::ZERO {
+        color(out, "Ke", material.emissive_color)?;
+    }
+    writeln!(out, "Ns {}", material.shininess)?;
+    writeln!(out, "d {}", material.dissolve)?;
+    writeln!(out, "Ni {}", material.optical_density)?;
+    if let Some(illumination_model) = material.illumination_model {
+        writeln!(out, "illum {}", illumination_model)?;
+    }
+
+    for (statement, texture) in [
+        ("map_Ka", &material.ambient_texture),
+        ("map_Kd", &material.diffuse_texture),
+        ("map_Ks", &material.specular_texture),
+        ("map_Ke", &material.emissive_texture),
+        ("map_d", &material.alpha_texture),
+    ] {
+        if let Some(map) = texture {
+            write_mtl_map(out, statement, map, dir)?;
+        }
+    }
+
+    // Parameters we understand were written above from their parsed values.
+    let mut params: Vec<_> = material
+        .unknown_params
+        .iter()
+        .filter(|(key, _)| !matches!(key.as_str(), "Ke" | "map_Ke" | "Tr"))
+        .collect();
+    params.sort();
+    for (key, value) in params {
+        writeln!(out, "{} {}", key, value)?;
+    }
+    writeln!(out)
+}
+
+/// Writes `path` and, next to it, an MTL library of the same name.
+pub fn write_obj(data: &ExportData, path: &Path) -> io::Result<()> {
+    let dir = output_dir(path);
+    let mtl_path = path.with_extension("mtl");
+    let mtl_name = mtl_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut mtl = BufWriter::new(File::create(&mtl_path)?);
+    for material in &data.materials {
+        write_mtl_material(&mut mtl, material, &dir)?;
+    }
+    mtl.flush()?;
+
+    let mut obj = BufWriter::new(File::create(path)?);
+    writeln!(obj, "mtllib {}", mtl_name)?;
+
+    // OBJ indices are 1-based and global to the file.
+    let mut first_vertex = 1;
+    for (i, mesh) in data.meshes.iter().enumerate() {
+        writeln!(obj, "o mesh_{}", i)?;
+        for vertex in &mesh.vertices {
+            let (position, normal, tex_coords) =
+                (vertex.position, vertex.normal, vertex.tex_coords);
+            writeln!(obj, "v {} {} {}", position.x, position.y, position.z)?;
+            writeln!(obj, "vt {} {}", tex_coords.x, tex_coords.y)?;
+            writeln!(obj, "vn {} {} {}", normal.x, normal.y, normal.z)?;
+        }
+
+        writeln!(obj, "usemtl {}", data.materials[mesh.material].name)?;
+        for triangle in mesh.indices.chunks_exact(3) {
+            write!(obj, "f")?;
+            for &index in triangle {
+                let index = first_vertex + index as usize;
+                write!(obj, " {0}/{0}/{0}", index)?;
+            }
+            writeln!(obj)?;
+        }
+        first_vertex += mesh.vertices.len();
+    }
+    obj.flush()
+}
+
+/// JSON string literal.
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Percent-encodes what may not appear in a relative URI reference.
+fn encode_uri(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            byte => {
+                let _ = write!(encoded, "%{:02X}", byte);
+            }
+        }
+    }
+    encoded
+}
+
+fn json_vec3(v: Vec3) -> String {
+    format!("[{},{},{}]", v.x, v.y, v.z)
+}
+
+const FLOAT: u32 = gl::FLOAT;
+const UNSIGNED_INT: u32 = gl::UNSIGNED_INT;
+const ARRAY_BUFFER: u32 = gl::ARRAY_BUFFER;
+const ELEMENT_ARRAY_BUFFER: u32 = gl::ELEMENT_ARRAY_BUFFER;
+
+/// The binary buffer of a glTF file with the views and accessors into it.
+#[derive(Default)]
+struct GltfBuffer {
+    data: Vec<u8>,
+    views: Vec<String>,
+    accessors: Vec<String>,
+}
+
+impl GltfBuffer {
+    fn add_vec3s(&mut self, values: &[Vec3], target: Option<u32>) -> usize {
+        let floats: Vec<f32> = values.iter().flat_map(|v| v.to_array()).collect();
+        // Positions require bounds, and they are cheap to give for all.
+        let min = values
+            .iter()
+            .fold(Vec3::splat(f32::MAX), |min, &v| min.min(v));
+        let max = values
+            .iter()
+            .fold(Vec3::splat(f32::MIN), |max, &v| max.max(v));
+        let bounds = format!(",\"min\":{},\"max\":{}", json_vec3(min), json_vec3(max));
+        self.add_accessor(&floats, FLOAT, values.len(), "VEC3", target, &bounds)
+    }
+
+    fn add_accessor<T: Copy>(
+        &mut self,
+        values: &[T],
+        component_type: u32,
+        count: usize,
+        kind: &str,
+        target: Option<u32>,
+        extra: &str,
+    ) -> usize {
+        while self.data.len() % 4 != 0 {
+            self.data.push(0);
+        }
+        let offset = self.data.len();
+        let bytes = unsafe {
+            std::slice::from_raw_parts(values.as_ptr() as *const u8, std::mem::size_of_val(values))
+        };
+        self.data.extend_from_slice(bytes);
+
+        let target = target.map_or_else(String::new, |target| format!(",\"target\":{}", target));
+        self.views.push(format!(
+            "{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{}{}}}",
+            offset,
+            bytes.len(),
+            target
+        ));
+        self.accessors.push(format!(
+            "{{\"bufferView\":{},\"componentType\":{},\"count\":{},\"type\":\"{}\"{}}}",
+            self.views.len() - 1,
+            component_type,
+            count,
+            kind,
+            extra
+        ));
+        self.accessors.len() - 1
+    }
+}
+
+fn gltf_material(
+    material: &MaterialData,
+    blend_mode: BlendMode,
+    texture_index: &mut dyn FnMut(&TextureMap) -> usize,
+) -> String {
+    let mut pbr = format!(
+        "\"baseColorFactor\":[{},{},{},{}],\"metallicFactor\":0,\"roughnessFactor\":{}",
+        material.diffuse_color.x,
+        material.diffuse_color.y,
+        material.diffuse_color.z,
+        material.dissolve,
+        // Inverse of the shininess approximation used on import.
+        1.0 - (material.shininess / 128.0).clamp(0.0, 1.0)
+    );
+    if let Some(map) = &material.diffuse_texture {
+        let _ = write!(
+            pbr,
+            ",\"baseColorTexture\":{{\"index\":{}}}",
+            texture_index(map)
+        );
+    }
+
+    let mut json = format!(
+        "{{\"name\":{},\"pbrMetallicRoughness\":{{{}}},\"emissiveFactor\":{}",
+        json_string(&material.name),
+        pbr,
+        json_vec3(material.emissive_color.min(Vec3::ONE))
+    );
+    if let Some(map) = &material.emissive_texture {
+        let _ = write!(
+            json,
+            ",\"emissiveTexture\":{{\"index\":{}}}",
+            texture_index(map)
+        );
+    }
+    match blend_mode {
+        BlendMode::Opaque => {}
+        BlendMode::Mask => {
+            let _ = write!(
+                json,
+                ",\"alphaMode\":\"MASK\",\"alphaCutoff\":{}",
+                ALPHA_CUTOFF
+            );
+        }
+        BlendMode::Blend => json.push_str(",\"alphaMode\":\"BLEND\""),
+    }
+    json.push('}');
+    json
+}
+
+fn json_array(items: &[String]) -> String {
+    format!("[{}]", items.join(","))
+}
+
+/// The extension through which glTF references a texture file of this
+/// kind, `None` for the PNG and JPEG images core glTF allows.
+fn image_extension(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "dds" => Some("MSFT_texture_dds"),
+        "ktx2" => Some("KHR_texture_basisu"),
+        _ => None,
+    }
+}
+
+/// JSON has no literal for NaN or the infinities, so values that would be
+/// written as numbers must be finite.
+fn check_finite(data: &ExportData) -> io::Result<()> {
+    let invalid = |what: String| {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} has non-finite values", what),
+        ))
+    };
+
+    for material in &data.materials {
+        let factors = [material.dissolve, material.shininess];
+        if !(material.diffuse_color.is_finite()
+            && material.emissive_color.is_finite()
+            && factors.iter().all(|factor| factor.is_finite()))
+        {
+            return invalid(format!("material {}", material.name));
+        }
+    }
+    for (i, mesh) in data.meshes.iter().enumerate() {
+        let vertices_finite = mesh.vertices.iter().all(|vertex| {
+            let (position, normal, tex_coords) =
+                (vertex.position, vertex.normal, vertex.tex_coords);
+            position.is_finite() && normal.is_finite() && tex_coords.is_finite()
+        });
+        let targets_finite = mesh.morph_targets.iter().all(|target| {
+            target
+                .positions
+                .iter()
+                .chain(&target.normals)
+                .all(|v| v.is_finite())
+        });
+        let weights_finite = mesh.morph_weights.iter().all(|weight| weight.is_finite());
+        if !(vertices_finite && targets_finite && weights_finite) {
+            return invalid(format!("mesh {}", i));
+        }
+    }
+    Ok(())
+}
+
+/// Writes `path` as a `.gltf` JSON file with its buffer in a `.bin` file of
+/// the same name. Fails without writing anything if `data` holds NaN or
+/// infinite values.
+pub fn write_gltf(data: &ExportData, path: &Path) -> io::Result<()> {
+    check_finite(data)?;
+    let dir = output_dir(path);
+    let bin_path = path.with_extension("bin");
+    let bin_name = bin_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    // Each image gets one texture, with the same index.
+    let mut images: Vec<(String, Option<&str>)> = Vec::new();
+    let mut image_indices: HashMap<String, usize> = HashMap::new();
+    let mut texture_index = |map: &TextureMap| -> usize {
+        let uri = encode_uri(&texture_reference(map, &dir));
+        *image_indices.entry(uri.clone()).or_insert_with(|| {
+            images.push((uri, image_extension(&map.path)));
+            images.len() - 1
+        })
+    };
+    let materials: Vec<String> = data
+        .materials
+        .iter()
+        .zip(&data.blend_modes)
+        .map(|(material, &blend_mode)| gltf_material(material, blend_mode, &mut texture_index))
+        .collect();
+
+    let mut buffer = GltfBuffer::default();
+    let mut meshes = Vec::new();
+    for mesh in &data.meshes {
+        let vertices = &mesh.vertices;
+        let positions: Vec<Vec3> = vertices.iter().map(|vertex| vertex.position).collect();
+        let normals: Vec<Vec3> = vertices.iter().map(|vertex| vertex.normal).collect();
+        // Textures are loaded flipped, glTF puts the UV origin at the top.
+        let tex_coords: Vec<[f32; 2]> = vertices
+            .iter()
+            .map(|vertex| {
+                let tex_coords = vertex.tex_coords;
+                [tex_coords.x, 1.0 - tex_coords.y]
+            })
+            .collect();
+
+        let position = buffer.add_vec3s(&positions, Some(ARRAY_BUFFER));
+        let normal = buffer.add_accessor(
+            &normals,
+            FLOAT,
+            normals.len(),
+            "VEC3",
+            Some(ARRAY_BUFFER),
+            "",
+        );
+        let tex_coord = buffer.add_accessor(
+            &tex_coords,
+            FLOAT,
+            tex_coords.len(),
+            "VEC2",
+            Some(ARRAY_BUFFER),
+            "",
+        );
+        let indices: &[GLuint] = &mesh.indices;
+        let indices = buffer.add_accessor(
+            indices,
+            UNSIGNED_INT,
+            indices.len(),
+            "SCALAR",
+            Some(ELEMENT_ARRAY_BUFFER),
+            "",
+        );
+
+        let mut primitive = format!(
+            "{{\"attributes\":{{\"POSITION\":{},\"NORMAL\":{},\"TEXCOORD_0\":{}}},\"indices\":{},\"material\":{}",
+            position,
+            normal,
+            tex_coord,
+            indices,
+            mesh.material
+        );
+        let mut weights = String::new();
+        if !mesh.morph_targets.is_empty() {
+            let targets: Vec<String> = mesh
+                .morph_targets
+                .iter()
+                .map(|target| {
+                    let mut json = format!(
+                        "{{\"POSITION\":{}",
+                        buffer.add_vec3s(&target.positions, None)
+                    );
+                    if !target.normals.is_empty() {
+                        let normals = &target.normals;
+                        let accessor =
+                            buffer.add_accessor(normals, FLOAT, normals.len(), "VEC3", None, "");
+                        let _ = write!(json, ",\"NORMAL\":{}", accessor);
+                    }
+                    json.push('}');
+                    json
+                })
+                .collect();
+            let _ = write!(primitive, ",\"targets\":{}", json_array(&targets));
+            let values: Vec<String> = mesh.morph_weights.iter().map(f32::to_string).collect();
+            weights = format!(",\"weights\":{}", json_array(&values));
+        }
+        primitive.push('}');
+        meshes.push(format!("{{\"primitives\":[{}]{}}}", primitive, weights));
+    }
+
+    let nodes: Vec<String> = (0..meshes.len())
+        .map(|i| format!("{{\"mesh\":{}}}", i))
+        .collect();
+    let node_indices: Vec<String> = (0..nodes.len()).map(|i| i.to_string()).collect();
+
+    // glTF forbids empty top-level arrays, so those are left out.
+    let mut json = String::from("{\"asset\":{\"version\":\"2.0\",\"generator\":\"learn_opengl\"}");
+    let mut array = |name: &str, items: &[String]| {
+        if !items.is_empty() {
+            let _ = write!(json, ",\"{}\":{}", name, json_array(items));
+        }
+    };
+    array(
+        "scenes",
+        &[format!("{{\"nodes\":{}}}", json_array(&node_indices))],
+    );
+    array("nodes", &nodes);
+    array("meshes", &meshes);
+    array("materials", &materials);
+    let textures: Vec<String> = images
+        .iter()
+        .enumerate()
+        .map(|(i, (_, extension))| match extension {
+            Some(extension) => format!(
+                "{{\"sampler\":0,\"extensions\":{{\"{}\":{{\"source\":{}}}}}}}",
+                extension, i
+            ),
+            None => format!("{{\"sampler\":0,\"source\":{}}}", i),
+        })
+        .collect();
+    array("textures", &textures);
+    let image_uris: Vec<String> = images
+        .iter()
+        .map(|(uri, _)| format!("{{\"uri\":{}}}", json_string(uri)))
+        .collect();
+    array("images", &image_uris);
+    // Without a PNG or JPEG fallback source, readers must understand the
+    // extension to get at the texture at all.
+    let mut extensions: Vec<String> = images
+        .iter()
+        .filter_map(|(_, extension)| extension.map(json_string))
+        .collect();
+    extensions.sort();
+    extensions.dedup();
+    array("extensionsUsed", &extensions);
+    array("extensionsRequired", &extensions);
+    if !images.is_empty() {
+        array(
+            "samplers",
+            &[format!(
+                "{{\"magFilter\":{},\"minFilter\":{}}}",
+                gl::LINEAR,
+                gl::LINEAR_MIPMAP_LINEAR
+            )],
+        );
+    }
+    array("accessors", &buffer.accessors);
+    array("bufferViews", &buffer.views);
+    array(
+        "buffers",
+        &[format!(
+            "{{\"byteLength\":{},\"uri\":{}}}",
+            buffer.data.len(),
+            json_string(&encode_uri(&bin_name))
+        )],
+    );
+    json.push_str(",\"scene\":0}");
+
+    fs::write(&bin_path, &buffer.data)?;
+    fs::write(path, json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::model::ModelData;
+
+    /// A quad and a triangle with different materials.
+    fn data() -> ExportData {
+        let vertex = |x: f32, y: f32| Vertex {
+            position: vec3(x, y, 0.5 * x),
+            normal: Vec3::Z,
+            tex_coords: vec2(x, y),
+        };
+        let mesh = |vertices: Vec<Vertex>, indices: Vec<GLuint>, material| MeshData {
+            vertices,
+            indices,
+            material,
+            skin: None,
+            morph_targets: Vec::new(),
+            morph_weights: Vec::new(),
+            node: None,
+        };
+        let material = |name: &str| MaterialData {
+            name: name.to_string(),
+            ..MaterialData::default()
+        };
+
+        ExportData {
+            materials: vec![material("paint"), material("glass")],
+            blend_modes: vec![BlendMode::Opaque; 2],
+            meshes: vec![
+                mesh(
+                    vec![
+                        vertex(0.0, 0.0),
+                        vertex(1.0, 0.0),
+                        vertex(0.0, 1.0),
+                        vertex(1.0, 1.0),
+                    ],
+                    vec![0, 1, 2, 2, 1, 3],
+                    0,
+                ),
+                mesh(
+                    vec![vertex(2.0, 0.0), vertex(3.0, 0.0), vertex(2.0, 1.0)],
+                    vec![0, 1, 2],
+                    1,
+                ),
+            ],
+        }
+    }
+
+    fn output_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("learn_opengl_export_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    /// Compares the triangles of each mesh by their corners' positions,
+    /// finding the mesh read back by its material's name since importers
+    /// need not keep the mesh order.
+    fn assert_same_meshes(expected: &ExportData, actual: &ModelData) {
+        let corners = |mesh: &MeshData| -> Vec<Vec3> {
+            mesh.indices
+                .iter()
+                .map(|&i| mesh.vertices[i as usize].position)
+                .collect()
+        };
+
+        assert_eq!(actual.meshes.len(), expected.meshes.len());
+        for expected_mesh in &expected.meshes {
+            let name = &expected.materials[expected_mesh.material].name;
+            let actual_mesh = actual
+                .meshes
+                .iter()
+                .find(|mesh| actual.materials[mesh.material].name == *name)
+                .unwrap_or_else(|| panic!("no mesh with material {}", name));
+            assert_eq!(actual_mesh.indices.len(), expected_mesh.indices.len());
+            assert_eq!(corners(actual_mesh), corners(expected_mesh));
+        }
+    }
+
+    #[test]
+    fn obj_round_trip() {
+        let data = data();
+        let path = output_path("round_trip.obj");
+        write_obj(&data, &path).unwrap();
+
+        let read = ModelData::from_obj(path.to_str().unwrap()).unwrap();
+        assert_same_meshes(&data, &read);
+    }
+
+    #[test]
+    fn gltf_round_trip() {
+        let data = data();
+        let path = output_path("round_trip.gltf");
+        write_gltf(&data, &path).unwrap();
+
+        let read = ModelData::from_gltf(path.to_str().unwrap(), &Default::default()).unwrap();
+        assert_same_meshes(&data, &read);
+    }
+
+    #[test]
+    fn gltf_rejects_non_finite_values() {
+        let path = output_path("non_finite.gltf");
+
+        let mut nan = data();
+        nan.meshes[1].vertices[2].position = vec3(f32::NAN, 0.0, 0.0);
+        assert!(write_gltf(&nan, &path).is_err());
+
+        let mut infinite = data();
+        infinite.materials[0].shininess = f32::INFINITY;
+        assert!(write_gltf(&infinite, &path).is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn compressed_textures_use_extensions() {
+        let mut data = data();
+        let map = |path: &str| TextureMap {
+            path: PathBuf::from(path),
+            offset: Vec3::ZERO,
+            scale: Vec3::ONE,
+            bump_multiplier: 1.0,
+        };
+        data.materials[0].diffuse_texture = Some(map("paint.dds"));
+        data.materials[0].emissive_texture = Some(map("paint_glow.png"));
+        data.materials[1].diffuse_texture = Some(map("glass.ktx2"));
+        let path = output_path("compressed.gltf");
+        write_gltf(&data, &path).unwrap();
+
+        let json = fs::read_to_string(&path).unwrap();
+        let extensions = "[\"KHR_texture_basisu\",\"MSFT_texture_dds\"]";
+        assert!(json.contains(&format!("\"extensionsUsed\":{}", extensions)));
+        assert!(json.contains(&format!("\"extensionsRequired\":{}", extensions)));
+        assert!(
+            json.contains("{\"sampler\":0,\"extensions\":{\"MSFT_texture_dds\":{\"source\":0}}}")
+        );
+        assert!(json.contains("{\"sampler\":0,\"source\":1}"));
+        assert!(
+            json.contains("{\"sampler\":0,\"extensions\":{\"KHR_texture_basisu\":{\"source\":2}}}")
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use glam::*;
+
+use super::bounds::{Aabb, BoundingSphere};
+
+/// The six clip planes of a projection, as `(normal, distance)` with normals
+/// pointing inwards so that points inside have `dot(normal, p) + d >= 0`.
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    planes: [Vec4; 6],
+}
+
+#[allow(dead_code)]
+impl Frustum {
+    /// Extracts the planes from a view-projection matrix (Gribb & Hartmann).
+    /// The planes are in whatever space the matrix transforms from, so
+    /// passing `projection * view * model` gives model-space planes.
+    pub fn from_matrix(matrix: &Mat4) -> Frustum {
+        let row = |i: usize| matrix.row(i);
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        let mut planes = [w + x, w - x, w + y, w - y, w + z, w - z];
+        for plane in &mut planes {
+            let length = plane.truncate().length();
+            if length > 0.0 {
+                *plane /= length;
+            }
+        }
+
+        Frustum { planes }
+    }
+
+    fn distance(plane: &Vec4, point: Vec3) -> f32 {
+        plane.truncate().dot(point) + plane.w
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| Frustum::distance(plane, point) >= 0.0)
+    }
+
+    /// Conservative: may accept spheres just outside a frustum corner.
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| Frustum::distance(plane, sphere.center) >= -sphere.radius)
+    }
+
+    /// Conservative like `intersects_sphere`: only boxes entirely behind one
+    /// plane are rejected.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        if aabb.is_empty() {
+            return false;
+        }
+
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane normal.
+            let normal = plane.truncate();
+            let positive = Vec3::select(normal.cmpge(Vec3::ZERO), aabb.max, aabb.min);
+            Frustum::distance(plane, positive) >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn culls_by_morphed_bounds() {
+        let projection = Mat4::perspective_rh_gl(1.0, 1.0, 0.1, 100.0);
+        let view = Mat4::look_at_rh(Vec3::ZERO, -Vec3::Z, Vec3::Y);
+        let frustum = Frustum::from_matrix(&(projection * view));
+
+        // A mesh behind the camera with a target pulling it in front.
+        let rest = Aabb::new(vec3(-1.0, -1.0, 2.0), vec3(1.0, 1.0, 4.0));
+        let target = Aabb::from_points([Vec3::ZERO, vec3(0.0, 0.0, -10.0)]);
+        assert!(!frustum.intersects_aabb(&rest));
+
+        let morphed = rest.displace(&target, 1.0);
+        assert_eq!(morphed.min.z, -8.0);
+        assert!(frustum.intersects_aabb(&morphed));
+        assert!(frustum.intersects_sphere(&BoundingSphere::new(
+            morphed.center(),
+            morphed.extents().length()
+        )));
+
+        // Negative weights push the other way, away from the view.
+        let pushed = rest.displace(&target, -1.0);
+        assert_eq!((pushed.min.z, pushed.max.z), (2.0, 14.0));
+        assert!(!frustum.intersects_aabb(&pushed));
+    }
+}
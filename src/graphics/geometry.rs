@@ -0,0 +1,170 @@
+use gl::types::*;
+use glam::*;
+use std::collections::HashMap;
+
+use super::bounds::Aabb;
+use super::model::Vertex;
+
+fn face_normal(a: Vec3, b: Vec3, c: Vec3) -> Vec3 {
+    (b - a).cross(c - a)
+}
+
+fn position_key(position: Vec3) -> [u32; 3] {
+    [
+        position.x.to_bits(),
+        position.y.to_bits(),
+        position.z.to_bits(),
+    ]
+}
+
+/// Area-weighted vertex normals. Vertices sharing a position are smoothed
+/// together, so UV seams do not show up as lighting seams.
+pub fn generate_smooth_normals(vertices: &mut [Vertex], indices: &[GLuint]) {
+    let mut accumulated: HashMap<[u32; 3], Vec3> = HashMap::new();
+
+    for triangle in indices.chunks_exact(3) {
+        let a = vertices[triangle[0] as usize].position;
+        let b = vertices[triangle[1] as usize].position;
+        let c = vertices[triangle[2] as usize].position;
+        let normal = face_normal(a, b, c);
+
+        for position in [a, b, c] {
+            *accumulated
+                .entry(position_key(position))
+                .or_insert(Vec3::ZERO) += normal;
+        }
+    }
+
+    for vertex in vertices.iter_mut() {
+        let normal = accumulated
+            .get(&position_key(vertex.position))
+            .copied()
+            .unwrap_or(Vec3::ZERO);
+        vertex.normal = normal.normalize_or_zero();
+    }
+}
+
+/// Unshares every triangle corner and gives it the face normal.
+pub fn generate_flat_normals(
+    vertices: &[Vertex],
+    indices: &[GLuint],
+) -> (Vec<Vertex>, Vec<GLuint>) {
+    let mut flat_vertices = Vec::with_capacity(indices.len());
+
+    for triangle in indices.chunks_exact(3) {
+        let corners = [
+            vertices[triangle[0] as usize],
+            vertices[triangle[1] as usize],
+            vertices[triangle[2] as usize],
+        ];
+        let normal = face_normal(
+            corners[0].position,
+            corners[1].position,
+            corners[2].position,
+        )
+        .normalize_or_zero();
+
+        for mut corner in corners {
+            corner.normal = normal;
+            flat_vertices.push(corner);
+        }
+    }
+
+    let flat_indices = (0..flat_vertices.len() as GLuint).collect();
+    (flat_vertices, flat_indices)
+}
+
+type Cell = [i32; 3];
+
+/// Vertex clustering on a grid of `resolution` cells along the longest side
+/// of the bounds. Each occupied cell keeps the vertex closest to the average
+/// of its vertices, and triangles that collapse are dropped.
+///
+/// Returns the original indices of the kept vertices and the new index
+/// buffer, which indexes into them.
+pub fn cluster_vertices(
+    vertices: &[Vertex],
+    indices: &[GLuint],
+    resolution: u32,
+) -> (Vec<GLuint>, Vec<GLuint>) {
+    let aabb = Aabb::from_points(indices.iter().map(|&i| vertices[i as usize].position));
+    let cell_size = aabb.size().max_element() / resolution.max(1) as f32;
+    if aabb.is_empty() || cell_size <= 0.0 {
+        return ((0..vertices.len() as GLuint).collect(), indices.to_vec());
+    }
+    let cell = |position: Vec3| -> Cell {
+        let cell = ((position - aabb.min) / cell_size).floor();
+        [cell.x as i32, cell.y as i32, cell.z as i32]
+    };
+
+    let mut used = vec![false; vertices.len()];
+    for &i in indices {
+        used[i as usize] = true;
+    }
+
+    let mut sums: HashMap<Cell, (Vec3, f32)> = HashMap::new();
+    for (vertex, _) in vertices.iter().zip(&used).filter(|(_, &used)| used) {
+        let sum = sums
+            .entry(cell(vertex.position))
+            .or_insert((Vec3::ZERO, 0.0));
+        sum.0 += vertex.position;
+        sum.1 += 1.0;
+    }
+
+    let mut closest: HashMap<Cell, (usize, f32)> = HashMap::new();
+    for (i, vertex) in vertices.iter().enumerate().filter(|&(i, _)| used[i]) {
+        let key = cell(vertex.position);
+        let (sum, count) = sums[&key];
+        let distance = vertex.position.distance_squared(sum / count);
+        let best = closest.entry(key).or_insert((i, distance));
+        if distance < best.1 {
+            *best = (i, distance);
+        }
+    }
+
+    // Keep the original vertex order.
+    let mut kept: Vec<GLuint> = closest.values().map(|&(i, _)| i as GLuint).collect();
+    kept.sort_unstable();
+    let new_index: HashMap<Cell, GLuint> = kept
+        .iter()
+        .enumerate()
+        .map(|(new, &old)| (cell(vertices[old as usize].position), new as GLuint))
+        .collect();
+
+    let mut clustered_indices = Vec::new();
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]]
+            .map(|i| new_index[&cell(vertices[i as usize].position)]);
+        if a != b && b != c && c != a {
+            clustered_indices.extend_from_slice(&[a, b, c]);
+        }
+    }
+
+    (kept, clustered_indices)
+}
+
+/// Clusters with the finest grid that gets the mesh down to at most
+/// `target_triangles`, see `cluster_vertices`.
+pub fn simplify_clustered(
+    vertices: &[Vertex],
+    indices: &[GLuint],
+    target_triangles: usize,
+) -> (Vec<GLuint>, Vec<GLuint>) {
+    if indices.len() / 3 <= target_triangles {
+        return ((0..vertices.len() as GLuint).collect(), indices.to_vec());
+    }
+
+    let mut best = cluster_vertices(vertices, indices, 1);
+    let (mut low, mut high) = (2, 1024);
+    while low <= high {
+        let resolution = (low + high) / 2;
+        let clustered = cluster_vertices(vertices, indices, resolution);
+        if clustered.1.len() / 3 <= target_triangles {
+            best = clustered;
+            low = resolution + 1;
+        } else {
+            high = resolution - 1;
+        }
+    }
+    best
+}
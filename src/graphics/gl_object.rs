@@ -0,0 +1,75 @@
+use gl::types::*;
+
+use super::leak_tracker::{self, GlObject};
+
+/// An owned buffer object, for geometry simple enough to need nothing
+/// more. Deleted on drop.
+pub struct Buffer {
+    id: GLuint,
+}
+
+#[allow(dead_code)]
+impl Buffer {
+    #[track_caller]
+    pub fn new() -> Buffer {
+        let mut id = 0;
+        unsafe { gl::GenBuffers(1, &mut id) };
+        leak_tracker::created(GlObject::Buffer, id);
+        Buffer { id }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    /// Binds the buffer to `target` and replaces its contents with `data`.
+    pub fn upload<T: Copy>(&self, target: GLenum, data: &[T], usage: GLenum) {
+        unsafe {
+            gl::BindBuffer(target, self.id);
+            gl::BufferData(
+                target,
+                std::mem::size_of_val(data) as GLsizeiptr,
+                data.as_ptr() as *const std::ffi::c_void,
+                usage,
+            );
+        }
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        leak_tracker::deleted(GlObject::Buffer, self.id);
+        unsafe { gl::DeleteBuffers(1, &self.id) };
+    }
+}
+
+/// An owned vertex array object. Deleted on drop.
+pub struct VertexArray {
+    id: GLuint,
+}
+
+#[allow(dead_code)]
+impl VertexArray {
+    #[track_caller]
+    pub fn new() -> VertexArray {
+        let mut id = 0;
+        unsafe { gl::GenVertexArrays(1, &mut id) };
+        leak_tracker::created(GlObject::VertexArray, id);
+        VertexArray { id }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn bind(&self) {
+        unsafe { gl::BindVertexArray(self.id) };
+    }
+}
+
+impl Drop for VertexArray {
+    fn drop(&mut self) {
+        leak_tracker::deleted(GlObject::VertexArray, self.id);
+        unsafe { gl::DeleteVertexArrays(1, &self.id) };
+    }
+}
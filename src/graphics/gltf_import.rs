@@ -0,0 +1,638 @@
+//! glTF 2.0 import into `ModelData`, including skins and animations.
+//!
+//! Static meshes have their node transforms baked into the vertices. Skinned
+//! meshes stay in bind space and are deformed by the skeleton's joints, and
+//! meshes below animated nodes become rigidly skinned to their node so the
+//! animation still moves them. Morph targets are kept per primitive, with
+//! their weights animated through the mesh's node.
+
+use gl::types::*;
+use glam::*;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use super::animation::{
+    AnimationClip, Channel, Interpolation, Joint, Keyframes, Node, Skeleton, Transform, MAX_JOINTS,
+};
+use super::geometry;
+use super::material::MaterialData;
+use super::model::{
+    ImportOptions, MeshData, ModelData, ModelError, MorphTarget, NormalMode, SkinWeights, Vertex,
+    MAX_MORPH_TARGETS,
+};
+use super::mtl::{self, TextureMap};
+
+/// Decodes `%XX` escapes in a relative URI.
+fn decode_uri(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn texture_map(
+    info: Option<gltf::texture::Info>,
+    base_dir: &Path,
+    search_paths: &[PathBuf],
+) -> Option<TextureMap> {
+    let info = info?;
+    match info.texture().source().source() {
+        gltf::image::Source::Uri { uri, .. } if !uri.starts_with("data:") => Some(TextureMap {
+            path: mtl::resolve_path(&decode_uri(uri), base_dir, search_paths),
+            offset: Vec3::ZERO,
+            scale: Vec3::ONE,
+            bump_multiplier: 1.0,
+        }),
+        _ => {
+            eprintln!(
+                "model load : embedded glTF images are not supported, skipping texture {}",
+                info.texture().index()
+            );
+            None
+        }
+    }
+}
+
+fn material_data(
+    material: &gltf::Material,
+    base_dir: &Path,
+    search_paths: &[PathBuf],
+) -> MaterialData {
+    let pbr = material.pbr_metallic_roughness();
+    let [r, g, b, a] = pbr.base_color_factor();
+
+    let dissolve = match material.alpha_mode() {
+        gltf::material::AlphaMode::Blend => a,
+        _ => 1.0,
+    };
+
+    // Rough approximation of the metallic-roughness model with Phong terms.
+    let roughness = pbr.roughness_factor();
+    let shininess = ((1.0 - roughness) * 128.0).max(1.0);
+
+    MaterialData {
+        name: material.name().unwrap_or("gltf material").to_owned(),
+        ambient_color: Vec3::new(r, g, b),
+        diffuse_color: Vec3::new(r, g, b),
+        specular_color: Vec3::splat(0.5 * (1.0 - roughness)),
+        emissive_color: Vec3::from(material.emissive_factor()),
+        diffuse_texture: texture_map(pbr.base_color_texture(), base_dir, search_paths),
+        emissive_texture: texture_map(material.emissive_texture(), base_dir, search_paths),
+        shininess,
+        dissolve,
+        ..Default::default()
+    }
+}
+
+/// Converts the node hierarchy, parents first. Returns the nodes and the
+/// mapping from glTF node index to skeleton node index.
+///
+/// Fails if the nodes do not form a forest, i.e. a node is the child of
+/// more than one node or part of a cycle.
+fn build_nodes(
+    document: &gltf::Document,
+) -> Result<(Vec<Node>, HashMap<usize, usize>), ModelError> {
+    let children: HashSet<usize> = document
+        .nodes()
+        .flat_map(|node| node.children().map(|child| child.index()))
+        .collect();
+
+    let mut nodes = Vec::new();
+    let mut mapping = HashMap::new();
+    // Nodes to visit with the skeleton index of their parent.
+    let mut stack: Vec<(gltf::Node, Option<usize>)> = document
+        .nodes()
+        .filter(|node| !children.contains(&node.index()))
+        .map(|node| (node, None))
+        .collect();
+    stack.reverse();
+
+    while let Some((node, parent)) = stack.pop() {
+        if mapping.insert(node.index(), nodes.len()).is_some() {
+            return Err(ModelError::InvalidNodeHierarchy { node: node.index() });
+        }
+        let (translation, rotation, scale) = node.transform().decomposed();
+        nodes.push(Node {
+            name: node
+                .name()
+                .map_or_else(|| format!("node {}", node.index()), str::to_owned),
+            parent,
+            rest: Transform {
+                translation: Vec3::from(translation),
+                rotation: Quat::from_array(rotation),
+                scale: Vec3::from(scale),
+            },
+        });
+        let parent = Some(nodes.len() - 1);
+        let mut children: Vec<(gltf::Node, Option<usize>)> =
+            node.children().map(|child| (child, parent)).collect();
+        children.reverse();
+        stack.extend(children);
+    }
+
+    // Nodes in a cycle are nobody's root and never reached.
+    if let Some(node) = document
+        .nodes()
+        .find(|node| !mapping.contains_key(&node.index()))
+    {
+        return Err(ModelError::InvalidNodeHierarchy { node: node.index() });
+    }
+
+    Ok((nodes, mapping))
+}
+
+/// Skeleton index of a glTF node, see `build_nodes`.
+fn skeleton_node(mapping: &HashMap<usize, usize>, node: &gltf::Node) -> Result<usize, ModelError> {
+    mapping
+        .get(&node.index())
+        .copied()
+        .ok_or(ModelError::InvalidNodeHierarchy { node: node.index() })
+}
+
+fn interpolation(interpolation: gltf::animation::Interpolation) -> Interpolation {
+    match interpolation {
+        gltf::animation::Interpolation::Step => Interpolation::Step,
+        gltf::animation::Interpolation::Linear => Interpolation::Linear,
+        gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+    }
+}
+
+fn read_animations(
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+    mapping: &HashMap<usize, usize>,
+) -> Result<Vec<AnimationClip>, ModelError> {
+    let mut clips = Vec::new();
+
+    for animation in document.animations() {
+        let mut channels = Vec::new();
+        let mut duration: f32 = 0.0;
+
+        for channel in animation.channels() {
+            let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+            let times: Vec<f32> = match reader.read_inputs() {
+                Some(inputs) => inputs.collect(),
+                None => continue,
+            };
+            let keyframes = match reader.read_outputs() {
+                Some(gltf::animation::util::ReadOutputs::Translations(values)) => {
+                    Keyframes::Translation(values.map(Vec3::from).collect())
+                }
+                Some(gltf::animation::util::ReadOutputs::Rotations(values)) => {
+                    Keyframes::Rotation(values.into_f32().map(Quat::from_array).collect())
+                }
+                Some(gltf::animation::util::ReadOutputs::Scales(values)) => {
+                    Keyframes::Scale(values.map(Vec3::from).collect())
+                }
+                Some(gltf::animation::util::ReadOutputs::MorphTargetWeights(values)) => {
+                    Keyframes::Weights(values.into_f32().collect())
+                }
+                None => continue,
+            };
+
+            let imported = Channel {
+                node: skeleton_node(mapping, &channel.target().node())?,
+                interpolation: interpolation(channel.sampler().interpolation()),
+                times,
+                keyframes,
+            };
+            if !imported.keys_match() {
+                eprintln!(
+                    "model load : {}: skipping channel {} with a mismatched number of keys",
+                    animation.name().unwrap_or("animation"),
+                    channel.index()
+                );
+                continue;
+            }
+
+            duration = duration.max(imported.times.last().copied().unwrap_or(0.0));
+            channels.push(imported);
+        }
+
+        clips.push(AnimationClip {
+            name: animation
+                .name()
+                .map_or_else(|| format!("animation {}", animation.index()), str::to_owned),
+            duration,
+            channels,
+        });
+    }
+
+    Ok(clips)
+}
+
+/// Vertices, indices and skin weights of one triangle primitive. `skin_joints`
+/// is the first skeleton joint of the skin and its number of joints.
+fn read_primitive(
+    name: &str,
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+    skin_joints: Option<(u16, usize)>,
+) -> Result<(Vec<Vertex>, Vec<GLuint>, Option<Vec<SkinWeights>>), ModelError> {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+    let positions: Vec<Vec3> = match reader.read_positions() {
+        Some(positions) => positions.map(Vec3::from).collect(),
+        None => {
+            return Err(ModelError::MismatchedAttributes {
+                mesh: name.to_owned(),
+                attribute: "positions",
+            })
+        }
+    };
+    let vertex_count = positions.len();
+
+    let normals: Option<Vec<Vec3>> = reader
+        .read_normals()
+        .map(|normals| normals.map(Vec3::from).collect());
+    // glTF puts the UV origin at the top left, textures are loaded flipped.
+    let tex_coords: Option<Vec<Vec2>> = reader
+        .read_tex_coords(0)
+        .map(|uvs| uvs.into_f32().map(|[u, v]| Vec2::new(u, 1.0 - v)).collect());
+
+    if normals
+        .as_ref()
+        .map_or(false, |normals| normals.len() != vertex_count)
+    {
+        return Err(ModelError::MismatchedAttributes {
+            mesh: name.to_owned(),
+            attribute: "normals",
+        });
+    }
+    if tex_coords
+        .as_ref()
+        .map_or(false, |tex_coords| tex_coords.len() != vertex_count)
+    {
+        return Err(ModelError::MismatchedAttributes {
+            mesh: name.to_owned(),
+            attribute: "texcoords",
+        });
+    }
+
+    let vertices = (0..vertex_count)
+        .map(|i| Vertex {
+            position: positions[i],
+            normal: normals.as_ref().map_or(Vec3::ZERO, |normals| normals[i]),
+            tex_coords: tex_coords
+                .as_ref()
+                .map_or(Vec2::ZERO, |tex_coords| tex_coords[i]),
+        })
+        .collect();
+
+    let indices: Vec<GLuint> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..vertex_count as GLuint).collect(),
+    };
+    if indices.len() % 3 != 0 {
+        return Err(ModelError::IncompleteTriangle {
+            mesh: name.to_owned(),
+        });
+    }
+    if let Some(&index) = indices.iter().find(|&&i| i as usize >= vertex_count) {
+        return Err(ModelError::IndexOutOfBounds {
+            mesh: name.to_owned(),
+            index,
+            vertex_count,
+        });
+    }
+
+    let skin = match skin_joints {
+        Some((offset, joint_count)) => {
+            let joints: Vec<[u16; 4]> = match reader.read_joints(0) {
+                Some(joints) => joints.into_u16().collect(),
+                None => vec![[0; 4]; vertex_count],
+            };
+            let weights: Vec<[f32; 4]> = match reader.read_weights(0) {
+                Some(weights) => weights.into_f32().collect(),
+                None => vec![[1.0, 0.0, 0.0, 0.0]; vertex_count],
+            };
+            if joints.len() != vertex_count || weights.len() != vertex_count {
+                return Err(ModelError::MismatchedAttributes {
+                    mesh: name.to_owned(),
+                    attribute: "skin weights",
+                });
+            }
+            if let Some(&joint) = joints
+                .iter()
+                .flatten()
+                .find(|&&joint| joint as usize >= joint_count)
+            {
+                return Err(ModelError::JointOutOfBounds {
+                    mesh: name.to_owned(),
+                    joint,
+                    joint_count,
+                });
+            }
+            Some(
+                joints
+                    .iter()
+                    .zip(&weights)
+                    .map(|(joints, weights)| SkinWeights {
+                        joints: joints.map(|joint| joint + offset),
+                        weights: *weights,
+                    })
+                    .collect(),
+            )
+        }
+        None => None,
+    };
+
+    Ok((vertices, indices, skin))
+}
+
+/// Position and normal displacements of each morph target of a primitive.
+fn read_morph_targets(
+    name: &str,
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+    vertex_count: usize,
+) -> Result<Vec<MorphTarget>, ModelError> {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+    let mut targets = Vec::new();
+    for (positions, normals, _) in reader.read_morph_targets() {
+        let target = MorphTarget {
+            positions: positions.map_or_else(
+                || vec![Vec3::ZERO; vertex_count],
+                |positions| positions.map(Vec3::from).collect(),
+            ),
+            normals: normals.map_or_else(Vec::new, |normals| normals.map(Vec3::from).collect()),
+        };
+        if target.positions.len() != vertex_count
+            || !(target.normals.is_empty() || target.normals.len() == vertex_count)
+        {
+            return Err(ModelError::MismatchedAttributes {
+                mesh: name.to_owned(),
+                attribute: "morph targets",
+            });
+        }
+        targets.push(target);
+    }
+
+    if targets.len() > MAX_MORPH_TARGETS {
+        return Err(ModelError::TooManyMorphTargets {
+            mesh: name.to_owned(),
+            count: targets.len(),
+            max: MAX_MORPH_TARGETS,
+        });
+    }
+    Ok(targets)
+}
+
+pub fn load(path: &str, options: &ImportOptions) -> Result<ModelData, ModelError> {
+    let base_dir = match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let gltf = gltf::Gltf::open(path).map_err(ModelError::Gltf)?;
+    let document = &gltf.document;
+    let buffers = gltf::import_buffers(document, Some(&base_dir), gltf.blob.clone())
+        .map_err(ModelError::Gltf)?;
+
+    let mut materials: Vec<MaterialData> = document
+        .materials()
+        .map(|material| material_data(&material, &base_dir, &options.search_paths))
+        .collect();
+    let mut default_material = None;
+
+    let (nodes, mapping) = build_nodes(document)?;
+    let mut skeleton = Skeleton {
+        nodes,
+        joints: Vec::new(),
+    };
+    let rest_globals = skeleton.global_transforms(&skeleton.rest_pose());
+
+    let animations = read_animations(document, &buffers, &mapping)?;
+
+    // Nodes that move, directly or through an ancestor.
+    let mut animated: HashSet<usize> = animations
+        .iter()
+        .flat_map(|clip| clip.channels.iter().map(|channel| channel.node))
+        .collect();
+    for (i, node) in skeleton.nodes.iter().enumerate() {
+        if node
+            .parent
+            .map_or(false, |parent| animated.contains(&parent))
+        {
+            animated.insert(i);
+        }
+    }
+
+    let mut skin_offsets = HashMap::new();
+    for skin in document.skins() {
+        let joint_count = skin.joints().count();
+        if joint_count > MAX_JOINTS {
+            return Err(ModelError::TooManyJoints {
+                count: joint_count,
+                max: MAX_JOINTS,
+            });
+        }
+        skin_offsets.insert(skin.index(), skeleton.joints.len());
+        let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+        let mut inverse_binds = reader
+            .read_inverse_bind_matrices()
+            .map(|matrices| {
+                matrices
+                    .map(|m| Mat4::from_cols_array_2d(&m))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default()
+            .into_iter();
+        for joint in skin.joints() {
+            skeleton.joints.push(Joint {
+                node: skeleton_node(&mapping, &joint)?,
+                inverse_bind: inverse_binds.next().unwrap_or(Mat4::IDENTITY),
+            });
+        }
+    }
+
+    let scene_nodes: Vec<gltf::Node> = match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => {
+            // A node listed both as a root and below another root is
+            // still visited once.
+            let mut nodes = Vec::new();
+            let mut visited = HashSet::new();
+            let mut stack: Vec<gltf::Node> = scene.nodes().collect();
+            while let Some(node) = stack.pop() {
+                if !visited.insert(node.index()) {
+                    continue;
+                }
+                stack.extend(node.children());
+                nodes.push(node);
+            }
+            nodes
+        }
+        None => document.nodes().collect(),
+    };
+
+    let mut meshes = Vec::new();
+
+    for node in scene_nodes {
+        let mesh = match node.mesh() {
+            Some(mesh) => mesh,
+            None => continue,
+        };
+        let node_index = skeleton_node(&mapping, &node)?;
+
+        // Where the vertices go: the skin's joints, a joint for the node
+        // itself, or baked into model space.
+        let (joint_offset, bake) = if let Some(skin) = node.skin() {
+            (Some(skin_offsets[&skin.index()]), None)
+        } else if animated.contains(&node_index) {
+            skeleton.joints.push(Joint {
+                node: node_index,
+                inverse_bind: Mat4::IDENTITY,
+            });
+            (Some(skeleton.joints.len() - 1), None)
+        } else {
+            (None, Some(rest_globals[node_index]))
+        };
+
+        // Vertices refer to joints of the whole model, which must still fit
+        // the 16-bit attributes.
+        if skeleton.joints.len() > MAX_JOINTS {
+            return Err(ModelError::TooManyJoints {
+                count: skeleton.joints.len(),
+                max: MAX_JOINTS,
+            });
+        }
+        let joint_offset = joint_offset.map(|offset| offset as u16);
+        // The skin's first joint and joint count, rigid joints replace any
+        // skin the primitives have below.
+        let skin_joints = node
+            .skin()
+            .zip(joint_offset)
+            .map(|(skin, offset)| (offset, skin.joints().count()));
+
+        for primitive in mesh.primitives() {
+            let name = mesh
+                .name()
+                .map_or_else(|| format!("mesh {}", mesh.index()), str::to_owned);
+
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                eprintln!("model load : {}: skipping non-triangle primitive", name);
+                continue;
+            }
+
+            let (mut vertices, mut indices, mut skin) =
+                read_primitive(&name, &primitive, &buffers, skin_joints)?;
+            if indices.is_empty() {
+                continue;
+            }
+            let mut morph_targets =
+                read_morph_targets(&name, &primitive, &buffers, vertices.len())?;
+
+            // Rigid joints move the whole primitive, so every vertex follows
+            // it fully.
+            if let (true, Some(offset)) = (node.skin().is_none(), joint_offset) {
+                skin = Some(vec![
+                    SkinWeights {
+                        joints: [offset, 0, 0, 0],
+                        weights: [1.0, 0.0, 0.0, 0.0]
+                    };
+                    vertices.len()
+                ]);
+            }
+
+            if let Some(transform) = bake {
+                let normal_matrix = Mat3::from_mat4(transform).inverse().transpose();
+                for vertex in &mut vertices {
+                    vertex.position = transform.transform_point3(vertex.position);
+                    vertex.normal = (normal_matrix * vertex.normal).normalize_or_zero();
+                }
+                // Deltas are directions, so only the linear part applies.
+                let linear = Mat3::from_mat4(transform);
+                for target in &mut morph_targets {
+                    for position in &mut target.positions {
+                        *position = linear * *position;
+                    }
+                    for normal in &mut target.normals {
+                        *normal = normal_matrix * *normal;
+                    }
+                }
+            }
+
+            if primitive.get(&gltf::Semantic::Normals).is_none() {
+                match options.normals {
+                    NormalMode::Smooth => {
+                        geometry::generate_smooth_normals(&mut vertices, &indices)
+                    }
+                    NormalMode::Flat => {
+                        // Flat shading gives every corner its own vertex.
+                        skin = skin.map(|skin| indices.iter().map(|&i| skin[i as usize]).collect());
+                        for target in &mut morph_targets {
+                            target.positions = indices
+                                .iter()
+                                .map(|&i| target.positions[i as usize])
+                                .collect();
+                            // Flat normals are not morphed.
+                            target.normals.clear();
+                        }
+                        let (flat_vertices, flat_indices) =
+                            geometry::generate_flat_normals(&vertices, &indices);
+                        vertices = flat_vertices;
+                        indices = flat_indices;
+                    }
+                }
+            }
+
+            let material = match primitive.material().index() {
+                Some(material) => material,
+                None => *default_material.get_or_insert_with(|| {
+                    materials.push(MaterialData::default());
+                    materials.len() - 1
+                }),
+            };
+
+            let morph_weights = node
+                .weights()
+                .or_else(|| mesh.weights())
+                .map_or_else(Vec::new, <[f32]>::to_vec);
+
+            meshes.push(MeshData {
+                vertices,
+                indices,
+                material,
+                skin,
+                morph_targets,
+                morph_weights,
+                node: Some(node_index),
+            });
+        }
+    }
+
+    let skeleton = if skeleton.joints.is_empty() {
+        None
+    } else {
+        Some(skeleton)
+    };
+
+    Ok(ModelData {
+        materials,
+        meshes,
+        skeleton,
+        animations,
+        lods: Vec::new(),
+    })
+}
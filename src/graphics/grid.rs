@@ -0,0 +1,138 @@
+use gl::types::*;
+use glam::*;
+use std::rc::Rc;
+
+use super::camera::Camera;
+use super::debug_draw::{DebugDraw, DepthMode};
+use super::leak_tracker::{self, GlObject};
+use super::shader::Shader;
+
+/// A ground plane grid at y = 0 reaching to the horizon, drawn as one
+/// full-screen triangle that casts a ray per fragment.
+///
+/// Lines stay about a pixel wide at any distance, minor lines fade out
+/// before they get dense enough to alias, and the whole grid fades with
+/// distance from the camera. The world X and Z axes are drawn in red and
+/// blue.
+pub struct InfiniteGrid {
+    /// `grid_vert.glsl` / `grid_frag.glsl`
+    shader: Rc<Shader>,
+    /// Core profiles need a bound VAO even for attribute-less draws.
+    vao: GLuint,
+    /// World units between minor lines, every tenth line is a major one.
+    pub cell_size: f32,
+    /// Distance from the camera at which the grid is gone, keep it within
+    /// the far plane.
+    pub fade_distance: f32,
+    pub color: Vec4,
+}
+
+#[allow(dead_code)]
+impl InfiniteGrid {
+    pub fn new(shader: Rc<Shader>) -> InfiniteGrid {
+        let mut vao = 0;
+        unsafe { gl::GenVertexArrays(1, &mut vao) };
+        leak_tracker::created(GlObject::VertexArray, vao);
+
+        InfiniteGrid {
+            shader,
+            vao,
+            cell_size: 1.0,
+            fade_distance: 50.0,
+            color: Vec4::new(0.6, 0.6, 0.6, 0.8),
+        }
+    }
+
+    /// Blends the grid over what has been drawn, depth tested against it
+    /// but without writing depth. Draw it after opaque geometry and before
+    /// transparent geometry, see `RenderQueue::draw_opaque`.
+    pub fn draw(&self, view: &Mat4, projection: &Mat4, camera_position: Vec3) {
+        let view_projection = *projection * *view;
+
+        self.shader.use_shader();
+        self.shader.set_mat4v("view_projection", &view_projection);
+        self.shader
+            .set_mat4v("inverse_view_projection", &view_projection.inverse());
+        self.shader.set_vec3v("camera_position", &camera_position);
+        self.shader.set_f32("cell_size", self.cell_size);
+        self.shader.set_f32("fade_distance", self.fade_distance);
+        self.shader.set_vec4v("grid_color", &self.color);
+
+        unsafe {
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            gl::DepthMask(gl::FALSE);
+
+            gl::BindVertexArray(self.vao);
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+            gl::BindVertexArray(0);
+
+            gl::DepthMask(gl::TRUE);
+            gl::Disable(gl::BLEND);
+        }
+    }
+}
+
+impl Drop for InfiniteGrid {
+    fn drop(&mut self) {
+        leak_tracker::deleted(GlObject::VertexArray, self.vao);
+        unsafe { gl::DeleteVertexArrays(1, &self.vao) };
+    }
+}
+
+/// World axes as seen from a camera's orientation, drawn in a square in
+/// the bottom left corner of the viewport.
+#[derive(Clone, Copy, Debug)]
+pub struct AxesGizmo {
+    /// Side of the square, in pixels.
+    pub size: i32,
+    /// Distance from the viewport edges, in pixels.
+    pub margin: i32,
+}
+
+impl Default for AxesGizmo {
+    fn default() -> AxesGizmo {
+        AxesGizmo {
+            size: 100,
+            margin: 10,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl AxesGizmo {
+    /// Queues the gizmo into `debug_draw`, to be drawn by its next flush
+    /// with `view_projection`. Takes the current viewport as the one that
+    /// flush draws into.
+    pub fn draw(&self, debug_draw: &mut DebugDraw, camera: &Camera, view_projection: &Mat4) {
+        let (yaw, pitch) = (camera.yaw().to_radians(), camera.pitch().to_radians());
+        let direction = Vec3::new(
+            pitch.cos() * yaw.cos(),
+            pitch.sin(),
+            pitch.cos() * yaw.sin(),
+        );
+        let view = Mat4::look_at_rh(-direction * 2.0, Vec3::ZERO, Vec3::Y);
+        let projection = Mat4::orthographic_rh_gl(-1.3, 1.3, -1.3, 1.3, 0.1, 4.0);
+
+        let mut viewport = [0; 4];
+        unsafe { gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr()) };
+        let (width, height) = (viewport[2].max(1) as f32, viewport[3].max(1) as f32);
+        let (size, margin) = (self.size as f32, self.margin as f32);
+        // Squeezes the gizmo's normalized device space into the corner
+        // square. Depth stays close to the near plane, where mapping back
+        // to world space is precise; overlay lines ignore it anyway.
+        let corner = Mat4::from_translation(Vec3::new(
+            -1.0 + (2.0 * margin + size) / width,
+            -1.0 + (2.0 * margin + size) / height,
+            -0.5,
+        )) * Mat4::from_scale(Vec3::new(size / width, size / height, 0.25));
+
+        let depth_mode = debug_draw.depth_mode();
+        let transform = debug_draw.transform();
+        debug_draw.set_depth_mode(DepthMode::Overlay);
+        debug_draw.set_transform(view_projection.inverse() * corner * projection * view);
+        debug_draw.axes(&Mat4::IDENTITY, 1.0);
+        debug_draw.set_transform(transform);
+        debug_draw.set_depth_mode(depth_mode);
+    }
+}
@@ -0,0 +1,156 @@
+use gl::types::*;
+use glam::*;
+use std::borrow::Cow;
+
+use super::leak_tracker::{self, GlObject};
+
+/// Attribute location of the first column of the per-instance model matrix,
+/// which takes this and the next three locations.
+pub const INSTANCE_TRANSFORM_LOCATION: GLuint = 3;
+/// Attribute location of the optional per-instance color.
+pub const INSTANCE_COLOR_LOCATION: GLuint = 7;
+
+/// Per-instance transforms and colors for `Model::draw_instanced`.
+///
+/// All transforms are stored first, followed by the colors if there are
+/// any, in a single vertex buffer.
+pub struct InstanceBuffer {
+    vbo: GLuint,
+    count: usize,
+    has_colors: bool,
+}
+
+#[allow(dead_code)]
+impl InstanceBuffer {
+    /// `colors`, if given, has one entry per transform. Instances without
+    /// one are white and extra colors are ignored.
+    pub fn new(transforms: &[Mat4], colors: Option<&[Vec4]>) -> InstanceBuffer {
+        let mut vbo = 0;
+        unsafe { gl::GenBuffers(1, &mut vbo) };
+        leak_tracker::created(GlObject::Buffer, vbo);
+
+        let mut buffer = InstanceBuffer {
+            vbo,
+            count: 0,
+            has_colors: false,
+        };
+        buffer.update(transforms, colors);
+        buffer
+    }
+
+    /// Replaces the contents, reallocating the buffer storage.
+    pub fn update(&mut self, transforms: &[Mat4], colors: Option<&[Vec4]>) {
+        let colors = colors.map(|colors| {
+            if colors.len() == transforms.len() {
+                Cow::Borrowed(colors)
+            } else {
+                colors
+                    .iter()
+                    .copied()
+                    .chain(std::iter::repeat(Vec4::ONE))
+                    .take(transforms.len())
+                    .collect()
+            }
+        });
+        let colors = colors.as_deref();
+
+        let transforms_size = std::mem::size_of_val(transforms);
+        let colors_size = colors.map_or(0, std::mem::size_of_val);
+
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                (transforms_size + colors_size) as GLsizeiptr,
+                std::ptr::null(),
+                gl::DYNAMIC_DRAW,
+            );
+            gl::BufferSubData(
+                gl::ARRAY_BUFFER,
+                0,
+                transforms_size as GLsizeiptr,
+                transforms.as_ptr() as *const std::ffi::c_void,
+            );
+            if let Some(colors) = colors {
+                gl::BufferSubData(
+                    gl::ARRAY_BUFFER,
+                    transforms_size as GLintptr,
+                    colors_size as GLsizeiptr,
+                    colors.as_ptr() as *const std::ffi::c_void,
+                );
+            }
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+
+        self.count = transforms.len();
+        self.has_colors = colors.is_some();
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Points the instance attributes of the currently bound VAO at this
+    /// buffer.
+    pub(super) fn bind_attributes(&self) {
+        let mat4_size = std::mem::size_of::<Mat4>();
+        let vec4_size = std::mem::size_of::<Vec4>();
+
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+
+            for column in 0..4 {
+                let location = INSTANCE_TRANSFORM_LOCATION + column;
+                gl::EnableVertexAttribArray(location);
+                gl::VertexAttribPointer(
+                    location,
+                    4,
+                    gl::FLOAT,
+                    gl::FALSE,
+                    mat4_size as GLsizei,
+                    (column as usize * vec4_size) as *const GLvoid,
+                );
+                gl::VertexAttribDivisor(location, 1);
+            }
+
+            if self.has_colors {
+                gl::EnableVertexAttribArray(INSTANCE_COLOR_LOCATION);
+                gl::VertexAttribPointer(
+                    INSTANCE_COLOR_LOCATION,
+                    4,
+                    gl::FLOAT,
+                    gl::FALSE,
+                    vec4_size as GLsizei,
+                    (self.count * mat4_size) as *const GLvoid,
+                );
+                gl::VertexAttribDivisor(INSTANCE_COLOR_LOCATION, 1);
+            } else {
+                gl::DisableVertexAttribArray(INSTANCE_COLOR_LOCATION);
+                gl::VertexAttrib4f(INSTANCE_COLOR_LOCATION, 1.0, 1.0, 1.0, 1.0);
+            }
+
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+    }
+
+    /// Disables the instance attributes of the currently bound VAO again so
+    /// it can be used for regular draws.
+    pub(super) fn unbind_attributes() {
+        unsafe {
+            for location in INSTANCE_TRANSFORM_LOCATION..=INSTANCE_COLOR_LOCATION {
+                gl::DisableVertexAttribArray(location);
+            }
+        }
+    }
+}
+
+impl Drop for InstanceBuffer {
+    fn drop(&mut self) {
+        leak_tracker::deleted(GlObject::Buffer, self.vbo);
+        unsafe { gl::DeleteBuffers(1, &self.vbo) };
+    }
+}
@@ -0,0 +1,82 @@
+//! Bookkeeping of the GL objects owned by the graphics types, so that any
+//! still alive when the context is about to go away can be reported.
+//!
+//! Owners call `created` after generating an object and `deleted` before
+//! deleting it. Both compile to nothing in release builds, where
+//! `report_leaks` never finds anything.
+
+use gl::types::*;
+
+#[cfg(debug_assertions)]
+use std::cell::RefCell;
+#[cfg(debug_assertions)]
+use std::collections::HashMap;
+#[cfg(debug_assertions)]
+use std::panic::Location;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum GlObject {
+    Buffer,
+    VertexArray,
+    Texture,
+    Sampler,
+    Framebuffer,
+    Renderbuffer,
+    Program,
+}
+
+// GL objects belong to the context, which is only ever current on one
+// thread.
+#[cfg(debug_assertions)]
+thread_local! {
+    static LIVE: RefCell<HashMap<(GlObject, GLuint), &'static Location<'static>>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Records `id` as alive, along with the caller as the place it was
+/// created. Zero, which no object is named, is ignored.
+#[track_caller]
+#[allow(unused_variables)]
+pub fn created(kind: GlObject, id: GLuint) {
+    #[cfg(debug_assertions)]
+    if id != 0 {
+        let location = Location::caller();
+        LIVE.with(|live| live.borrow_mut().insert((kind, id), location));
+    }
+}
+
+#[allow(unused_variables)]
+pub fn deleted(kind: GlObject, id: GLuint) {
+    #[cfg(debug_assertions)]
+    LIVE.with(|live| live.borrow_mut().remove(&(kind, id)));
+}
+
+/// Number of objects created and not deleted yet.
+#[cfg(debug_assertions)]
+pub fn live_count() -> usize {
+    LIVE.with(|live| live.borrow().len())
+}
+
+#[cfg(not(debug_assertions))]
+pub fn live_count() -> usize {
+    0
+}
+
+/// Prints every object still alive and where it was created, and returns
+/// how many there are. Call it after dropping everything that owns GL
+/// objects, while the context is still current.
+pub fn report_leaks() -> usize {
+    #[cfg(debug_assertions)]
+    LIVE.with(|live| {
+        let mut leaks: Vec<_> = live
+            .borrow()
+            .iter()
+            .map(|(&key, &location)| (key, location))
+            .collect();
+        leaks.sort_by_key(|&((kind, id), _)| (kind, id));
+        for ((kind, id), location) in &leaks {
+            eprintln!("gl leak : {:?} {} created at {}", kind, id, location);
+        }
+    });
+    live_count()
+}
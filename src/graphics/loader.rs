@@ -0,0 +1,321 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use super::assets::AssetManager;
+use super::mesh_cache::MeshCache;
+use super::model::{ImportOptions, Model, ModelData};
+use super::texture::{ImageData, Texture, TextureOptions};
+
+pub type LoadId = u64;
+
+enum Task {
+    ParseModel {
+        id: LoadId,
+        path: PathBuf,
+        options: ImportOptions,
+        cache: Option<MeshCache>,
+    },
+    DecodeImage {
+        id: LoadId,
+        path: PathBuf,
+        options: TextureOptions,
+    },
+}
+
+enum Output {
+    Model {
+        id: LoadId,
+        result: Result<ModelData, String>,
+    },
+    Image {
+        id: LoadId,
+        path: PathBuf,
+        options: TextureOptions,
+        result: Result<ImageData, String>,
+    },
+}
+
+#[allow(dead_code)]
+pub enum LoadEvent {
+    /// Geometry is uploaded; textures that are not loaded yet are replaced
+    /// by a placeholder until their `TextureReady` event.
+    ModelReady {
+        id: LoadId,
+        model: Model,
+    },
+    /// A texture for the material slots that use `path` with `options`,
+    /// see `Model::set_texture`.
+    TextureReady {
+        id: LoadId,
+        path: PathBuf,
+        options: TextureOptions,
+        texture: Rc<Texture>,
+    },
+    Finished {
+        id: LoadId,
+    },
+    Failed {
+        id: LoadId,
+        error: String,
+    },
+}
+
+#[allow(dead_code)]
+pub struct LoadProgress {
+    pub id: LoadId,
+    pub path: PathBuf,
+    pub done: usize,
+    pub total: usize,
+}
+
+struct Job {
+    path: PathBuf,
+    done: usize,
+    total: usize,
+    batch_meshes: bool,
+}
+
+/// Loads models in the background. OBJ parsing and image decoding, or
+/// reading DDS/KTX2 files, run on worker threads; `poll` performs the GL uploads on the calling (render)
+/// thread.
+pub struct AsyncLoader {
+    tasks: Sender<Task>,
+    outputs: Receiver<Output>,
+    jobs: HashMap<LoadId, Job>,
+    next_id: LoadId,
+    placeholder: Rc<Texture>,
+    mesh_cache: Option<MeshCache>,
+}
+
+fn run_task(task: Task) -> Output {
+    match task {
+        Task::ParseModel {
+            id,
+            path,
+            options,
+            cache,
+        } => {
+            let result = match (path.to_str(), &cache) {
+                (Some(path), Some(cache)) => {
+                    ModelData::from_path_cached(path, &options, cache).map_err(|e| e.to_string())
+                }
+                (Some(path), None) => ModelData::from_path(path, &options).map_err(|e| e.to_string()),
+                (None, _) => Err("Model path is not valid UTF-8".to_owned()),
+            };
+            Output::Model { id, result }
+        }
+        Task::DecodeImage { id, path, options } => {
+            let result = ImageData::read(&path)
+                .map_err(|e| e.to_string())
+                .map(|data| match data {
+                    ImageData::Decoded(img) if options.flip => ImageData::Decoded(img.flipv()),
+                    data => data,
+                });
+            Output::Image {
+                id,
+                path,
+                options,
+                result,
+            }
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl AsyncLoader {
+    pub fn new(worker_count: usize) -> AsyncLoader {
+        let (tasks, task_receiver) = channel::<Task>();
+        let (output_sender, outputs) = channel();
+        let task_receiver = Arc::new(Mutex::new(task_receiver));
+
+        for _ in 0..worker_count.max(1) {
+            let task_receiver = task_receiver.clone();
+            let output_sender = output_sender.clone();
+            thread::spawn(move || loop {
+                let task = match task_receiver.lock().map(|receiver| receiver.recv()) {
+                    Ok(Ok(task)) => task,
+                    // The loader was dropped, nothing left to do.
+                    _ => return,
+                };
+                if output_sender.send(run_task(task)).is_err() {
+                    return;
+                }
+            });
+        }
+
+        AsyncLoader {
+            tasks,
+            outputs,
+            jobs: HashMap::new(),
+            next_id: 0,
+            placeholder: Rc::new(Texture::solid_color([255, 255, 255, 255])),
+            mesh_cache: None,
+        }
+    }
+
+    /// A loader that keeps imported models in `cache`, see
+    /// `ModelData::from_path_cached`.
+    pub fn with_mesh_cache(worker_count: usize, cache: MeshCache) -> AsyncLoader {
+        AsyncLoader {
+            mesh_cache: Some(cache),
+            ..AsyncLoader::new(worker_count)
+        }
+    }
+
+    pub fn load_model<P: AsRef<Path>>(&mut self, path: P) -> LoadId {
+        self.load_model_with_options(path, ImportOptions::default())
+    }
+
+    pub fn load_model_with_options<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        options: ImportOptions,
+    ) -> LoadId {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let path = path.as_ref().to_path_buf();
+        self.jobs.insert(
+            id,
+            Job {
+                path: path.clone(),
+                done: 0,
+                total: 1,
+                batch_meshes: options.batch_meshes,
+            },
+        );
+        let _ = self.tasks.send(Task::ParseModel {
+            id,
+            path,
+            options,
+            cache: self.mesh_cache.clone(),
+        });
+        id
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    pub fn progress(&self) -> Vec<LoadProgress> {
+        let mut progress: Vec<LoadProgress> = self
+            .jobs
+            .iter()
+            .map(|(id, job)| LoadProgress {
+                id: *id,
+                path: job.path.clone(),
+                done: job.done,
+                total: job.total,
+            })
+            .collect();
+        progress.sort_by_key(|p| p.id);
+        progress
+    }
+
+    /// Uploads whatever the workers finished since the last call. Textures
+    /// are registered with `assets` so later loads share them.
+    pub fn poll(&mut self, assets: &mut AssetManager) -> Vec<LoadEvent> {
+        let mut events = Vec::new();
+
+        while let Ok(output) = self.outputs.try_recv() {
+            match output {
+                Output::Model { id, result } => match result {
+                    Ok(data) => self.model_parsed(id, data, assets, &mut events),
+                    Err(error) => {
+                        self.jobs.remove(&id);
+                        events.push(LoadEvent::Failed { id, error });
+                    }
+                },
+                Output::Image {
+                    id,
+                    path,
+                    options,
+                    result,
+                } => {
+                    match result {
+                        Ok(data) => {
+                            match Texture::from_image_data(data, &options.flip(false)) {
+                                Ok(texture) => {
+                                    let texture = Rc::new(texture);
+                                    eprintln!("texture create : {}", path.display());
+                                    let _ = assets.insert_texture(&path, &options, texture.clone());
+                                    events.push(LoadEvent::TextureReady {
+                                        id,
+                                        path,
+                                        options,
+                                        texture,
+                                    });
+                                }
+                                Err(e) => eprintln!("texture load : {}: {}", path.display(), e),
+                            }
+                        }
+                        Err(e) => eprintln!("texture load : {}: {}", path.display(), e),
+                    }
+                    self.step_done(id, &mut events);
+                }
+            }
+        }
+
+        events
+    }
+
+    fn model_parsed(
+        &mut self,
+        id: LoadId,
+        data: ModelData,
+        assets: &mut AssetManager,
+        events: &mut Vec<LoadEvent>,
+    ) {
+        let mut pending = 0;
+        for (path, options) in data.texture_paths() {
+            if assets.cached_texture(&path, &options).is_none() {
+                let _ = self.tasks.send(Task::DecodeImage { id, path, options });
+                pending += 1;
+            }
+        }
+
+        let placeholder = self.placeholder.clone();
+        let mut texture = |path: &Path, options: &TextureOptions| {
+            Ok(assets
+                .cached_texture(path, options)
+                .unwrap_or_else(|| placeholder.clone()))
+        };
+
+        let batch_meshes = self.jobs.get(&id).map_or(false, |job| job.batch_meshes);
+        let model = if batch_meshes {
+            Model::from_data_batched(data, &mut texture)
+        } else {
+            Model::from_data(data, &mut texture)
+        };
+        match model {
+            Ok(model) => {
+                if let Some(job) = self.jobs.get_mut(&id) {
+                    job.total += pending;
+                }
+                events.push(LoadEvent::ModelReady { id, model });
+                self.step_done(id, events);
+            }
+            Err(e) => {
+                self.jobs.remove(&id);
+                events.push(LoadEvent::Failed {
+                    id,
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+
+    fn step_done(&mut self, id: LoadId, events: &mut Vec<LoadEvent>) {
+        if let Some(job) = self.jobs.get_mut(&id) {
+            job.done += 1;
+            if job.done >= job.total {
+                self.jobs.remove(&id);
+                events.push(LoadEvent::Finished { id });
+            }
+        }
+    }
+}
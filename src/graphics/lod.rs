@@ -0,0 +1,83 @@
+use glam::*;
+
+use super::bounds::BoundingSphere;
+
+/// Fraction of the viewport height covered by `sphere`, roughly. Cameras
+/// inside the sphere get `f32::INFINITY`.
+pub fn screen_size(sphere: &BoundingSphere, camera_position: Vec3, fov_y: f32) -> f32 {
+    let distance = sphere.center.distance(camera_position);
+    if distance <= sphere.radius {
+        return f32::INFINITY;
+    }
+    sphere.radius / (distance * (fov_y * 0.5).tan())
+}
+
+/// Picks a level of detail from the screen size of a model.
+///
+/// Level `i` is used while the model is at least `thresholds[i]` of the
+/// screen tall, the last level below that. `hysteresis` widens each switch
+/// point by that fraction in the direction of travel so that a model
+/// hovering at a threshold does not flicker between levels.
+#[derive(Clone, Debug)]
+pub struct LodSelector {
+    thresholds: Vec<f32>,
+    pub hysteresis: f32,
+    current: usize,
+}
+
+impl Default for LodSelector {
+    fn default() -> LodSelector {
+        LodSelector::with_levels(1)
+    }
+}
+
+#[allow(dead_code)]
+impl LodSelector {
+    /// `thresholds` must be descending, one per level except the last.
+    pub fn new(thresholds: Vec<f32>, hysteresis: f32) -> LodSelector {
+        LodSelector {
+            thresholds,
+            hysteresis,
+            current: 0,
+        }
+    }
+
+    /// Halves the screen size for each level, starting at half the screen.
+    pub fn with_levels(level_count: usize) -> LodSelector {
+        let thresholds = (0..level_count.saturating_sub(1))
+            .map(|level| 0.5 / (1 << level) as f32)
+            .collect();
+        LodSelector::new(thresholds, 0.1)
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.thresholds.len() + 1
+    }
+
+    pub fn thresholds(&self) -> &[f32] {
+        &self.thresholds
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Jumps straight to `level`, e.g. when the model was not visible.
+    pub fn set_current(&mut self, level: usize) {
+        self.current = level.min(self.thresholds.len());
+    }
+
+    pub fn select(&mut self, screen_size: f32) -> usize {
+        while self.current < self.thresholds.len()
+            && screen_size < self.thresholds[self.current] * (1.0 - self.hysteresis)
+        {
+            self.current += 1;
+        }
+        while self.current > 0
+            && screen_size > self.thresholds[self.current - 1] * (1.0 + self.hysteresis)
+        {
+            self.current -= 1;
+        }
+        self.current
+    }
+}
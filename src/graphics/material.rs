@@ -0,0 +1,273 @@
+use glam::*;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use super::mtl::TextureMap;
+use super::shader::Shader;
+use super::texture::Texture;
+
+/// Whether each texture slot, in the order of `texture_slots_mut`, holds
+/// color stored as sRGB rather than linear data such as specular intensity
+/// or coverage.
+pub const SRGB_SLOTS: [bool; 5] = [true, true, false, true, false];
+
+/// A texture bound to a material slot, remembering the map statement it came
+/// from so it can be swapped once a background load finishes.
+pub struct MaterialTexture {
+    pub map: TextureMap,
+    pub texture: Rc<Texture>,
+}
+
+/// How a material's surface is composited.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    Opaque,
+    /// Alpha tested against `ALPHA_CUTOFF`, written to depth like opaque.
+    Mask,
+    /// Alpha blended, drawn after everything opaque.
+    Blend,
+}
+
+pub const ALPHA_CUTOFF: f32 = 0.5;
+
+#[allow(dead_code)]
+pub struct Material {
+    pub name: String,
+    pub ambient_color: Vec3,
+    pub diffuse_color: Vec3,
+    pub specular_color: Vec3,
+    pub emissive_color: Vec3,
+    pub ambient_texture: Option<MaterialTexture>,
+    pub diffuse_texture: Option<MaterialTexture>,
+    pub specular_texture: Option<MaterialTexture>,
+    pub emissive_texture: Option<MaterialTexture>,
+    pub alpha_texture: Option<MaterialTexture>,
+    pub shininess: f32,
+    /// `d`, or `1 - Tr`. 1.0 is fully opaque.
+    pub dissolve: f32,
+    /// `Ni`, index of refraction.
+    pub optical_density: f32,
+    /// `illum`
+    pub illumination_model: Option<u8>,
+    /// Statements the loader does not interpret, keyed by their keyword.
+    pub unknown_params: HashMap<String, String>,
+}
+
+/// CPU side of a material, see `ModelData`.
+pub struct MaterialData {
+    pub name: String,
+    pub ambient_color: Vec3,
+    pub diffuse_color: Vec3,
+    pub specular_color: Vec3,
+    pub emissive_color: Vec3,
+    pub ambient_texture: Option<TextureMap>,
+    pub diffuse_texture: Option<TextureMap>,
+    pub specular_texture: Option<TextureMap>,
+    pub emissive_texture: Option<TextureMap>,
+    pub alpha_texture: Option<TextureMap>,
+    pub shininess: f32,
+    pub dissolve: f32,
+    pub optical_density: f32,
+    pub illumination_model: Option<u8>,
+    pub unknown_params: HashMap<String, String>,
+}
+
+impl Default for MaterialData {
+    /// Plain light gray, used for meshes without a (valid) `usemtl`.
+    fn default() -> MaterialData {
+        MaterialData {
+            name: String::from("default"),
+            ambient_color: Vec3::splat(1.0),
+            diffuse_color: Vec3::splat(0.8),
+            specular_color: Vec3::splat(0.5),
+            emissive_color: Vec3::ZERO,
+            ambient_texture: None,
+            diffuse_texture: None,
+            specular_texture: None,
+            emissive_texture: None,
+            alpha_texture: None,
+            shininess: 32.0,
+            dissolve: 1.0,
+            optical_density: 1.0,
+            illumination_model: None,
+            unknown_params: HashMap::new(),
+        }
+    }
+}
+
+fn parse_color(value: Option<&String>) -> Option<Vec3> {
+    let components: Vec<f32> = value?
+        .split_whitespace()
+        .map(|c| c.parse().ok())
+        .collect::<Option<Vec<f32>>>()?;
+    match components.as_slice() {
+        [v] => Some(Vec3::splat(*v)),
+        [r, g, b] => Some(Vec3::new(*r, *g, *b)),
+        _ => None,
+    }
+}
+
+impl MaterialData {
+    /// Converts a parsed MTL material, resolving its maps relative to
+    /// `base_dir` and `search_paths`. `has_dissolve` tells whether the
+    /// material sets `d`, see `mtl::materials_with_dissolve`.
+    pub fn from_tobj(
+        material: &tobj::Material,
+        has_dissolve: bool,
+        base_dir: &std::path::Path,
+        search_paths: &[PathBuf],
+    ) -> MaterialData {
+        let texture_map = |statement: &str| TextureMap::parse(statement, base_dir, search_paths);
+
+        let mut unknown_params: HashMap<String, String> = material
+            .unknown_param
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+
+        // tobj keeps `Ke`, `map_Ke` and `Tr` as unknown parameters.
+        let emissive_color = parse_color(unknown_params.get("Ke")).unwrap_or(Vec3::ZERO);
+        let emissive_texture = unknown_params
+            .get("map_Ke")
+            .and_then(|statement| texture_map(statement));
+
+        let mut dissolve = material.dissolve;
+        if let Some(transparency) = unknown_params
+            .get("Tr")
+            .and_then(|tr| tr.trim().parse::<f32>().ok())
+        {
+            // `d` wins when both are given.
+            if !has_dissolve {
+                dissolve = 1.0 - transparency;
+            }
+        }
+
+        for known in ["Ke", "map_Ke", "Tr"] {
+            unknown_params.remove(known);
+        }
+
+        MaterialData {
+            name: material.name.clone(),
+            ambient_color: Vec3::from_slice(&material.ambient),
+            diffuse_color: Vec3::from_slice(&material.diffuse),
+            specular_color: Vec3::from_slice(&material.specular),
+            emissive_color,
+            ambient_texture: texture_map(&material.ambient_texture),
+            diffuse_texture: texture_map(&material.diffuse_texture),
+            specular_texture: texture_map(&material.specular_texture),
+            emissive_texture,
+            alpha_texture: texture_map(&material.dissolve_texture),
+            shininess: material.shininess,
+            dissolve: dissolve.clamp(0.0, 1.0),
+            optical_density: material.optical_density,
+            illumination_model: material.illumination_model,
+            unknown_params,
+        }
+    }
+
+    /// Every map, with whether it holds color, see `SRGB_SLOTS`.
+    pub fn texture_maps(&self) -> impl Iterator<Item = (&TextureMap, bool)> {
+        [
+            &self.ambient_texture,
+            &self.diffuse_texture,
+            &self.specular_texture,
+            &self.emissive_texture,
+            &self.alpha_texture,
+        ]
+        .into_iter()
+        .zip(SRGB_SLOTS)
+        .filter_map(|(map, srgb)| map.as_ref().map(|map| (map, srgb)))
+    }
+}
+
+/// Packs a map's `-s` and `-o` options as `(scale.xy, offset.xy)`.
+fn uv_transform(texture: &Option<MaterialTexture>) -> Vec4 {
+    match texture {
+        Some(texture) => Vec4::new(
+            texture.map.scale.x,
+            texture.map.scale.y,
+            texture.map.offset.x,
+            texture.map.offset.y,
+        ),
+        None => Vec4::new(1.0, 1.0, 0.0, 0.0),
+    }
+}
+
+// Texture units used by `Material::apply`.
+const DIFFUSE_UNIT: u32 = 0;
+const SPECULAR_UNIT: u32 = 1;
+const AMBIENT_UNIT: u32 = 2;
+const EMISSIVE_UNIT: u32 = 3;
+const ALPHA_UNIT: u32 = 4;
+
+#[allow(dead_code)]
+impl Material {
+    pub fn texture_slots_mut(&mut self) -> [&mut Option<MaterialTexture>; 5] {
+        [
+            &mut self.ambient_texture,
+            &mut self.diffuse_texture,
+            &mut self.specular_texture,
+            &mut self.emissive_texture,
+            &mut self.alpha_texture,
+        ]
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        if self.dissolve < 1.0 {
+            return BlendMode::Blend;
+        }
+        let diffuse_alpha = self
+            .diffuse_texture
+            .as_ref()
+            .map_or(false, |diffuse| diffuse.texture.has_alpha());
+        if self.alpha_texture.is_some() || diffuse_alpha {
+            BlendMode::Mask
+        } else {
+            BlendMode::Opaque
+        }
+    }
+
+    /// `illum` 0 is a constant color and 1 is diffuse only; every other
+    /// model includes a specular term.
+    pub fn has_specular(&self) -> bool {
+        !matches!(self.illumination_model, Some(0) | Some(1))
+    }
+
+    /// Sets the `material` uniforms and binds the maps.
+    pub fn apply(&self, shader: &Shader) {
+        let slots = [
+            ("diffuse", DIFFUSE_UNIT, &self.diffuse_texture),
+            ("specular", SPECULAR_UNIT, &self.specular_texture),
+            ("ambient", AMBIENT_UNIT, &self.ambient_texture),
+            ("emissive", EMISSIVE_UNIT, &self.emissive_texture),
+            ("alpha", ALPHA_UNIT, &self.alpha_texture),
+        ];
+
+        for (name, unit, slot) in slots {
+            if let Some(texture) = slot {
+                shader.set_i32(&format!("material.{}_tex", name), unit as i32);
+                texture.texture.bind(unit);
+            }
+            shader.set_i32(&format!("material.has_{}_tex", name), slot.is_some() as i32);
+            shader.set_vec4v(&format!("material.{}_uv", name), &uv_transform(slot));
+        }
+
+        shader.set_vec3v("material.ambient", &self.ambient_color);
+        shader.set_vec3v("material.diffuse", &self.diffuse_color);
+        shader.set_vec3v("material.specular", &self.specular_color);
+        shader.set_vec3v("material.emissive", &self.emissive_color);
+        shader.set_f32("material.shininess", self.shininess);
+        shader.set_f32("material.dissolve", self.dissolve);
+        shader.set_i32(
+            "material.illumination_model",
+            self.illumination_model.map_or(2, |illum| illum as i32),
+        );
+
+        let alpha_cutoff = match self.blend_mode() {
+            BlendMode::Mask => ALPHA_CUTOFF,
+            BlendMode::Opaque | BlendMode::Blend => 0.0,
+        };
+        shader.set_f32("material.alpha_cutoff", alpha_cutoff);
+    }
+}
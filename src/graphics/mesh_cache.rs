@@ -0,0 +1,895 @@
+use gl::types::*;
+use glam::*;
+use memmap2::Mmap;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+
+use super::animation::{
+    AnimationClip, Channel, Interpolation, Joint, Keyframes, Node, Skeleton, Transform,
+};
+use super::material::MaterialData;
+use super::model::{
+    ImportOptions, MeshData, ModelData, MorphTarget, SkinWeights, Vertex, MAX_MORPH_TARGETS,
+};
+use super::mtl::{self, TextureMap};
+use super::shader_cache::Fnv1aHasher;
+
+const MAGIC: &[u8; 8] = b"LGLMESH\0";
+/// Bump whenever the layout below or of any type stored as raw bytes
+/// (`Vertex`, `SkinWeights`) changes.
+const VERSION: u32 = 1;
+/// Written in native byte order; reads back differently on a machine of
+/// the other endianness, whose raw arrays would be garbage.
+const BYTE_ORDER_MARK: u32 = 0x0102_0304;
+
+/// Types stored as their in-memory bytes.
+///
+/// # Safety
+/// Every bit pattern must be a valid value and the type must not contain
+/// pointers.
+unsafe trait Pod: Copy {}
+
+unsafe impl Pod for u16 {}
+unsafe impl Pod for u32 {}
+unsafe impl Pod for f32 {}
+unsafe impl Pod for Vec3 {}
+unsafe impl Pod for Quat {}
+unsafe impl Pod for Mat4 {}
+unsafe impl Pod for Vertex {}
+unsafe impl Pod for SkinWeights {}
+
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_ne_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_ne_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.u32(value.to_bits());
+    }
+
+    fn len(&mut self, len: usize) {
+        self.u64(len as u64);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.len(value.len());
+        self.data.extend_from_slice(value.as_bytes());
+    }
+
+    fn vec3(&mut self, value: Vec3) {
+        self.pod(&[value]);
+    }
+
+    fn optional_index(&mut self, value: Option<usize>) {
+        self.u32(value.map_or(u32::MAX, |value| value as u32));
+    }
+
+    /// Raw bytes, without a length.
+    fn pod<T: Pod>(&mut self, values: &[T]) {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(values.as_ptr() as *const u8, std::mem::size_of_val(values))
+        };
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn array<T: Pod>(&mut self, values: &[T]) {
+        self.len(values.len());
+        self.pod(values);
+    }
+}
+
+/// Reads back what `Writer` wrote. Every read returns `None` past the end
+/// of the data, so truncated files are rejected rather than panicking.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.data.len() {
+            return None;
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_ne_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_ne_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        Some(f32::from_bits(self.u32()?))
+    }
+
+    fn len(&mut self) -> Option<usize> {
+        usize::try_from(self.u64()?).ok()
+    }
+
+    fn str(&mut self) -> Option<String> {
+        let len = self.len()?;
+        String::from_utf8(self.bytes(len)?.to_vec()).ok()
+    }
+
+    fn vec3(&mut self) -> Option<Vec3> {
+        Some(self.pod::<Vec3>(1)?[0])
+    }
+
+    fn optional_index(&mut self) -> Option<Option<usize>> {
+        let value = self.u32()?;
+        Some((value != u32::MAX).then(|| value as usize))
+    }
+
+    /// Copies `count` values out, as the data need not be aligned for `T`.
+    fn pod<T: Pod>(&mut self, count: usize) -> Option<Vec<T>> {
+        let bytes = self.bytes(count.checked_mul(std::mem::size_of::<T>())?)?;
+        let mut values: Vec<T> = Vec::with_capacity(count);
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                bytes.as_ptr(),
+                values.as_mut_ptr() as *mut u8,
+                bytes.len(),
+            );
+            values.set_len(count);
+        }
+        Some(values)
+    }
+
+    fn array<T: Pod>(&mut self) -> Option<Vec<T>> {
+        let len = self.len()?;
+        self.pod(len)
+    }
+}
+
+fn write_texture_map(writer: &mut Writer, map: &Option<TextureMap>) {
+    match map {
+        Some(map) => {
+            writer.u8(1);
+            writer.str(&map.path.to_string_lossy());
+            writer.vec3(map.offset);
+            writer.vec3(map.scale);
+            writer.f32(map.bump_multiplier);
+        }
+        None => writer.u8(0),
+    }
+}
+
+fn read_texture_map(reader: &mut Reader) -> Option<Option<TextureMap>> {
+    if reader.u8()? == 0 {
+        return Some(None);
+    }
+    Some(Some(TextureMap {
+        path: PathBuf::from(reader.str()?),
+        offset: reader.vec3()?,
+        scale: reader.vec3()?,
+        bump_multiplier: reader.f32()?,
+    }))
+}
+
+fn write_material(writer: &mut Writer, material: &MaterialData) {
+    writer.str(&material.name);
+    for color in [
+        material.ambient_color,
+        material.diffuse_color,
+        material.specular_color,
+        material.emissive_color,
+    ] {
+        writer.vec3(color);
+    }
+    for map in [
+        &material.ambient_texture,
+        &material.diffuse_texture,
+        &material.specular_texture,
+        &material.emissive_texture,
+        &material.alpha_texture,
+    ] {
+        write_texture_map(writer, map);
+    }
+    writer.f32(material.shininess);
+    writer.f32(material.dissolve);
+    writer.f32(material.optical_density);
+    writer.u32(material.illumination_model.map_or(u32::MAX, u32::from));
+
+    // Sorted so the same material always produces the same bytes.
+    let mut params: Vec<_> = material.unknown_params.iter().collect();
+    params.sort();
+    writer.len(params.len());
+    for (key, value) in params {
+        writer.str(key);
+        writer.str(value);
+    }
+}
+
+fn read_material(reader: &mut Reader) -> Option<MaterialData> {
+    let name = reader.str()?;
+    let ambient_color = reader.vec3()?;
+    let diffuse_color = reader.vec3()?;
+    let specular_color = reader.vec3()?;
+    let emissive_color = reader.vec3()?;
+    let ambient_texture = read_texture_map(reader)?;
+    let diffuse_texture = read_texture_map(reader)?;
+    let specular_texture = read_texture_map(reader)?;
+    let emissive_texture = read_texture_map(reader)?;
+    let alpha_texture = read_texture_map(reader)?;
+    let shininess = reader.f32()?;
+    let dissolve = reader.f32()?;
+    let optical_density = reader.f32()?;
+    let illumination_model = u8::try_from(reader.u32()?).ok();
+
+    let mut unknown_params = HashMap::new();
+    for _ in 0..reader.len()? {
+        unknown_params.insert(reader.str()?, reader.str()?);
+    }
+
+    Some(MaterialData {
+        name,
+        ambient_color,
+        diffuse_color,
+        specular_color,
+        emissive_color,
+        ambient_texture,
+        diffuse_texture,
+        specular_texture,
+        emissive_texture,
+        alpha_texture,
+        shininess,
+        dissolve,
+        optical_density,
+        illumination_model,
+        unknown_params,
+    })
+}
+
+fn write_mesh(writer: &mut Writer, mesh: &MeshData) {
+    writer.array(&mesh.vertices);
+    writer.array(&mesh.indices);
+    writer.u32(mesh.material as u32);
+    match &mesh.skin {
+        Some(skin) => {
+            writer.u8(1);
+            writer.pod(skin);
+        }
+        None => writer.u8(0),
+    }
+    writer.len(mesh.morph_targets.len());
+    for target in &mesh.morph_targets {
+        writer.pod(&target.positions);
+        writer.array(&target.normals);
+    }
+    writer.array(&mesh.morph_weights);
+    writer.optional_index(mesh.node);
+}
+
+fn read_mesh(reader: &mut Reader) -> Option<MeshData> {
+    let vertices: Vec<Vertex> = reader.array()?;
+    let vertex_count = vertices.len();
+    let indices: Vec<GLuint> = reader.array()?;
+    if indices.len() % 3 != 0 || indices.iter().any(|&i| i as usize >= vertex_count) {
+        return None;
+    }
+    let material = reader.u32()? as usize;
+    let skin = match reader.u8()? {
+        0 => None,
+        _ => Some(reader.pod(vertex_count)?),
+    };
+
+    let target_count = reader.len()?;
+    if target_count > MAX_MORPH_TARGETS {
+        return None;
+    }
+    let mut morph_targets = Vec::new();
+    for _ in 0..target_count {
+        let target = MorphTarget {
+            positions: reader.pod(vertex_count)?,
+            normals: reader.array()?,
+        };
+        if !(target.normals.is_empty() || target.normals.len() == vertex_count) {
+            return None;
+        }
+        morph_targets.push(target);
+    }
+    let morph_weights = reader.array()?;
+    let node = reader.optional_index()?;
+
+    Some(MeshData {
+        vertices,
+        indices,
+        material,
+        skin,
+        morph_targets,
+        morph_weights,
+        node,
+    })
+}
+
+fn write_meshes(writer: &mut Writer, meshes: &[MeshData]) {
+    writer.len(meshes.len());
+    for mesh in meshes {
+        write_mesh(writer, mesh);
+    }
+}
+
+fn read_meshes(reader: &mut Reader, material_count: usize) -> Option<Vec<MeshData>> {
+    let count = reader.len()?;
+    let mut meshes = Vec::new();
+    for _ in 0..count {
+        let mesh = read_mesh(reader)?;
+        if mesh.material >= material_count {
+            return None;
+        }
+        meshes.push(mesh);
+    }
+    Some(meshes)
+}
+
+fn write_transform(writer: &mut Writer, transform: &Transform) {
+    writer.vec3(transform.translation);
+    writer.pod(&[transform.rotation]);
+    writer.vec3(transform.scale);
+}
+
+fn read_transform(reader: &mut Reader) -> Option<Transform> {
+    Some(Transform {
+        translation: reader.vec3()?,
+        rotation: reader.pod::<Quat>(1)?[0],
+        scale: reader.vec3()?,
+    })
+}
+
+fn write_skeleton(writer: &mut Writer, skeleton: &Skeleton) {
+    writer.len(skeleton.nodes.len());
+    for node in &skeleton.nodes {
+        writer.str(&node.name);
+        writer.optional_index(node.parent);
+        write_transform(writer, &node.rest);
+    }
+    writer.len(skeleton.joints.len());
+    for joint in &skeleton.joints {
+        writer.u32(joint.node as u32);
+        writer.pod(&[joint.inverse_bind]);
+    }
+}
+
+fn read_skeleton(reader: &mut Reader) -> Option<Skeleton> {
+    let mut nodes = Vec::new();
+    for i in 0..reader.len()? {
+        let node = Node {
+            name: reader.str()?,
+            parent: reader.optional_index()?,
+            rest: read_transform(reader)?,
+        };
+        // Parents come first, see `Skeleton`.
+        if node.parent.map_or(false, |parent| parent >= i) {
+            return None;
+        }
+        nodes.push(node);
+    }
+
+    let mut joints = Vec::new();
+    for _ in 0..reader.len()? {
+        let joint = Joint {
+            node: reader.u32()? as usize,
+            inverse_bind: reader.pod::<Mat4>(1)?[0],
+        };
+        if joint.node >= nodes.len() {
+            return None;
+        }
+        joints.push(joint);
+    }
+
+    Some(Skeleton { nodes, joints })
+}
+
+fn write_clip(writer: &mut Writer, clip: &AnimationClip) {
+    writer.str(&clip.name);
+    writer.f32(clip.duration);
+    writer.len(clip.channels.len());
+    for channel in &clip.channels {
+        writer.u32(channel.node as u32);
+        writer.u8(match channel.interpolation {
+            Interpolation::Step => 0,
+            Interpolation::Linear => 1,
+            Interpolation::CubicSpline => 2,
+        });
+        writer.array(&channel.times);
+        match &channel.keyframes {
+            Keyframes::Translation(values) => {
+                writer.u8(0);
+                writer.array(values);
+            }
+            Keyframes::Rotation(values) => {
+                writer.u8(1);
+                writer.array(values);
+            }
+            Keyframes::Scale(values) => {
+                writer.u8(2);
+                writer.array(values);
+            }
+            Keyframes::Weights(values) => {
+                writer.u8(3);
+                writer.array(values);
+            }
+        }
+    }
+}
+
+fn read_clip(reader: &mut Reader, node_count: usize) -> Option<AnimationClip> {
+    let name = reader.str()?;
+    let duration = reader.f32()?;
+    let mut channels = Vec::new();
+    for _ in 0..reader.len()? {
+        let node = reader.u32()? as usize;
+        if node >= node_count {
+            return None;
+        }
+        let interpolation = match reader.u8()? {
+            0 => Interpolation::Step,
+            1 => Interpolation::Linear,
+            2 => Interpolation::CubicSpline,
+            _ => return None,
+        };
+        let times = reader.array()?;
+        let keyframes = match reader.u8()? {
+            0 => Keyframes::Translation(reader.array()?),
+            1 => Keyframes::Rotation(reader.array()?),
+            2 => Keyframes::Scale(reader.array()?),
+            3 => Keyframes::Weights(reader.array()?),
+            _ => return None,
+        };
+        let channel = Channel {
+            node,
+            interpolation,
+            times,
+            keyframes,
+        };
+        if !channel.keys_match() {
+            return None;
+        }
+        channels.push(channel);
+    }
+
+    Some(AnimationClip {
+        name,
+        duration,
+        channels,
+    })
+}
+
+fn serialize(data: &ModelData, source_hash: u64) -> Vec<u8> {
+    let mut writer = Writer { data: Vec::new() };
+    writer.data.extend_from_slice(MAGIC);
+    writer.u32(VERSION);
+    writer.u32(BYTE_ORDER_MARK);
+    writer.u64(source_hash);
+
+    writer.len(data.materials.len());
+    for material in &data.materials {
+        write_material(&mut writer, material);
+    }
+    write_meshes(&mut writer, &data.meshes);
+    writer.len(data.lods.len());
+    for lod in &data.lods {
+        write_meshes(&mut writer, lod);
+    }
+
+    match &data.skeleton {
+        Some(skeleton) => {
+            writer.u8(1);
+            write_skeleton(&mut writer, skeleton);
+        }
+        None => writer.u8(0),
+    }
+    writer.len(data.animations.len());
+    for clip in &data.animations {
+        write_clip(&mut writer, clip);
+    }
+
+    writer.data
+}
+
+/// `None` for anything but a complete, current entry of `source_hash`
+/// whose indices are all in bounds.
+fn deserialize(data: &[u8], source_hash: u64) -> Option<ModelData> {
+    let mut reader = Reader { data };
+    if reader.bytes(MAGIC.len())? != MAGIC
+        || reader.u32()? != VERSION
+        || reader.u32()? != BYTE_ORDER_MARK
+        || reader.u64()? != source_hash
+    {
+        return None;
+    }
+
+    let mut materials = Vec::new();
+    for _ in 0..reader.len()? {
+        materials.push(read_material(&mut reader)?);
+    }
+    let meshes = read_meshes(&mut reader, materials.len())?;
+    let mut lods = Vec::new();
+    for _ in 0..reader.len()? {
+        lods.push(read_meshes(&mut reader, materials.len())?);
+    }
+
+    let skeleton = match reader.u8()? {
+        0 => None,
+        _ => Some(read_skeleton(&mut reader)?),
+    };
+    let node_count = skeleton.as_ref().map_or(0, |skeleton| skeleton.nodes.len());
+    let joint_count = skeleton
+        .as_ref()
+        .map_or(0, |skeleton| skeleton.joints.len());
+    // Meshes come before the skeleton their skins and nodes refer to.
+    let in_skeleton = |mesh: &MeshData| {
+        mesh.node.map_or(true, |node| node < node_count)
+            && mesh.skin.as_ref().map_or(true, |skin| {
+                skin.iter().all(|weights| {
+                    weights
+                        .joints
+                        .iter()
+                        .all(|&joint| (joint as usize) < joint_count)
+                })
+            })
+    };
+    if !meshes.iter().chain(lods.iter().flatten()).all(in_skeleton) {
+        return None;
+    }
+    let mut animations = Vec::new();
+    for _ in 0..reader.len()? {
+        animations.push(read_clip(&mut reader, node_count)?);
+    }
+
+    if !reader.data.is_empty() {
+        return None;
+    }
+
+    Some(ModelData {
+        materials,
+        meshes,
+        skeleton,
+        animations,
+        lods,
+    })
+}
+
+/// Files a model is imported from besides itself: the MTL libraries of an
+/// OBJ, or the external buffers of a glTF file.
+fn dependencies(path: &Path, options: &ImportOptions) -> Vec<PathBuf> {
+    let base_dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("gltf") | Some("glb") => match gltf::Gltf::open(path) {
+            Ok(gltf) => gltf
+                .buffers()
+                .filter_map(|buffer| match buffer.source() {
+                    gltf::buffer::Source::Uri(uri) if !uri.starts_with("data:") => {
+                        Some(base_dir.join(uri))
+                    }
+                    _ => None,
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        },
+        _ => fs::read_to_string(path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("mtllib "))
+            .flat_map(|names| names.split_whitespace())
+            .map(|name| mtl::resolve_path(name, &base_dir, &options.search_paths))
+            .collect(),
+    }
+}
+
+/// On-disk store of imported models in a binary format that loads without
+/// any parsing or processing.
+///
+/// There is one entry per model file and set of import options. Each entry
+/// records a hash of the model's source files and the options, and is
+/// rebuilt when they no longer match.
+#[derive(Clone)]
+pub struct MeshCache {
+    dir: PathBuf,
+}
+
+#[allow(dead_code)]
+impl MeshCache {
+    pub fn new<P: AsRef<Path>>(dir: P) -> MeshCache {
+        MeshCache {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    fn options_key(options: &ImportOptions) -> String {
+        // Debug output covers every field and is stable between builds.
+        // Batching happens on upload and does not change the imported data.
+        let options = ImportOptions {
+            batch_meshes: false,
+            ..options.clone()
+        };
+        format!("{:?}", options)
+    }
+
+    fn entry_path(&self, path: &Path, options: &ImportOptions) -> PathBuf {
+        let mut hasher = Fnv1aHasher::default();
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        hasher.write(path.to_string_lossy().as_bytes());
+        hasher.write_u8(0);
+        hasher.write(MeshCache::options_key(options).as_bytes());
+        self.dir.join(format!("{:016x}.mesh", hasher.finish()))
+    }
+
+    /// Hash of the model file, the files it references and `options`.
+    pub fn source_hash(path: &Path, options: &ImportOptions) -> Result<u64, Box<dyn Error>> {
+        let mut hasher = Fnv1aHasher::default();
+        hasher.write(&fs::read(path)?);
+        for dependency in dependencies(path, options) {
+            hasher.write_u8(0);
+            hasher.write(dependency.to_string_lossy().as_bytes());
+            // A missing library changes the import too.
+            if let Ok(contents) = fs::read(&dependency) {
+                hasher.write(&contents);
+            }
+        }
+        hasher.write_u8(0);
+        hasher.write(MeshCache::options_key(options).as_bytes());
+        Ok(hasher.finish())
+    }
+
+    /// The cached import of `path`, if there is an entry matching
+    /// `source_hash`. Unreadable or stale entries are removed.
+    pub fn load(
+        &self,
+        path: &Path,
+        options: &ImportOptions,
+        source_hash: u64,
+    ) -> Option<ModelData> {
+        let entry_path = self.entry_path(path, options);
+        let file = File::open(&entry_path).ok()?;
+        // Safety: the map is only read while it exists and the entry is not
+        // modified by this process meanwhile; a concurrent writer elsewhere
+        // would at worst produce an entry that fails to deserialize.
+        let map = unsafe { Mmap::map(&file) }.ok()?;
+
+        let data = deserialize(&map, source_hash);
+        if data.is_none() {
+            eprintln!("mesh cache : rebuilding {}", path.display());
+            drop(map);
+            let _ = fs::remove_file(&entry_path);
+        }
+        data
+    }
+
+    pub fn store(
+        &self,
+        path: &Path,
+        options: &ImportOptions,
+        source_hash: u64,
+        data: &ModelData,
+    ) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(&self.dir)?;
+        // Written next to the entry and renamed over it, so a reader never
+        // maps a half-written file.
+        let entry_path = self.entry_path(path, options);
+        let temp_path = entry_path.with_extension("tmp");
+        fs::write(&temp_path, serialize(data, source_hash))?;
+        fs::rename(&temp_path, &entry_path)?;
+        Ok(())
+    }
+
+    /// Removes every cached model.
+    pub fn clear(&self) -> Result<(), Box<dyn Error>> {
+        if self.dir.exists() {
+            for entry in fs::read_dir(&self.dir)? {
+                let path = entry?.path();
+                if path.extension().map_or(false, |ext| ext == "mesh") {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: u64 = 0x1234_5678_9abc_def0;
+
+    fn vertex(x: f32, y: f32) -> Vertex {
+        Vertex {
+            position: vec3(x, y, 0.0),
+            normal: Vec3::Z,
+            tex_coords: vec2(x, y),
+        }
+    }
+
+    /// A skinned, morphed triangle with one LOD and a clip of every kind.
+    fn model() -> ModelData {
+        let mesh = MeshData {
+            vertices: vec![vertex(0.0, 0.0), vertex(1.0, 0.0), vertex(0.0, 1.0)],
+            indices: vec![0, 1, 2],
+            material: 0,
+            skin: Some(vec![
+                SkinWeights {
+                    joints: [0, 1, 0, 0],
+                    weights: [0.5, 0.5, 0.0, 0.0],
+                };
+                3
+            ]),
+            morph_targets: vec![MorphTarget {
+                positions: vec![Vec3::Z; 3],
+                normals: Vec::new(),
+            }],
+            morph_weights: vec![0.25],
+            node: Some(1),
+        };
+        let lod = MeshData {
+            vertices: mesh.vertices.clone(),
+            indices: vec![0, 1, 2],
+            material: 0,
+            skin: mesh.skin.clone(),
+            morph_targets: Vec::new(),
+            morph_weights: Vec::new(),
+            node: None,
+        };
+
+        let node = |name: &str, parent| Node {
+            name: name.to_string(),
+            parent,
+            rest: Transform::default(),
+        };
+        let skeleton = Skeleton {
+            nodes: vec![node("root", None), node("arm", Some(0))],
+            joints: vec![
+                Joint {
+                    node: 0,
+                    inverse_bind: Mat4::IDENTITY,
+                },
+                Joint {
+                    node: 1,
+                    inverse_bind: Mat4::from_translation(Vec3::X),
+                },
+            ],
+        };
+        let channel = |interpolation, keyframes| Channel {
+            node: 1,
+            interpolation,
+            times: vec![0.0, 1.0],
+            keyframes,
+        };
+        let clip = AnimationClip {
+            name: "wave".to_string(),
+            duration: 1.0,
+            channels: vec![
+                channel(
+                    Interpolation::Linear,
+                    Keyframes::Translation(vec![Vec3::ZERO, Vec3::Y]),
+                ),
+                channel(
+                    Interpolation::Step,
+                    Keyframes::Rotation(vec![Quat::IDENTITY; 2]),
+                ),
+                channel(
+                    Interpolation::CubicSpline,
+                    Keyframes::Scale(vec![Vec3::ONE; 6]),
+                ),
+                channel(Interpolation::Linear, Keyframes::Weights(vec![0.0, 1.0])),
+            ],
+        };
+
+        ModelData {
+            materials: vec![MaterialData {
+                name: "skin".to_string(),
+                ..MaterialData::default()
+            }],
+            meshes: vec![mesh],
+            skeleton: Some(skeleton),
+            animations: vec![clip],
+            lods: vec![vec![lod]],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = serialize(&model(), HASH);
+        let data = deserialize(&bytes, HASH).expect("cache entry rejected");
+
+        assert_eq!(data.materials[0].name, "skin");
+        assert_eq!(data.meshes[0].indices, [0, 1, 2]);
+        assert_eq!(data.meshes[0].skin.as_ref().map(Vec::len), Some(3));
+        assert_eq!(data.meshes[0].node, Some(1));
+        assert_eq!(data.lods.len(), 1);
+        assert_eq!(
+            data.skeleton.as_ref().map(|s| s.nodes[1].parent),
+            Some(Some(0))
+        );
+        assert_eq!(data.animations[0].channels.len(), 4);
+        // Everything read back is written out identically.
+        assert_eq!(serialize(&data, HASH), bytes);
+    }
+
+    #[test]
+    fn truncated_entries_are_rejected() {
+        let bytes = serialize(&model(), HASH);
+        for len in 0..bytes.len() {
+            assert!(
+                deserialize(&bytes[..len], HASH).is_none(),
+                "accepted {len} bytes"
+            );
+        }
+
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert!(deserialize(&trailing, HASH).is_none());
+    }
+
+    #[test]
+    fn other_sources_are_rejected() {
+        let bytes = serialize(&model(), HASH);
+        assert!(deserialize(&bytes, HASH + 1).is_none());
+
+        let mut version = bytes;
+        version[MAGIC.len()] ^= 1;
+        assert!(deserialize(&version, HASH).is_none());
+    }
+
+    #[test]
+    fn out_of_range_references_are_rejected() {
+        let mut data = model();
+        data.meshes[0].indices[2] = 3;
+        assert!(deserialize(&serialize(&data, HASH), HASH).is_none());
+
+        let mut data = model();
+        data.lods[0][0].material = 1;
+        assert!(deserialize(&serialize(&data, HASH), HASH).is_none());
+
+        let mut data = model();
+        data.meshes[0].skin.as_mut().unwrap()[1].joints[0] = 2;
+        assert!(deserialize(&serialize(&data, HASH), HASH).is_none());
+
+        let mut data = model();
+        data.animations[0].channels[0].times.push(2.0);
+        assert!(deserialize(&serialize(&data, HASH), HASH).is_none());
+    }
+
+    #[test]
+    fn malformed_meshes_are_rejected() {
+        let mut data = model();
+        data.meshes[0].indices.push(0);
+        assert!(deserialize(&serialize(&data, HASH), HASH).is_none());
+
+        let mut data = model();
+        let target = data.meshes[0].morph_targets[0].clone();
+        data.meshes[0].morph_targets = vec![target; MAX_MORPH_TARGETS + 1];
+        assert!(deserialize(&serialize(&data, HASH), HASH).is_none());
+    }
+}
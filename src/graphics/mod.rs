@@ -9,15 +9,112 @@ pub fn gl_str_to_rust_string(name: u32) -> String {
     }
 }
 
+/// Returns the `(major, minor)` version of the current context.
+pub fn gl_version() -> (i32, i32) {
+    let mut major = 0;
+    let mut minor = 0;
+    unsafe {
+        gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+        gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+    }
+    (major, minor)
+}
+
+pub fn gl_version_at_least(major: i32, minor: i32) -> bool {
+    gl_version() >= (major, minor)
+}
+
+pub fn gl_has_extension(name: &str) -> bool {
+    unsafe {
+        let mut count = 0;
+        gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+        (0..count as u32).any(|i| {
+            let buf = gl::GetStringi(gl::EXTENSIONS, i);
+            !buf.is_null() && CStr::from_ptr(buf as *const i8).to_bytes() == name.as_bytes()
+        })
+    }
+}
+
+/// Texels a buffer texture may hold, at least 65536.
+pub fn max_texture_buffer_size() -> usize {
+    let mut size = 0;
+    unsafe { gl::GetIntegerv(gl::MAX_TEXTURE_BUFFER_SIZE, &mut size) };
+    size.max(0) as usize
+}
+
 mod shader;
-pub use self::shader::Shader;
+pub use self::shader::{Shader, ShaderBuilder};
+
+mod shader_cache;
+pub use self::shader_cache::ProgramCache;
+
+mod mesh_cache;
+pub use self::mesh_cache::MeshCache;
+
+pub mod compressed;
+
+pub mod leak_tracker;
 
 mod texture;
-pub use self::texture::Texture;
+pub use self::texture::{Filter, Sampler, Texture, TextureOptions, Wrap};
 
 pub mod camera;
 pub use camera::Camera;
 
 mod model;
-pub use model::Model;
+pub use model::{ImportOptions, Model, ModelError, NormalMode, Simplification};
+
+mod export;
+pub use export::{write_gltf, write_obj, ExportData};
+
+mod material;
+pub use material::{BlendMode, Material};
+
+mod render_queue;
+pub use render_queue::{RenderQueue, RenderStats};
+
+mod oit;
+pub use oit::WeightedBlendedOit;
+
+mod geometry;
+mod optimize;
+mod simplify;
+
+pub mod animation;
+mod gltf_import;
+
+mod bounds;
+pub use bounds::{Aabb, BoundingSphere};
+
+mod frustum;
+pub use frustum::Frustum;
+
+mod instancing;
+pub use instancing::InstanceBuffer;
+
+mod gl_object;
+pub use gl_object::{Buffer, VertexArray};
+
+mod dynamic_buffer;
+pub use dynamic_buffer::{DynamicBuffer, StreamMode};
+
+mod dynamic_mesh;
+pub use dynamic_mesh::DynamicMesh;
+
+mod debug_draw;
+pub use debug_draw::{DebugDraw, DepthMode};
+
+mod grid;
+pub use grid::{AxesGizmo, InfiniteGrid};
+
+mod lod;
+pub use lod::LodSelector;
+
+pub mod mtl;
+
+mod assets;
+pub use assets::{AssetManager, MemoryUsage};
+
+mod loader;
+pub use loader::{AsyncLoader, LoadEvent, LoadProgress};
 
@@ -1,60 +1,407 @@
-use super::texture::Texture;
+use super::assets::AssetManager;
+use super::texture::{Filter, Texture, TextureOptions};
 use super::shader::Shader;
+use super::geometry;
+use super::mtl::{self, TextureMap};
+use super::material::{BlendMode, Material, MaterialData, MaterialTexture, SRGB_SLOTS};
+use super::bounds::{Aabb, BoundingSphere};
+use super::instancing::InstanceBuffer;
+use super::animation::{AnimationClip, Skeleton, Transform};
+use super::gltf_import;
+use super::lod::{self, LodSelector};
+use super::optimize;
+use super::simplify;
+use super::mesh_cache::MeshCache;
+use super::leak_tracker::{self, GlObject};
+use super::max_texture_buffer_size;
 
 use gl::types::*;
 use glam::*;
 
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
 
 use std::rc::Rc;
 
 
 #[repr(packed)]
+#[derive(Clone, Copy)]
 pub struct Vertex {
     pub position: Vec3,
     pub normal: Vec3,
     pub tex_coords: Vec2,
 }
 
-pub struct Mesh {
-    vertices: Vec<Vertex>,
-    indices: Vec<GLuint>,
-    material: Rc<Material>,
-    vao: GLuint,
+/// Joint influences of a skinned vertex, uploaded as a second vertex stream
+/// next to `Vertex`. Weights sum to 1.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SkinWeights {
+    pub joints: [u16; 4],
+    pub weights: [f32; 4],
+}
+
+/// Per-vertex displacements of one blend shape. `normals` may be empty.
+#[derive(Clone, Debug, Default)]
+pub struct MorphTarget {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+}
+
+/// Morph targets blended per draw, see `lighting_vert.glsl`.
+pub const MAX_MORPH_TARGETS: usize = 64;
+
+/// Texture unit of the morph target deltas, after the material maps.
+pub(super) const MORPH_DELTAS_UNIT: u32 = 5;
+/// Texture unit of the joint matrices of skinned meshes.
+pub(super) const JOINT_MATRICES_UNIT: u32 = 6;
+
+/// A skeleton pose in a buffer texture the vertex shader reads as
+/// `joint_matrices`, four RGBA32F texels (columns) per joint. Uniform
+/// arrays of that many matrices exceed what GL 3.3 guarantees.
+#[derive(Default)]
+pub(super) struct JointBuffer {
+    buffer: GLuint,
+    texture: GLuint,
+}
+
+impl JointBuffer {
+    fn upload(&mut self, matrices: &[Mat4]) {
+        if matrices.is_empty() {
+            return;
+        }
+
+        unsafe {
+            if self.buffer == 0 {
+                gl::GenBuffers(1, &mut self.buffer);
+                gl::GenTextures(1, &mut self.texture);
+                leak_tracker::created(GlObject::Buffer, self.buffer);
+                leak_tracker::created(GlObject::Texture, self.texture);
+            }
+
+            gl::BindBuffer(gl::TEXTURE_BUFFER, self.buffer);
+            gl::BufferData(gl::TEXTURE_BUFFER,
+                           (matrices.len() * std::mem::size_of::<Mat4>()) as GLsizeiptr,
+                           matrices.as_ptr() as *const GLvoid,
+                           gl::DYNAMIC_DRAW);
+            gl::BindBuffer(gl::TEXTURE_BUFFER, 0);
+
+            gl::BindTexture(gl::TEXTURE_BUFFER, self.texture);
+            gl::TexBuffer(gl::TEXTURE_BUFFER, gl::RGBA32F, self.buffer);
+            gl::BindTexture(gl::TEXTURE_BUFFER, 0);
+        }
+    }
+
+    pub(super) fn bind(&self) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + JOINT_MATRICES_UNIT);
+            gl::BindTexture(gl::TEXTURE_BUFFER, self.texture);
+        }
+    }
 }
 
+impl Drop for JointBuffer {
+    fn drop(&mut self) {
+        if self.buffer == 0 {
+            return;
+        }
+        leak_tracker::deleted(GlObject::Buffer, self.buffer);
+        leak_tracker::deleted(GlObject::Texture, self.texture);
+        unsafe {
+            gl::DeleteTextures(1, &self.texture);
+            gl::DeleteBuffers(1, &self.buffer);
+        }
+    }
+}
 
-pub struct Material {
-    pub name: String,
-    pub ambient_color: Vec3,
-    pub diffuse_color: Vec3,
-    pub specular_color: Vec3,
-    pub diffuse_texture: Option<Texture>,
-    pub specular_texture: Option<Texture>,
-    pub shininess: f32,
+/// Vertex, index and skin buffers with the vertex array reading them,
+/// shared by every mesh packed into them.
+struct MeshBuffers {
+    vao: GLuint,
+    vbo: GLuint,
+    ebo: GLuint,
+    skin_vbo: GLuint,
+    /// `UNSIGNED_SHORT` when no mesh in the buffers has more vertices than
+    /// 16-bit indices can address, `UNSIGNED_INT` otherwise.
+    index_type: GLenum,
+}
+
+pub struct Mesh {
+    vertices: Vec<Vertex>,
+    indices: Vec<GLuint>,
+    skin: Vec<SkinWeights>,
+    buffers: Rc<MeshBuffers>,
+    /// Where the mesh starts in `buffers`: the vertex its indices are
+    /// relative to, and the byte offset of its first index.
+    base_vertex: GLint,
+    index_offset: usize,
+    /// Kept on the CPU, like the vertices, for exporting.
+    morph_targets: Vec<MorphTarget>,
+    morph_weights: Vec<f32>,
+    /// Range of each target's position deltas, which the bounds grow by.
+    morph_extents: Vec<Aabb>,
+    /// Buffer texture holding a position and a normal delta per vertex and
+    /// target, targets one after another.
+    morph_buffer: GLuint,
+    morph_texture: GLuint,
+    /// Scene node the mesh was instanced from, whose animated morph
+    /// weights apply to it.
+    node: Option<usize>,
+    material: usize,
+    /// Bounds of the current pose, which is the vertices as stored unless
+    /// skinned or morphed, see `pose_bounds`.
+    aabb: Aabb,
+    bounding_sphere: BoundingSphere,
+    /// Bounds of the vertices as stored, i.e. in bind space if skinned and
+    /// without morph targets.
+    bind_aabb: Aabb,
+    /// Joints influencing any vertex, sorted.
+    skin_joints: Vec<u16>,
 }
 
 #[derive(Default)]
 pub struct Model {
-    materials: Vec<Rc<Material>>,
+    materials: Vec<Material>,
     meshes: Vec<Mesh>,
+    aabb: Aabb,
+    bounding_sphere: BoundingSphere,
+    skeleton: Option<Skeleton>,
+    animations: Vec<AnimationClip>,
+    /// Current pose of `skeleton`, see `animate`.
+    joint_matrices: Vec<Mat4>,
+    /// `joint_matrices` on the GPU.
+    joint_buffer: JointBuffer,
+    /// Coarser versions of `meshes`, most detailed first.
+    lods: Vec<Vec<Mesh>>,
+    lod_selector: LodSelector,
 }
 
-impl Mesh {
-    pub fn new(vertices: Vec<Vertex>, indices: Vec<GLuint>, material: Rc<Material>) -> Mesh{
-        let mut vao = 0;
-        let mut vbo = 0;
-        let mut ebo = 0;
+/// CPU side of a mesh, produced by parsing and consumed by `Model::from_data`.
+pub struct MeshData {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<GLuint>,
+    pub material: usize,
+    /// One entry per vertex for meshes deformed by the skeleton.
+    pub skin: Option<Vec<SkinWeights>>,
+    pub morph_targets: Vec<MorphTarget>,
+    /// Initial weight of each morph target.
+    pub morph_weights: Vec<f32>,
+    /// Skeleton node of the mesh, for morph weight animation.
+    pub node: Option<usize>,
+}
+
+/// Everything needed to build a `Model` without touching GL, so it can be
+/// produced on a worker thread.
+pub struct ModelData {
+    pub materials: Vec<MaterialData>,
+    pub meshes: Vec<MeshData>,
+    pub skeleton: Option<Skeleton>,
+    pub animations: Vec<AnimationClip>,
+    /// Coarser versions of `meshes`, most detailed first. Their materials,
+    /// skins and nodes refer to the same lists as `meshes`.
+    pub lods: Vec<Vec<MeshData>>,
+}
+
+/// How normals are generated for meshes that have none.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NormalMode {
+    Smooth,
+    Flat,
+}
+
+/// Decimation algorithm, see `MeshData::simplify`.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Simplification {
+    /// Fast grid snapping, which ignores the shape within each cell.
+    Clustering,
+    /// Edge collapses ordered by quadric error, keeping seams and borders.
+    Quadric,
+}
+
+#[derive(Clone, Debug)]
+pub struct ImportOptions {
+    pub normals: NormalMode,
+    /// Extra directories searched for MTL files and textures that are not
+    /// found next to the OBJ file.
+    pub search_paths: Vec<PathBuf>,
+    /// Triangle count of each generated level of detail relative to the
+    /// full mesh, e.g. `[0.5, 0.25]` for two extra levels.
+    pub lod_ratios: Vec<f32>,
+    pub lod_simplification: Simplification,
+    /// Merges vertices whose attributes are all identical.
+    pub weld_vertices: bool,
+    /// Decimates every mesh to this fraction of its triangles.
+    pub simplify_ratio: Option<f32>,
+    /// Reorders triangles for the vertex cache and overdraw, and vertices
+    /// for fetch locality.
+    pub optimize: bool,
+    /// Uploads all meshes of a level of detail into one vertex and index
+    /// buffer and draws them with base vertex offsets, see
+    /// `Model::from_data_batched`.
+    pub batch_meshes: bool,
+}
+
+impl Default for ImportOptions {
+    fn default() -> ImportOptions {
+        ImportOptions {
+            normals: NormalMode::Smooth,
+            search_paths: Vec::new(),
+            lod_ratios: Vec::new(),
+            lod_simplification: Simplification::Quadric,
+            weld_vertices: false,
+            simplify_ratio: None,
+            optimize: false,
+            batch_meshes: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ModelError {
+    Io(std::io::Error),
+    Obj(tobj::LoadError),
+    Mtl(tobj::LoadError),
+    MismatchedAttributes { mesh: String, attribute: &'static str },
+    IncompleteTriangle { mesh: String },
+    IndexOutOfBounds { mesh: String, index: u32, vertex_count: usize },
+    Texture { path: PathBuf, message: String },
+    Gltf(gltf::Error),
+    TooManyJoints { count: usize, max: usize },
+    TooManyMorphTargets { mesh: String, count: usize, max: usize },
+    InvalidNodeHierarchy { node: usize },
+    JointOutOfBounds { mesh: String, joint: u16, joint_count: usize },
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModelError::Io(e) => write!(f, "{}", e),
+            ModelError::Obj(e) => write!(f, "OBJ: {}", e),
+            ModelError::Mtl(e) => write!(f, "MTL: {}", e),
+            ModelError::MismatchedAttributes { mesh, attribute } => {
+                write!(f, "mesh '{}': {} do not match the vertex count", mesh, attribute)
+            }
+            ModelError::IncompleteTriangle { mesh } => {
+                write!(f, "mesh '{}': index count is not a multiple of 3", mesh)
+            }
+            ModelError::IndexOutOfBounds { mesh, index, vertex_count } => write!(
+                f,
+                "mesh '{}': index {} out of bounds for {} vertices",
+                mesh, index, vertex_count
+            ),
+            ModelError::Texture { path, message } => write!(f, "texture {}: {}", path.display(), message),
+            ModelError::Gltf(e) => write!(f, "glTF: {}", e),
+            ModelError::TooManyJoints { count, max } => {
+                write!(f, "{} joints, at most {} are supported", count, max)
+            }
+            ModelError::TooManyMorphTargets { mesh, count, max } => write!(
+                f,
+                "mesh '{}': {} morph targets, at most {} are supported",
+                mesh, count, max
+            ),
+            ModelError::InvalidNodeHierarchy { node } => {
+                write!(f, "node {} has several parents or is part of a cycle", node)
+            }
+            ModelError::JointOutOfBounds { mesh, joint, joint_count } => write!(
+                f,
+                "mesh '{}': joint {} out of bounds for a skin of {} joints",
+                mesh, joint, joint_count
+            ),
+        }
+    }
+}
+
+impl Error for ModelError {}
+
+/// Points attributes 0 (position), 1 (normal) and 2 (texture coordinates)
+/// of the bound vertex array at `Vertex`es in the bound `ARRAY_BUFFER`.
+pub(super) unsafe fn set_vertex_attributes() {
+    gl::EnableVertexAttribArray(0);
+    gl::VertexAttribPointer(
+        0,
+        3,
+        gl::FLOAT,
+        gl::FALSE,
+        (std::mem::size_of::<Vertex>()) as GLsizei,
+        (0 * std::mem::size_of::<f32>()) as *const GLvoid,
+    );
+
+    gl::EnableVertexAttribArray(1);
+    gl::VertexAttribPointer(
+        1,
+        3,
+        gl::FLOAT,
+        gl::FALSE,
+        (std::mem::size_of::<Vertex>()) as GLsizei,
+        (3 * std::mem::size_of::<f32>()) as *const GLvoid,
+    );
+
+    gl::EnableVertexAttribArray(2);
+    gl::VertexAttribPointer(
+        2,
+        2,
+        gl::FLOAT,
+        gl::FALSE,
+        (std::mem::size_of::<Vertex>()) as GLsizei,
+        (6 * std::mem::size_of::<f32>()) as *const GLvoid,
+    );
+}
+
+/// Index type for meshes of at most `vertex_count` vertices.
+pub(super) fn index_type(vertex_count: usize) -> GLenum {
+    if vertex_count <= u16::MAX as usize + 1 {
+        gl::UNSIGNED_SHORT
+    } else {
+        gl::UNSIGNED_INT
+    }
+}
+
+pub(super) fn index_size(index_type: GLenum) -> usize {
+    match index_type {
+        gl::UNSIGNED_SHORT => std::mem::size_of::<u16>(),
+        _ => std::mem::size_of::<GLuint>(),
+    }
+}
+
+impl MeshBuffers {
+    /// Uploads `indices` as `index_type` and, unless `skin` is empty, the
+    /// joint influences to attributes 8 (joints) and 9 (weights).
+    fn new(vertices: &[Vertex], indices: &[GLuint], index_type: GLenum, skin: &[SkinWeights]) -> MeshBuffers {
+        let mut buffers = MeshBuffers {
+            vao: 0,
+            vbo: 0,
+            ebo: 0,
+            skin_vbo: 0,
+            index_type,
+        };
+
+        let short_indices: Vec<u16>;
+        let (index_data, index_bytes) = if index_type == gl::UNSIGNED_SHORT {
+            short_indices = indices.iter().map(|&i| i as u16).collect();
+            (short_indices.as_ptr() as *const std::ffi::c_void, short_indices.len() * std::mem::size_of::<u16>())
+        } else {
+            (indices.as_ptr() as *const std::ffi::c_void, indices.len() * std::mem::size_of::<GLuint>())
+        };
 
         unsafe {
-            gl::GenVertexArrays(1, &mut vao);
+            gl::GenVertexArrays(1, &mut buffers.vao);
 
-            gl::GenBuffers(1, &mut vbo);
-            gl::GenBuffers(1, &mut ebo);
+            gl::GenBuffers(1, &mut buffers.vbo);
+            gl::GenBuffers(1, &mut buffers.ebo);
+            leak_tracker::created(GlObject::VertexArray, buffers.vao);
+            leak_tracker::created(GlObject::Buffer, buffers.vbo);
+            leak_tracker::created(GlObject::Buffer, buffers.ebo);
 
-            gl::BindVertexArray(vao);
+            gl::BindVertexArray(buffers.vao);
 
-            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, buffers.vbo);
 
             gl::BufferData(
                 gl::ARRAY_BUFFER,
@@ -63,149 +410,647 @@ impl Mesh {
                 gl::STATIC_DRAW,
             );
 
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, buffers.ebo);
 
             gl::BufferData(
                 gl::ELEMENT_ARRAY_BUFFER,
-                (indices.len() * std::mem::size_of::<GLuint>()) as GLsizeiptr,
-                indices.as_ptr() as *const std::ffi::c_void,
+                index_bytes as GLsizeiptr,
+                index_data,
                 gl::STATIC_DRAW,
             );
 
-            gl::EnableVertexAttribArray(0);
-            gl::VertexAttribPointer(
-                0,
-                3,
-                gl::FLOAT,
-                gl::FALSE,
-                (std::mem::size_of::<Vertex>()) as GLsizei,
-                (0 * std::mem::size_of::<f32>()) as *const GLvoid,
-            );
+            set_vertex_attributes();
 
-            gl::EnableVertexAttribArray(1);
-            gl::VertexAttribPointer(
-                1,
-                3,
-                gl::FLOAT,
-                gl::FALSE,
-                (std::mem::size_of::<Vertex>()) as GLsizei,
-                (3 * std::mem::size_of::<f32>()) as *const GLvoid,
-            );
+            if !skin.is_empty() {
+                gl::GenBuffers(1, &mut buffers.skin_vbo);
+                leak_tracker::created(GlObject::Buffer, buffers.skin_vbo);
+                gl::BindBuffer(gl::ARRAY_BUFFER, buffers.skin_vbo);
+                gl::BufferData(
+                    gl::ARRAY_BUFFER,
+                    (skin.len() * std::mem::size_of::<SkinWeights>()) as GLsizeiptr,
+                    skin.as_ptr() as *const std::ffi::c_void,
+                    gl::STATIC_DRAW,
+                );
 
-            gl::EnableVertexAttribArray(2);
-            gl::VertexAttribPointer(
-                2,
-                2,
-                gl::FLOAT,
-                gl::FALSE,
-                (std::mem::size_of::<Vertex>()) as GLsizei,
-                (6 * std::mem::size_of::<f32>()) as *const GLvoid,
-            );
+                gl::EnableVertexAttribArray(8);
+                gl::VertexAttribIPointer(
+                    8,
+                    4,
+                    gl::UNSIGNED_SHORT,
+                    (std::mem::size_of::<SkinWeights>()) as GLsizei,
+                    0 as *const GLvoid,
+                );
+
+                gl::EnableVertexAttribArray(9);
+                gl::VertexAttribPointer(
+                    9,
+                    4,
+                    gl::FLOAT,
+                    gl::FALSE,
+                    (std::mem::size_of::<SkinWeights>()) as GLsizei,
+                    (4 * std::mem::size_of::<u16>()) as *const GLvoid,
+                );
+            }
             
             gl::BindVertexArray(0);
         }
 
+        buffers
+    }
+}
+
+impl Drop for MeshBuffers {
+    fn drop(&mut self) {
+        leak_tracker::deleted(GlObject::VertexArray, self.vao);
+        leak_tracker::deleted(GlObject::Buffer, self.vbo);
+        leak_tracker::deleted(GlObject::Buffer, self.ebo);
+        leak_tracker::deleted(GlObject::Buffer, self.skin_vbo);
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteBuffers(1, &self.ebo);
+            // Deleting 0 is a no-op for unskinned meshes.
+            gl::DeleteBuffers(1, &self.skin_vbo);
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl Mesh {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<GLuint>, material: usize) -> Mesh{
+        Mesh::with_skin(vertices, indices, Vec::new(), material)
+    }
+
+    /// A mesh with its own buffers. `skin` is empty or has one entry per
+    /// vertex.
+    fn with_skin(vertices: Vec<Vertex>, indices: Vec<GLuint>, skin: Vec<SkinWeights>, material: usize) -> Mesh {
+        assert!(skin.is_empty() || skin.len() == vertices.len(), "one skin entry is needed per vertex");
+
+        let buffers = MeshBuffers::new(&vertices, &indices, index_type(vertices.len()), &skin);
+        Mesh::in_buffers(vertices, indices, skin, material, Rc::new(buffers), 0, 0)
+    }
+
+    /// Packs `meshes` into one set of buffers, one after another, so that
+    /// drawing them needs no buffer or vertex array changes in between.
+    fn batch(meshes: Vec<MeshData>) -> Vec<Mesh> {
+        let largest = meshes.iter().map(|mesh| mesh.vertices.len()).max().unwrap_or(0);
+        let any_skinned = meshes.iter().any(|mesh| mesh.skin.is_some());
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut skin = Vec::new();
+        for mesh in &meshes {
+            vertices.extend_from_slice(&mesh.vertices);
+            indices.extend_from_slice(&mesh.indices);
+            // Unskinned meshes get empty influences, which their draws
+            // ignore, to keep the skin stream aligned with the vertices.
+            if any_skinned {
+                match &mesh.skin {
+                    Some(mesh_skin) => skin.extend_from_slice(mesh_skin),
+                    None => skin.resize(vertices.len(), SkinWeights::default()),
+                }
+            }
+        }
+
+        let index_type = index_type(largest);
+        let buffers = Rc::new(MeshBuffers::new(&vertices, &indices, index_type, &skin));
+
+        let mut base_vertex = 0;
+        let mut index_offset = 0;
+        meshes.into_iter()
+            .map(|mut mesh| {
+                let (vertex_count, index_count) = (mesh.vertices.len(), mesh.indices.len());
+                let vertices = std::mem::take(&mut mesh.vertices);
+                let indices = std::mem::take(&mut mesh.indices);
+                let skin = mesh.skin.take().unwrap_or_default();
+                let mut gpu_mesh = Mesh::in_buffers(vertices, indices, skin, mesh.material,
+                                                    buffers.clone(), base_vertex, index_offset);
+                gpu_mesh.set_morph_data(mesh);
+                base_vertex += vertex_count as GLint;
+                index_offset += index_count * index_size(index_type);
+                gpu_mesh
+            })
+            .collect()
+    }
+
+    fn in_buffers(
+        vertices: Vec<Vertex>,
+        indices: Vec<GLuint>,
+        skin: Vec<SkinWeights>,
+        material: usize,
+        buffers: Rc<MeshBuffers>,
+        base_vertex: GLint,
+        index_offset: usize,
+    ) -> Mesh {
+        let positions = vertices.iter().map(|vertex| vertex.position);
+        let aabb = Aabb::from_points(positions.clone());
+        let bounding_sphere = BoundingSphere::from_points(positions);
+
+        let mut skin_joints: Vec<u16> = skin.iter()
+            .flat_map(|influence| {
+                let (joints, weights) = (influence.joints, influence.weights);
+                (0..4).filter(move |&i| weights[i] != 0.0).map(move |i| joints[i])
+            })
+            .collect();
+        skin_joints.sort_unstable();
+        skin_joints.dedup();
+
         Mesh {
             vertices,
             indices,
+            skin,
+            buffers,
+            base_vertex,
+            index_offset,
+            morph_targets: Vec::new(),
+            morph_weights: Vec::new(),
+            morph_extents: Vec::new(),
+            morph_buffer: 0,
+            morph_texture: 0,
+            node: None,
             material,
-            vao,
+            aabb,
+            bounding_sphere,
+            bind_aabb: aabb,
+            skin_joints,
         }
     }
 
-    pub fn draw(&self, shader: &Shader) {
+    /// Moves the bounds to the current morph weights and, if skinned, the
+    /// pose of `joint_matrices`.
+    ///
+    /// Each target moves a vertex by at most its weight times the range of
+    /// the target's deltas. Skinning then puts the vertex at a weighted
+    /// average of where its joints take it, so it stays within the morphed
+    /// box transformed by each of them.
+    fn pose_bounds(&mut self, joint_matrices: &[Mat4]) {
+        if self.skin_joints.is_empty() && self.morph_extents.is_empty() {
+            return;
+        }
+
+        let morphed = self.morph_extents.iter()
+            .zip(&self.morph_weights)
+            .fold(self.bind_aabb, |aabb, (extents, &weight)| aabb.displace(extents, weight));
+        self.aabb = if self.skin_joints.is_empty() {
+            morphed
+        } else {
+            self.skin_joints.iter()
+                .filter_map(|&joint| joint_matrices.get(joint as usize))
+                .fold(Aabb::EMPTY, |aabb, matrix| aabb.union(&morphed.transform(matrix)))
+        };
+        self.bounding_sphere = BoundingSphere::new(self.aabb.center(), self.aabb.extents().length());
+    }
+
+    /// Takes the morph targets and node of `mesh`, whose geometry this is.
+    fn set_morph_data(&mut self, mesh: MeshData) {
+        if !mesh.morph_targets.is_empty() {
+            self.set_morph_targets(mesh.morph_targets, mesh.morph_weights);
+        }
+        self.node = mesh.node;
+    }
+
+    /// Uploads blend shapes into a buffer texture the vertex shader reads
+    /// with `gl_VertexID`. `Model::from_data` has checked that they fit
+    /// `MAX_TEXTURE_BUFFER_SIZE`.
+    fn set_morph_targets(&mut self, targets: Vec<MorphTarget>, weights: Vec<f32>) {
+        let vertex_count = self.vertices.len();
+        // RGBA32F rather than RGB32F, which needs GL 4.0 for buffer textures.
+        let mut deltas: Vec<Vec4> = Vec::with_capacity(targets.len() * vertex_count * 2);
+        for target in &targets {
+            for i in 0..vertex_count {
+                let position = target.positions.get(i).copied().unwrap_or(Vec3::ZERO);
+                let normal = target.normals.get(i).copied().unwrap_or(Vec3::ZERO);
+                deltas.push(position.extend(0.0));
+                deltas.push(normal.extend(0.0));
+            }
+        }
+
+        unsafe {
+            if self.morph_buffer == 0 {
+                gl::GenBuffers(1, &mut self.morph_buffer);
+                gl::GenTextures(1, &mut self.morph_texture);
+                leak_tracker::created(GlObject::Buffer, self.morph_buffer);
+                leak_tracker::created(GlObject::Texture, self.morph_texture);
+            }
+
+            gl::BindBuffer(gl::TEXTURE_BUFFER, self.morph_buffer);
+            gl::BufferData(gl::TEXTURE_BUFFER,
+                           (deltas.len() * std::mem::size_of::<Vec4>()) as GLsizeiptr,
+                           deltas.as_ptr() as *const GLvoid,
+                           gl::STATIC_DRAW);
+            gl::BindBuffer(gl::TEXTURE_BUFFER, 0);
+
+            gl::BindTexture(gl::TEXTURE_BUFFER, self.morph_texture);
+            gl::TexBuffer(gl::TEXTURE_BUFFER, gl::RGBA32F, self.morph_buffer);
+            gl::BindTexture(gl::TEXTURE_BUFFER, 0);
+        }
+
+        // Vertices without a delta stay put.
+        self.morph_extents = targets.iter()
+            .map(|target| Aabb::from_points(target.positions.iter().copied().chain([Vec3::ZERO])))
+            .collect();
+        self.morph_weights = weights;
+        self.morph_weights.resize(targets.len(), 0.0);
+        self.morph_targets = targets;
+    }
+
+    pub fn morph_target_count(&self) -> usize {
+        self.morph_targets.len()
+    }
+
+    pub fn morph_targets(&self) -> &[MorphTarget] {
+        &self.morph_targets
+    }
+
+    pub fn morph_weights(&self) -> &[f32] {
+        &self.morph_weights
+    }
+
+    /// Missing weights are zero, extra ones are ignored.
+    pub fn set_morph_weights(&mut self, weights: &[f32]) {
+        for (i, weight) in self.morph_weights.iter_mut().enumerate() {
+            *weight = weights.get(i).copied().unwrap_or(0.0);
+        }
+    }
+
+    pub fn node(&self) -> Option<usize> {
+        self.node
+    }
+
+    /// The sampler always gets its own unit: a `samplerBuffer` left on unit 0
+    /// next to the material's `sampler2D`s fails validation.
+    fn apply_morph_targets(&self, shader: &Shader) {
+        shader.set_i32("morph_deltas", MORPH_DELTAS_UNIT as i32);
+        shader.set_i32("morph_target_count", self.morph_targets.len() as i32);
+        if self.morph_targets.is_empty() {
+            return;
+        }
+
+        shader.set_i32("morph_vertex_count", self.vertices.len() as i32);
+        // gl_VertexID counts from the start of the shared buffer.
+        shader.set_i32("morph_base_vertex", self.base_vertex);
+        shader.set_f32_array("morph_weights", &self.morph_weights);
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + MORPH_DELTAS_UNIT);
+            gl::BindTexture(gl::TEXTURE_BUFFER, self.morph_texture);
+        }
+    }
+
+    /// Like `morph_deltas`, `joint_matrices` gets its unit even when unused.
+    fn apply_skinning(&self, shader: &Shader) {
+        shader.set_i32("skinned", self.is_skinned() as i32);
+        shader.set_i32("joint_matrices", JOINT_MATRICES_UNIT as i32);
+    }
+
+    pub fn is_skinned(&self) -> bool {
+        !self.skin.is_empty()
+    }
+
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &[GLuint] {
+        &self.indices
+    }
+
+    /// Joint influences per vertex, empty unless skinned.
+    pub fn skin(&self) -> &[SkinWeights] {
+        &self.skin
+    }
+
+    pub fn material(&self) -> usize {
+        self.material
+    }
+
+    /// Bounds in model space, following the pose of skinned meshes.
+    pub fn aabb(&self) -> &Aabb {
+        &self.aabb
+    }
+
+    pub fn bounding_sphere(&self) -> &BoundingSphere {
+        &self.bounding_sphere
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.vertices.len() * std::mem::size_of::<Vertex>()
+            + self.indices.len() * index_size(self.buffers.index_type)
+            + self.skin.len() * std::mem::size_of::<SkinWeights>()
+            + self.morph_targets.len() * self.vertices.len() * 2 * std::mem::size_of::<Vec4>()
+    }
+
+    /// Skinned meshes expect their model's joint matrices to be bound, see
+    /// `Model::bind_joint_matrices`.
+    pub fn draw(&self, shader: &Shader, material: &Material) {
         shader.use_shader();
 
-        if let Some(difuse_texture) = &self.material.diffuse_texture {
-            shader.set_i32("material.diffuse_tex", 0 as i32);
-            difuse_texture.bind(0);
+        material.apply(shader);
+        self.apply_skinning(shader);
+        self.apply_morph_targets(shader);
+
+        unsafe {
+            gl::BindVertexArray(self.buffers.vao);
+            gl::DrawElementsBaseVertex(gl::TRIANGLES,
+                                       self.indices.len() as GLsizei,
+                                       self.buffers.index_type,
+                                       self.index_offset as *const GLvoid,
+                                       self.base_vertex);
+
         }
+    }
 
-        if let Some(specular_texture) = &self.material.specular_texture {
-            shader.set_i32("material.specular_tex", 1 as i32);
-            specular_texture.bind(1);
+    /// Draws one copy of the mesh per entry in `instances`. The shader reads
+    /// the instance transform and color from attributes 3-7 while its
+    /// `instanced` uniform is set.
+    pub fn draw_instanced(&self, shader: &Shader, material: &Material, instances: &InstanceBuffer) {
+        if instances.is_empty() {
+            return;
         }
 
-        shader.set_vec3v("material.ambient", &self.material.ambient_color);
-        shader.set_vec3v("material.diffuse", &self.material.diffuse_color);
-        shader.set_vec3v("material.specular", &self.material.specular_color);
-        shader.set_f32("material.shininess", self.material.shininess);
+        shader.use_shader();
+
+        material.apply(shader);
+        self.apply_skinning(shader);
+        self.apply_morph_targets(shader);
+        shader.set_i32("instanced", 1);
 
         unsafe {
-            gl::BindVertexArray(self.vao);
-            gl::DrawElements(gl::TRIANGLES,
-                             self.indices.len() as GLsizei,
-                             gl::UNSIGNED_INT,
-                             0 as *const GLvoid);
+            gl::BindVertexArray(self.buffers.vao);
+            instances.bind_attributes();
+            gl::DrawElementsInstancedBaseVertex(gl::TRIANGLES,
+                                                self.indices.len() as GLsizei,
+                                                self.buffers.index_type,
+                                                self.index_offset as *const GLvoid,
+                                                instances.len() as GLsizei,
+                                                self.base_vertex);
+            InstanceBuffer::unbind_attributes();
+            gl::BindVertexArray(0);
+        }
 
+        shader.set_i32("instanced", 0);
+    }
+}
+
+/// The vertex and index buffers go with the last mesh sharing them.
+impl Drop for Mesh {
+    fn drop(&mut self) {
+        if self.morph_buffer == 0 {
+            return;
+        }
+        leak_tracker::deleted(GlObject::Buffer, self.morph_buffer);
+        leak_tracker::deleted(GlObject::Texture, self.morph_texture);
+        unsafe {
+            gl::DeleteTextures(1, &self.morph_texture);
+            gl::DeleteBuffers(1, &self.morph_buffer);
         }
     }
 }
 
-impl Model {
-    pub fn new(path: &str) -> Result<Model, Box<dyn Error>> {
-        let mut materials = Vec::new();
-        let mut meshes = Vec::new();
+fn target_triangles(mesh: &MeshData, ratio: f32) -> usize {
+    ((mesh.indices.len() / 3) as f32 * ratio).max(1.0) as usize
+}
 
+impl MeshData {
+    /// The vertices at `kept`, with their skin and morph target deltas.
+    /// `indices` index into `kept`.
+    pub fn select_vertices(&self, kept: &[GLuint], indices: Vec<GLuint>) -> MeshData {
+        fn select<T: Copy>(values: &[T], kept: &[GLuint]) -> Vec<T> {
+            kept.iter().map(|&i| values[i as usize]).collect()
+        }
 
-        let mut tobj_load_options = tobj::LoadOptions::default();
-        tobj_load_options.single_index = true;
-        tobj_load_options.triangulate = true;
+        MeshData {
+            vertices: select(&self.vertices, kept),
+            indices,
+            material: self.material,
+            skin: self.skin.as_ref().map(|skin| select(skin, kept)),
+            morph_targets: self.morph_targets.iter()
+                .map(|target| MorphTarget {
+                    positions: select(&target.positions, kept),
+                    normals: if target.normals.is_empty() { Vec::new() } else { select(&target.normals, kept) },
+                })
+                .collect(),
+            morph_weights: self.morph_weights.clone(),
+            node: self.node,
+        }
+    }
 
+    /// Merges vertices that are bit-identical in every attribute, including
+    /// skin weights and morph target deltas.
+    pub fn weld(&self) -> MeshData {
+        let mut unique: HashMap<Vec<u32>, GLuint> = HashMap::new();
+        let mut kept = Vec::new();
+        let mut remap = Vec::with_capacity(self.vertices.len());
 
-        let (models, tobj_materials) =
-            tobj::load_obj(path, &tobj_load_options)?;
+        for (i, vertex) in self.vertices.iter().enumerate() {
+            let (position, normal, tex_coords) = (vertex.position, vertex.normal, vertex.tex_coords);
+            let mut key: Vec<f32> = Vec::new();
+            key.extend(position.to_array());
+            key.extend(normal.to_array());
+            key.extend(tex_coords.to_array());
+            if let Some(skin) = &self.skin {
+                key.extend(skin[i].joints.map(f32::from));
+                key.extend(skin[i].weights);
+            }
+            for target in &self.morph_targets {
+                key.extend(target.positions[i].to_array());
+                if let Some(normal) = target.normals.get(i) {
+                    key.extend(normal.to_array());
+                }
+            }
 
-        let tobj_materials = tobj_materials?;
-        
-        for tobj_material in &tobj_materials {
-            let mut diffuse_texture = None;
-            let mut specular_texture = None;
+            let key = key.into_iter().map(f32::to_bits).collect();
+            let index = *unique.entry(key).or_insert_with(|| {
+                kept.push(i as GLuint);
+                kept.len() as GLuint - 1
+            });
+            remap.push(index);
+        }
+
+        let indices = self.indices.iter().map(|&i| remap[i as usize]).collect();
+        self.select_vertices(&kept, indices)
+    }
+
+    /// Decimates the mesh to about `target_triangles`.
+    pub fn simplify(&self, target_triangles: usize, simplification: Simplification) -> MeshData {
+        let (kept, indices) = match simplification {
+            Simplification::Clustering => geometry::simplify_clustered(&self.vertices, &self.indices, target_triangles),
+            Simplification::Quadric => simplify::simplify(&self.vertices, &self.indices, target_triangles),
+        };
+        self.select_vertices(&kept, indices)
+    }
+
+    /// Reorders triangles for the post-transform vertex cache, then for
+    /// overdraw unless that costs more than 5% of the cache hits, then
+    /// vertices in the order they are used.
+    pub fn optimize(&self) -> MeshData {
+        let cache_optimized = optimize::optimize_vertex_cache(&self.indices, self.vertices.len());
+        let overdraw_optimized = optimize::optimize_overdraw(&self.vertices, &cache_optimized);
+        let miss_ratio = |indices: &[GLuint]| optimize::average_cache_miss_ratio(indices, 16);
+        let indices = if miss_ratio(&overdraw_optimized) <= miss_ratio(&cache_optimized) * 1.05 {
+            overdraw_optimized
+        } else {
+            cache_optimized
+        };
+
+        let (kept, indices) = optimize::optimize_vertex_fetch(&indices, self.vertices.len());
+        self.select_vertices(&kept, indices)
+    }
+}
+
+#[allow(dead_code)]
+impl ModelData {
+    /// Parses an OBJ or, by extension, a glTF (`.gltf`/`.glb`) file.
+    pub fn from_path(path: &str, options: &ImportOptions) -> Result<ModelData, ModelError> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        let mut data = match extension.as_deref() {
+            Some("gltf") | Some("glb") => ModelData::from_gltf(path, options)?,
+            _ => ModelData::from_obj_with_options(path, options)?,
+        };
+
+        data.process(options);
+        Ok(data)
+    }
+
+    /// Like `from_path`, but loads the result of an earlier import from
+    /// `cache` when the source files and options are unchanged, and stores
+    /// it there otherwise.
+    pub fn from_path_cached(path: &str, options: &ImportOptions, cache: &MeshCache) -> Result<ModelData, ModelError> {
+        let source_hash = match MeshCache::source_hash(Path::new(path), options) {
+            Ok(source_hash) => source_hash,
+            // Let the import report the missing file.
+            Err(_) => return ModelData::from_path(path, options),
+        };
+
+        if let Some(data) = cache.load(Path::new(path), options, source_hash) {
+            return Ok(data);
+        }
+
+        let data = ModelData::from_path(path, options)?;
+        if let Err(e) = cache.store(Path::new(path), options, source_hash, &data) {
+            eprintln!("mesh cache : could not store {}: {}", path, e);
+        }
+        Ok(data)
+    }
 
-            if !tobj_material.diffuse_texture.is_empty() {
-                diffuse_texture = Some(Texture::from_path(tobj_material.diffuse_texture.as_str())?);
+    /// The import-time processing stage: welding and simplification of the
+    /// meshes, level of detail generation, then index and vertex reordering
+    /// of every level.
+    pub fn process(&mut self, options: &ImportOptions) {
+        for mesh in &mut self.meshes {
+            if options.weld_vertices {
+                *mesh = mesh.weld();
             }
-            
-            if !tobj_material.specular_texture.is_empty() {
-                specular_texture = Some(Texture::from_path(tobj_material.specular_texture.as_str())?);
+            if let Some(ratio) = options.simplify_ratio {
+                *mesh = mesh.simplify(target_triangles(mesh, ratio), Simplification::Quadric);
             }
+        }
 
+        if !options.lod_ratios.is_empty() {
+            self.generate_lods(&options.lod_ratios, options.lod_simplification);
+        }
 
+        if options.optimize {
+            for mesh in self.meshes.iter_mut().chain(self.lods.iter_mut().flatten()) {
+                *mesh = mesh.optimize();
+            }
+        }
+    }
 
-            let ambient_color = Vec3::from_slice(&tobj_material.ambient);
-            let diffuse_color = Vec3::from_slice(&tobj_material.diffuse);
-            let specular_color = Vec3::from_slice(&tobj_material.specular);
-            let shininess =  tobj_material.shininess;
+    pub fn from_gltf(path: &str, options: &ImportOptions) -> Result<ModelData, ModelError> {
+        gltf_import::load(path, options)
+    }
 
+    pub fn from_obj(path: &str) -> Result<ModelData, ModelError> {
+        ModelData::from_obj_with_options(path, &ImportOptions::default())
+    }
 
-            let material = Rc::new(Material {
-                name: tobj_material.name.clone(),
-                ambient_color,
-                diffuse_color,
-                specular_color,
-                diffuse_texture,
-                specular_texture,
-                shininess,
-            });
+    pub fn from_obj_with_options(path: &str, options: &ImportOptions) -> Result<ModelData, ModelError> {
+        let mut materials = Vec::new();
+        let mut meshes = Vec::new();
+
+
+        let mut tobj_load_options = tobj::LoadOptions::default();
+        tobj_load_options.single_index = true;
+        tobj_load_options.triangulate = true;
+
+
+        // Everything the OBJ references is looked up relative to it rather
+        // than to the working directory.
+        let base_dir = match Path::new(path).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
+        let file = File::open(path).map_err(ModelError::Io)?;
+        let mut reader = BufReader::new(file);
+
+        let explicit_dissolve = RefCell::new(HashSet::new());
+        let (models, tobj_materials) =
+            tobj::load_obj_buf(&mut reader, &tobj_load_options, |mtl_path| {
+                let mtl_name = mtl_path.to_string_lossy();
+                let resolved = mtl::resolve_path(&mtl_name, &base_dir, &options.search_paths);
+                let source = fs::read(resolved).map_err(|_| tobj::LoadError::OpenFileFailed)?;
+                explicit_dissolve
+                    .borrow_mut()
+                    .extend(mtl::materials_with_dissolve(&String::from_utf8_lossy(&source)));
+                tobj::load_mtl_buf(&mut source.as_slice())
+            }).map_err(ModelError::Obj)?;
+        let explicit_dissolve = explicit_dissolve.into_inner();
 
-            materials.push(material);
+        let tobj_materials = match tobj_materials {
+            Ok(tobj_materials) => tobj_materials,
+            Err(tobj::LoadError::OpenFileFailed) => {
+                eprintln!("model load : {}: material library not found, using default material", path);
+                Vec::new()
+            }
+            Err(e) => return Err(ModelError::Mtl(e)),
+        };
+
+        for tobj_material in &tobj_materials {
+            let has_dissolve = explicit_dissolve.contains(&tobj_material.name);
+            materials.push(MaterialData::from_tobj(tobj_material, has_dissolve, &base_dir, &options.search_paths));
         }
 
+        let mut default_material = None;
+
         for model in models {
             let tobj_mesh = &model.mesh;
-            
-        
-            let mut vertices = Vec::new();
-            let mut indices = Vec::new();
-            
+
+            if tobj_mesh.indices.is_empty() {
+                continue;
+            }
 
             let vertices_count = tobj_mesh.positions.len()/3;
-            let normals_count = tobj_mesh.normals.len()/3;
-            let texcoords_count = tobj_mesh.texcoords.len()/2;
+            let has_normals = !tobj_mesh.normals.is_empty();
+            let has_texcoords = !tobj_mesh.texcoords.is_empty();
 
-            assert_eq!(vertices_count, normals_count);
-            assert_eq!(normals_count, texcoords_count);
+            if has_normals && tobj_mesh.normals.len() != vertices_count*3 {
+                return Err(ModelError::MismatchedAttributes {
+                    mesh: model.name.clone(),
+                    attribute: "normals",
+                });
+            }
+            if has_texcoords && tobj_mesh.texcoords.len() != vertices_count*2 {
+                return Err(ModelError::MismatchedAttributes {
+                    mesh: model.name.clone(),
+                    attribute: "texcoords",
+                });
+            }
+            if tobj_mesh.indices.len() % 3 != 0 {
+                return Err(ModelError::IncompleteTriangle { mesh: model.name.clone() });
+            }
+            if let Some(&index) = tobj_mesh.indices.iter().find(|&&i| i as usize >= vertices_count) {
+                return Err(ModelError::IndexOutOfBounds {
+                    mesh: model.name.clone(),
+                    index,
+                    vertex_count: vertices_count,
+                });
+            }
 
+            let mut vertices = Vec::with_capacity(vertices_count);
 
             for i in 0..vertices_count {
                 let position = Vec3::new(
@@ -214,16 +1059,24 @@ impl Model {
                     tobj_mesh.positions[i*3 + 2]
                 );
 
-                let normal = Vec3::new(
-                    tobj_mesh.normals[i*3 + 0],
-                    tobj_mesh.normals[i*3 + 1],
-                    tobj_mesh.normals[i*3 + 2]
-                );
+                let normal = if has_normals {
+                    Vec3::new(
+                        tobj_mesh.normals[i*3 + 0],
+                        tobj_mesh.normals[i*3 + 1],
+                        tobj_mesh.normals[i*3 + 2]
+                    )
+                } else {
+                    Vec3::ZERO
+                };
 
-                let tex_coords = Vec2::new(
-                    tobj_mesh.texcoords[i*2 + 0],
-                    tobj_mesh.texcoords[i*2 + 1],
-                    );
+                let tex_coords = if has_texcoords {
+                    Vec2::new(
+                        tobj_mesh.texcoords[i*2 + 0],
+                        tobj_mesh.texcoords[i*2 + 1],
+                    )
+                } else {
+                    Vec2::ZERO
+                };
 
                 let vertex = Vertex{
                     position,
@@ -234,45 +1087,462 @@ impl Model {
                 vertices.push(vertex);
             }
 
-            for idx in &tobj_mesh.indices {
-                indices.push(idx.clone() as GLuint);
+            let mut indices: Vec<GLuint> = tobj_mesh.indices.clone();
+
+            if !has_normals {
+                match options.normals {
+                    NormalMode::Smooth => geometry::generate_smooth_normals(&mut vertices, &indices),
+                    NormalMode::Flat => {
+                        let (flat_vertices, flat_indices) = geometry::generate_flat_normals(&vertices, &indices);
+                        vertices = flat_vertices;
+                        indices = flat_indices;
+                    }
+                }
             }
 
+            let material = match tobj_mesh.material_id {
+                Some(material) if material < materials.len() => material,
+                _ => *default_material.get_or_insert_with(|| {
+                    materials.push(MaterialData::default());
+                    materials.len() - 1
+                }),
+            };
 
-            let tobj_material = &tobj_materials[tobj_mesh.material_id.unwrap()];
+            meshes.push(MeshData {
+                vertices,
+                indices,
+                material,
+                skin: None,
+                morph_targets: Vec::new(),
+                morph_weights: Vec::new(),
+                node: None,
+            });
 
-            let mut material_found = false;
-            let mut mesh_material= materials[0].clone();
+        }
+
+        Ok(ModelData {
+            materials,
+            meshes,
+            skeleton: None,
+            animations: Vec::new(),
+            lods: Vec::new(),
+        })
+    }
 
-            for material in &materials {
-                if tobj_material.name.eq(&material.name) {
-                    mesh_material = material.clone();
-                    material_found = true;
-                    break;
+    /// Appends one level of detail per entry in `ratios`, simplified from
+    /// the full meshes.
+    pub fn generate_lods(&mut self, ratios: &[f32], simplification: Simplification) {
+        for &ratio in ratios {
+            let lod = self.meshes.iter()
+                .map(|mesh| mesh.simplify(target_triangles(mesh, ratio), simplification))
+                .collect();
+            self.lods.push(lod);
+        }
+    }
+
+    /// Appends the meshes of a separately authored model as the next level
+    /// of detail. Its materials are matched to ours by name and added if
+    /// missing. Skins, morph targets and animations of `lod` are dropped, as
+    /// they refer to its own hierarchy.
+    pub fn add_lod(&mut self, lod: ModelData) {
+        let mut material_map = Vec::with_capacity(lod.materials.len());
+        for material in lod.materials {
+            let index = match self.materials.iter().position(|existing| existing.name == material.name) {
+                Some(index) => index,
+                None => {
+                    self.materials.push(material);
+                    self.materials.len() - 1
+                }
+            };
+            material_map.push(index);
+        }
+
+        let meshes = lod.meshes.into_iter()
+            .map(|mesh| MeshData {
+                vertices: mesh.vertices,
+                indices: mesh.indices,
+                material: material_map[mesh.material],
+                skin: None,
+                morph_targets: Vec::new(),
+                morph_weights: Vec::new(),
+                node: None,
+            })
+            .collect();
+        self.lods.push(meshes);
+    }
+
+    /// Every distinct texture file referenced by the materials, with the
+    /// options it is loaded with. A file used both as color and as data is
+    /// listed twice.
+    pub fn texture_paths(&self) -> Vec<(PathBuf, TextureOptions)> {
+        let mut paths: Vec<(PathBuf, TextureOptions)> = Vec::new();
+        for material in &self.materials {
+            for (map, srgb) in material.texture_maps() {
+                let texture = (map.path.clone(), Model::texture_options(srgb));
+                if !paths.contains(&texture) {
+                    paths.push(texture);
                 }
             }
+        }
+        paths
+    }
+}
 
-            if !material_found {
-                return Err("Cannot find material required for mesh".into());
+#[allow(dead_code)]
+impl Model {
+    pub fn new(path: &str) -> Result<Model, ModelError> {
+        Model::load(path, &mut AssetManager::new())
+    }
+
+    /// Loads a model, taking its textures from `assets` so they are shared
+    /// with every other material and model using the same file.
+    pub fn load(path: &str, assets: &mut AssetManager) -> Result<Model, ModelError> {
+        let data = ModelData::from_path(path, &ImportOptions::default())?;
+
+        let mut texture = |path: &Path, options: &TextureOptions| assets.texture(path, options);
+        Model::from_data(data, &mut texture)
+    }
+
+    /// Options used for material maps, with an sRGB internal format for
+    /// color maps, see `SRGB_SLOTS`.
+    pub fn texture_options(srgb: bool) -> TextureOptions {
+        TextureOptions::new()
+            .mipmaps(true)
+            .filter(Filter::Trilinear)
+            .anisotropy(8.0)
+            .srgb(srgb)
+    }
+
+    /// Uploads parsed data. `texture` resolves each referenced file with the
+    /// options of its slot, see `texture_options`, e.g. from an
+    /// `AssetManager` or to a placeholder while it is still loading.
+    ///
+    /// Fails with `TooManyJoints` or `TooManyMorphTargets` if the joint
+    /// matrices or morph target deltas do not fit the driver's buffer
+    /// textures.
+    pub fn from_data<F>(data: ModelData, texture: &mut F) -> Result<Model, ModelError>
+    where
+        F: FnMut(&Path, &TextureOptions) -> Result<Rc<Texture>, Box<dyn Error>>,
+    {
+        Model::upload(data, false, texture)
+    }
+
+    /// Like `from_data`, with the meshes of each level of detail packed
+    /// into one shared set of buffers, see `ImportOptions::batch_meshes`.
+    pub fn from_data_batched<F>(data: ModelData, texture: &mut F) -> Result<Model, ModelError>
+    where
+        F: FnMut(&Path, &TextureOptions) -> Result<Rc<Texture>, Box<dyn Error>>,
+    {
+        Model::upload(data, true, texture)
+    }
+
+    fn upload<F>(data: ModelData, batch_meshes: bool, texture: &mut F) -> Result<Model, ModelError>
+    where
+        F: FnMut(&Path, &TextureOptions) -> Result<Rc<Texture>, Box<dyn Error>>,
+    {
+        let max_texels = max_texture_buffer_size();
+        let joint_count = data.skeleton.as_ref().map_or(0, |skeleton| skeleton.joints.len());
+        if joint_count > max_texels / 4 {
+            return Err(ModelError::TooManyJoints { count: joint_count, max: max_texels / 4 });
+        }
+        // Two texels per vertex and target, see `Mesh::set_morph_targets`.
+        let levels = std::iter::once(&data.meshes).chain(&data.lods);
+        for (level, meshes) in levels.enumerate() {
+            for (index, mesh) in meshes.iter().enumerate() {
+                let texels_per_target = mesh.vertices.len() * 2;
+                if mesh.morph_targets.len() * texels_per_target > max_texels {
+                    return Err(ModelError::TooManyMorphTargets {
+                        mesh: format!("mesh {} at level of detail {}", index, level),
+                        count: mesh.morph_targets.len(),
+                        max: max_texels / texels_per_target,
+                    });
+                }
             }
+        }
 
-            
-            let mesh = Mesh::new(vertices, indices, mesh_material);
-            meshes.push(mesh);
+        let mut materials = Vec::new();
+        let mut meshes = Vec::new();
+
+        let mut material_texture = |map: Option<TextureMap>, srgb: bool| -> Result<Option<MaterialTexture>, ModelError> {
+            match map {
+                Some(map) => {
+                    let options = Model::texture_options(srgb);
+                    let texture = texture(&map.path, &options).map_err(|e| ModelError::Texture {
+                        path: map.path.clone(),
+                        message: e.to_string(),
+                    })?;
+                    Ok(Some(MaterialTexture { map, texture }))
+                }
+                None => Ok(None),
+            }
+        };
 
+        for material in data.materials {
+            materials.push(Material {
+                name: material.name,
+                ambient_color: material.ambient_color,
+                diffuse_color: material.diffuse_color,
+                specular_color: material.specular_color,
+                emissive_color: material.emissive_color,
+                ambient_texture: material_texture(material.ambient_texture, SRGB_SLOTS[0])?,
+                diffuse_texture: material_texture(material.diffuse_texture, SRGB_SLOTS[1])?,
+                specular_texture: material_texture(material.specular_texture, SRGB_SLOTS[2])?,
+                emissive_texture: material_texture(material.emissive_texture, SRGB_SLOTS[3])?,
+                alpha_texture: material_texture(material.alpha_texture, SRGB_SLOTS[4])?,
+                shininess: material.shininess,
+                dissolve: material.dissolve,
+                optical_density: material.optical_density,
+                illumination_model: material.illumination_model,
+                unknown_params: material.unknown_params,
+            });
         }
 
-        Ok(Model{
+        let upload = |meshes: Vec<MeshData>| -> Vec<Mesh> {
+            if batch_meshes {
+                return Mesh::batch(meshes);
+            }
+            meshes.into_iter()
+                .map(|mut mesh| {
+                    let vertices = std::mem::take(&mut mesh.vertices);
+                    let indices = std::mem::take(&mut mesh.indices);
+                    let skin = mesh.skin.take().unwrap_or_default();
+                    let mut gpu_mesh = Mesh::with_skin(vertices, indices, skin, mesh.material);
+                    gpu_mesh.set_morph_data(mesh);
+                    gpu_mesh
+                })
+                .collect()
+        };
+
+        meshes.extend(upload(data.meshes));
+        let lods: Vec<Vec<Mesh>> = data.lods.into_iter().map(upload).collect();
+
+        let aabb = meshes.iter().fold(Aabb::EMPTY, |aabb, mesh| aabb.union(mesh.aabb()));
+        let bounding_sphere = BoundingSphere::from_points(
+            meshes.iter().flat_map(|mesh| mesh.vertices.iter().map(|vertex| vertex.position))
+        );
+
+        let joint_matrices = data.skeleton.as_ref()
+            .map_or_else(Vec::new, |skeleton| skeleton.joint_matrices(&skeleton.rest_pose()));
+        let mut joint_buffer = JointBuffer::default();
+        joint_buffer.upload(&joint_matrices);
+
+        let mut model = Model{
             materials,
-            meshes
-        })
+            meshes,
+            aabb,
+            bounding_sphere,
+            skeleton: data.skeleton,
+            animations: data.animations,
+            joint_matrices,
+            joint_buffer,
+            lod_selector: LodSelector::with_levels(lods.len() + 1),
+            lods,
+        };
+        model.pose_bounds();
+        Ok(model)
+    }
+
+    /// Replaces every material texture loaded from `path` with `options`.
+    pub fn set_texture(&mut self, path: &Path, options: &TextureOptions, texture: Rc<Texture>) {
+        for material in &mut self.materials {
+            for (slot, srgb) in material.texture_slots_mut().into_iter().zip(SRGB_SLOTS) {
+                match slot {
+                    Some(slot) if slot.map.path == path && Model::texture_options(srgb) == *options => {
+                        slot.texture = texture.clone();
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Meshes of the full level of detail.
+    pub fn meshes(&self) -> &[Mesh] {
+        &self.meshes
+    }
+
+    pub fn lod_count(&self) -> usize {
+        self.lods.len() + 1
+    }
+
+    /// Meshes of level of detail `level`, 0 being the full meshes.
+    pub fn lod_meshes(&self, level: usize) -> &[Mesh] {
+        match level {
+            0 => &self.meshes,
+            level => &self.lods[level - 1],
+        }
+    }
+
+    /// The meshes drawn, those of the current level of detail.
+    pub fn active_meshes(&self) -> &[Mesh] {
+        self.lod_meshes(self.lod())
+    }
+
+    pub fn lod(&self) -> usize {
+        self.lod_selector.current()
+    }
+
+    /// Overrides the automatic selection until the next `update_lod`.
+    pub fn set_lod(&mut self, level: usize) {
+        self.lod_selector.set_current(level);
+    }
+
+    pub fn lod_selector_mut(&mut self) -> &mut LodSelector {
+        &mut self.lod_selector
+    }
+
+    /// Selects the level of detail from the size of the model placed at
+    /// `transform` as seen from `camera_position`.
+    pub fn update_lod(&mut self, transform: &Mat4, camera_position: Vec3, fov_y: f32) -> usize {
+        let sphere = self.bounding_sphere.transform(transform);
+        self.lod_selector.select(lod::screen_size(&sphere, camera_position, fov_y))
+    }
+
+    pub fn materials(&self) -> &[Material] {
+        &self.materials
+    }
+
+    /// Bounds of all meshes in model space, following the current pose of
+    /// skinned meshes.
+    pub fn aabb(&self) -> &Aabb {
+        &self.aabb
+    }
+
+    pub fn bounding_sphere(&self) -> &BoundingSphere {
+        &self.bounding_sphere
     }
 
+    pub fn skeleton(&self) -> Option<&Skeleton> {
+        self.skeleton.as_ref()
+    }
+
+    pub fn animations(&self) -> &[AnimationClip] {
+        &self.animations
+    }
+
+    pub fn joint_matrices(&self) -> &[Mat4] {
+        &self.joint_matrices
+    }
+
+    /// Poses the skeleton and sets the morph weights at `time` seconds into
+    /// animation `clip`.
+    pub fn animate(&mut self, clip: usize, time: f32) {
+        if let (Some(skeleton), Some(clip)) = (&self.skeleton, self.animations.get(clip)) {
+            let mut pose = skeleton.rest_pose();
+            clip.sample(time, &mut pose);
+            self.joint_matrices = skeleton.joint_matrices(&pose);
+            self.joint_buffer.upload(&self.joint_matrices);
 
+            let meshes = self.meshes.iter_mut().chain(self.lods.iter_mut().flatten());
+            for mesh in meshes {
+                let weights = mesh.node.and_then(|node| clip.sample_weights(time, node));
+                if let Some(weights) = weights {
+                    mesh.set_morph_weights(&weights);
+                }
+            }
+        }
+        self.pose_bounds();
+    }
 
+    /// Sets the weights of mesh `mesh` and of the generated meshes derived
+    /// from it at every level of detail.
+    pub fn set_morph_weights(&mut self, mesh: usize, weights: &[f32]) {
+        let node = self.meshes[mesh].node;
+        self.meshes[mesh].set_morph_weights(weights);
+        for lod in &mut self.lods {
+            if let Some(lod_mesh) = lod.get_mut(mesh).filter(|lod_mesh| lod_mesh.node == node) {
+                lod_mesh.set_morph_weights(weights);
+            }
+        }
+        self.pose_bounds();
+    }
+
+    /// Poses the skeleton with explicit local node transforms.
+    pub fn set_pose(&mut self, pose: &[Transform]) {
+        if let Some(skeleton) = &self.skeleton {
+            self.joint_matrices = skeleton.joint_matrices(pose);
+            self.joint_buffer.upload(&self.joint_matrices);
+            self.pose_bounds();
+        }
+    }
+
+    /// Updates the bounds of skinned and morphed meshes, and the model's own
+    /// if it has any, after `joint_matrices` or morph weights changed.
+    /// Culling and level of detail selection would otherwise use the bind
+    /// pose.
+    fn pose_bounds(&mut self) {
+        let joint_matrices = &self.joint_matrices;
+        let meshes = self.meshes.iter_mut().chain(self.lods.iter_mut().flatten());
+        for mesh in meshes {
+            mesh.pose_bounds(joint_matrices);
+        }
+
+        if self.meshes.iter().any(|mesh| mesh.is_skinned() || mesh.morph_target_count() > 0) {
+            self.aabb = self.meshes.iter().fold(Aabb::EMPTY, |aabb, mesh| aabb.union(mesh.aabb()));
+            self.bounding_sphere = BoundingSphere::new(self.aabb.center(), self.aabb.extents().length());
+        }
+    }
+
+    /// Binds the current pose for skinned meshes.
+    pub fn bind_joint_matrices(&self) {
+        if !self.joint_matrices.is_empty() {
+            self.joint_buffer.bind();
+        }
+    }
+
+    pub(super) fn joint_buffer(&self) -> &JointBuffer {
+        &self.joint_buffer
+    }
+
+    /// Vertex and index memory of all meshes. Textures are accounted for by
+    /// the `AssetManager` that loaded them.
+    pub fn size_bytes(&self) -> usize {
+        (0..self.lod_count())
+            .flat_map(|level| self.lod_meshes(level))
+            .map(|mesh| mesh.size_bytes())
+            .sum()
+    }
+
+    /// Draws opaque and alpha-tested meshes first, then blended ones with
+    /// depth writes off so they do not hide each other. Blended meshes are
+    /// drawn in file order; use a `RenderQueue` to sort them.
     pub fn draw(&self, shader: &Shader) {
-        for mesh in &self.meshes {
-            mesh.draw(shader);
+        self.draw_meshes(|mesh, material| mesh.draw(shader, material));
+    }
+
+    /// Like `draw`, once per entry in `instances`, with one
+    /// `DrawElementsInstanced` per mesh.
+    pub fn draw_instanced(&self, shader: &Shader, instances: &InstanceBuffer) {
+        self.draw_meshes(|mesh, material| mesh.draw_instanced(shader, material, instances));
+    }
+
+    fn draw_meshes<F: Fn(&Mesh, &Material)>(&self, draw: F) {
+        self.bind_joint_matrices();
+
+        let is_blended = |mesh: &&Mesh| self.materials[mesh.material].blend_mode() == BlendMode::Blend;
+
+        let meshes = self.active_meshes();
+        for mesh in meshes.iter().filter(|mesh| !is_blended(mesh)) {
+            draw(mesh, &self.materials[mesh.material]);
+        }
+
+        if !meshes.iter().any(|mesh| is_blended(&mesh)) {
+            return;
+        }
+
+        unsafe {
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            gl::DepthMask(gl::FALSE);
+        }
+
+        for mesh in meshes.iter().filter(is_blended) {
+            draw(mesh, &self.materials[mesh.material]);
+        }
+
+        unsafe {
+            gl::DepthMask(gl::TRUE);
+            gl::Disable(gl::BLEND);
         }
     }
 }
@@ -0,0 +1,149 @@
+use glam::*;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// A texture referenced from an MTL map statement (`map_Kd`, `map_Ks`, ...)
+/// together with the options given before the file name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureMap {
+    pub path: PathBuf,
+    /// `-o u v w`
+    pub offset: Vec3,
+    /// `-s u v w`
+    pub scale: Vec3,
+    /// `-bm mult`
+    pub bump_multiplier: f32,
+}
+
+/// Number of arguments taken by map options we parse but do not use.
+fn ignored_option_arity(flag: &str) -> Option<usize> {
+    match flag {
+        "-blendu" | "-blendv" | "-cc" | "-clamp" | "-boost" | "-texres" | "-imfchan" | "-type" => {
+            Some(1)
+        }
+        "-mm" => Some(2),
+        _ => None,
+    }
+}
+
+/// Reads up to three numbers following `-s`, `-o` or `-t`; missing
+/// components keep their default.
+fn parse_vec3<'a, I>(tokens: &mut std::iter::Peekable<I>, default: Vec3) -> Vec3
+where
+    I: Iterator<Item = &'a str>,
+{
+    let mut value = default;
+    for i in 0..3 {
+        match tokens.peek().and_then(|token| token.parse::<f32>().ok()) {
+            Some(component) => {
+                value[i] = component;
+                tokens.next();
+            }
+            None => break,
+        }
+    }
+    value
+}
+
+impl TextureMap {
+    /// Parses the argument of a map statement, e.g.
+    /// `-bm 0.5 -s 2 2 1 textures\brick.png`, and resolves the file name with
+    /// `resolve_path`. Returns `None` for an empty statement.
+    pub fn parse(statement: &str, base_dir: &Path, search_paths: &[PathBuf]) -> Option<TextureMap> {
+        let mut offset = Vec3::ZERO;
+        let mut scale = Vec3::ONE;
+        let mut bump_multiplier = 1.0;
+
+        let mut tokens = statement.split_whitespace().peekable();
+        let mut name = Vec::new();
+
+        while let Some(token) = tokens.next() {
+            match token {
+                "-o" => offset = parse_vec3(&mut tokens, offset),
+                "-s" => scale = parse_vec3(&mut tokens, scale),
+                "-t" => {
+                    parse_vec3(&mut tokens, Vec3::ZERO);
+                }
+                "-bm" => {
+                    if let Some(value) = tokens.next().and_then(|t| t.parse().ok()) {
+                        bump_multiplier = value;
+                    }
+                }
+                flag => match ignored_option_arity(flag) {
+                    Some(arity) => {
+                        for _ in 0..arity {
+                            tokens.next();
+                        }
+                    }
+                    // Everything after the options is the file name, which
+                    // may contain spaces.
+                    None => {
+                        name.push(token);
+                        name.extend(tokens.by_ref());
+                    }
+                },
+            }
+        }
+
+        if name.is_empty() {
+            return None;
+        }
+
+        Some(TextureMap {
+            path: resolve_path(&name.join(" "), base_dir, search_paths),
+            offset,
+            scale,
+            bump_multiplier,
+        })
+    }
+}
+
+/// Names of the materials in MTL `source` that have a `d` statement, which
+/// tobj does not tell apart from the default dissolve of 1.
+pub fn materials_with_dissolve(source: &str) -> HashSet<String> {
+    let mut names = HashSet::new();
+    let mut current = "";
+    for line in source.lines() {
+        let line = line.trim();
+        match line.split_whitespace().next() {
+            Some("newmtl") => current = line["newmtl".len()..].trim(),
+            Some("d") => {
+                names.insert(current.to_owned());
+            }
+            _ => {}
+        }
+    }
+    names
+}
+
+/// Finds a file referenced from an OBJ or MTL file.
+///
+/// Backslashes are treated as separators. The name is tried relative to
+/// `base_dir` (the directory of the referencing file), then relative to each
+/// search path, and finally by bare file name in the same places, which
+/// covers absolute paths from the machine the asset was exported on. If
+/// nothing exists the path relative to `base_dir` is returned so the error
+/// names the most likely location.
+pub fn resolve_path(name: &str, base_dir: &Path, search_paths: &[PathBuf]) -> PathBuf {
+    let relative = PathBuf::from(name.replace('\\', "/"));
+
+    let mut candidates = Vec::new();
+    if relative.is_absolute() {
+        candidates.push(relative.clone());
+    }
+    candidates.push(base_dir.join(&relative));
+    for dir in search_paths {
+        candidates.push(dir.join(&relative));
+    }
+    if let Some(file_name) = relative.file_name() {
+        candidates.push(base_dir.join(file_name));
+        for dir in search_paths {
+            candidates.push(dir.join(file_name));
+        }
+    }
+
+    candidates
+        .into_iter()
+        .find(|candidate| candidate.is_file())
+        .unwrap_or_else(|| base_dir.join(relative))
+}
@@ -0,0 +1,226 @@
+use gl::types::*;
+use std::error::Error;
+use std::rc::Rc;
+
+use super::gl_version_at_least;
+use super::leak_tracker::{self, GlObject};
+use super::shader::Shader;
+
+/// Weighted blended order-independent transparency (McGuire & Bavoil 2013).
+///
+/// Transparent surfaces are accumulated into an RGBA16F target weighted by
+/// depth and coverage, while an R8 target multiplies up how much of the
+/// background is still revealed. `resolve` then composites the average
+/// color over the default framebuffer. Needs GL 4.0 for per-target blend
+/// functions.
+pub struct WeightedBlendedOit {
+    fbo: GLuint,
+    accum: GLuint,
+    revealage: GLuint,
+    depth: GLuint,
+    width: u32,
+    height: u32,
+    composite: Rc<Shader>,
+    /// Core profiles need a bound VAO even for attribute-less draws.
+    vao: GLuint,
+}
+
+unsafe fn allocate_texture(
+    texture: GLuint,
+    internal_format: GLenum,
+    format: GLenum,
+    width: u32,
+    height: u32,
+) {
+    gl::BindTexture(gl::TEXTURE_2D, texture);
+    gl::TexImage2D(
+        gl::TEXTURE_2D,
+        0,
+        internal_format as GLint,
+        width as GLsizei,
+        height as GLsizei,
+        0,
+        format,
+        gl::FLOAT,
+        std::ptr::null(),
+    );
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
+}
+
+#[allow(dead_code)]
+impl WeightedBlendedOit {
+    /// `composite` is the full-screen resolve program
+    /// (`oit_composite_vert.glsl` / `oit_composite_frag.glsl`).
+    pub fn new(
+        width: u32,
+        height: u32,
+        composite: Rc<Shader>,
+    ) -> Result<WeightedBlendedOit, Box<dyn Error>> {
+        if !gl_version_at_least(4, 0) {
+            return Err("Weighted blended OIT requires OpenGL 4.0".into());
+        }
+
+        let mut oit = WeightedBlendedOit {
+            fbo: 0,
+            accum: 0,
+            revealage: 0,
+            depth: 0,
+            width: 0,
+            height: 0,
+            composite,
+            vao: 0,
+        };
+
+        unsafe {
+            gl::GenFramebuffers(1, &mut oit.fbo);
+            gl::GenTextures(1, &mut oit.accum);
+            gl::GenTextures(1, &mut oit.revealage);
+            gl::GenRenderbuffers(1, &mut oit.depth);
+            gl::GenVertexArrays(1, &mut oit.vao);
+        }
+        leak_tracker::created(GlObject::Framebuffer, oit.fbo);
+        leak_tracker::created(GlObject::Texture, oit.accum);
+        leak_tracker::created(GlObject::Texture, oit.revealage);
+        leak_tracker::created(GlObject::Renderbuffer, oit.depth);
+        leak_tracker::created(GlObject::VertexArray, oit.vao);
+        oit.resize(width, height);
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, oit.fbo);
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_2D,
+                oit.accum,
+                0,
+            );
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT1,
+                gl::TEXTURE_2D,
+                oit.revealage,
+                0,
+            );
+            gl::FramebufferRenderbuffer(
+                gl::FRAMEBUFFER,
+                gl::DEPTH_STENCIL_ATTACHMENT,
+                gl::RENDERBUFFER,
+                oit.depth,
+            );
+            let draw_buffers = [gl::COLOR_ATTACHMENT0, gl::COLOR_ATTACHMENT1];
+            gl::DrawBuffers(2, draw_buffers.as_ptr());
+
+            let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                return Err(format!("OIT framebuffer incomplete: 0x{:x}", status).into());
+            }
+        }
+
+        Ok(oit)
+    }
+
+    /// Reallocates the targets, which must match the default framebuffer
+    /// for its depth to be copied.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if (width, height) == (self.width, self.height) {
+            return;
+        }
+        self.width = width;
+        self.height = height;
+
+        unsafe {
+            allocate_texture(self.accum, gl::RGBA16F, gl::RGBA, width, height);
+            allocate_texture(self.revealage, gl::R8, gl::RED, width, height);
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+
+            gl::BindRenderbuffer(gl::RENDERBUFFER, self.depth);
+            gl::RenderbufferStorage(
+                gl::RENDERBUFFER,
+                gl::DEPTH24_STENCIL8,
+                width as GLsizei,
+                height as GLsizei,
+            );
+            gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+        }
+    }
+
+    /// Copies the opaque depth from the default framebuffer so transparent
+    /// surfaces behind it are rejected, then clears and binds the targets.
+    pub fn begin(&self) {
+        let (width, height) = (self.width as GLint, self.height as GLint);
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.fbo);
+            gl::BlitFramebuffer(
+                0,
+                0,
+                width,
+                height,
+                0,
+                0,
+                width,
+                height,
+                gl::DEPTH_BUFFER_BIT,
+                gl::NEAREST,
+            );
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+
+            let no_color = [0.0f32; 4];
+            let fully_revealed = [1.0f32; 4];
+            gl::ClearBufferfv(gl::COLOR, 0, no_color.as_ptr());
+            gl::ClearBufferfv(gl::COLOR, 1, fully_revealed.as_ptr());
+
+            gl::Enable(gl::BLEND);
+            gl::BlendFunci(0, gl::ONE, gl::ONE);
+            gl::BlendFunci(1, gl::ZERO, gl::ONE_MINUS_SRC_COLOR);
+            gl::DepthMask(gl::FALSE);
+        }
+    }
+
+    /// Blends the accumulated transparency over the default framebuffer.
+    pub fn resolve(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::DepthMask(gl::TRUE);
+            gl::Disable(gl::DEPTH_TEST);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, self.accum);
+            gl::ActiveTexture(gl::TEXTURE1);
+            gl::BindTexture(gl::TEXTURE_2D, self.revealage);
+        }
+
+        self.composite.use_shader();
+        self.composite.set_i32("accum_tex", 0);
+        self.composite.set_i32("revealage_tex", 1);
+
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+            gl::BindVertexArray(0);
+
+            gl::Enable(gl::DEPTH_TEST);
+            gl::Disable(gl::BLEND);
+        }
+    }
+}
+
+impl Drop for WeightedBlendedOit {
+    fn drop(&mut self) {
+        leak_tracker::deleted(GlObject::Framebuffer, self.fbo);
+        leak_tracker::deleted(GlObject::Texture, self.accum);
+        leak_tracker::deleted(GlObject::Texture, self.revealage);
+        leak_tracker::deleted(GlObject::Renderbuffer, self.depth);
+        leak_tracker::deleted(GlObject::VertexArray, self.vao);
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.fbo);
+            gl::DeleteTextures(1, &self.accum);
+            gl::DeleteTextures(1, &self.revealage);
+            gl::DeleteRenderbuffers(1, &self.depth);
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
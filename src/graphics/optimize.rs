@@ -0,0 +1,311 @@
+//! Index and vertex reordering for faster rendering, without changing the
+//! rendered mesh.
+
+use gl::types::*;
+use glam::*;
+
+use super::model::Vertex;
+
+/// Simulated post-transform cache size for `optimize_vertex_cache`.
+const CACHE_SIZE: usize = 32;
+/// FIFO size used to find cluster boundaries in `optimize_overdraw`, small
+/// enough to be a lower bound for the hardware.
+const OVERDRAW_CACHE_SIZE: usize = 16;
+
+/// Forsyth's vertex score: recently used vertices and those with few
+/// remaining triangles score higher.
+fn vertex_score(cache_position: Option<usize>, remaining_triangles: usize) -> f32 {
+    if remaining_triangles == 0 {
+        return -1.0;
+    }
+
+    let cache_score = match cache_position {
+        // The triangle just drawn; reusing it straight away is not better
+        // than any other recent vertex.
+        Some(position) if position < 3 => 0.75,
+        Some(position) => {
+            let scale = 1.0 / (CACHE_SIZE - 3) as f32;
+            (1.0 - (position - 3) as f32 * scale).powf(1.5)
+        }
+        None => 0.0,
+    };
+    let valence_score = 2.0 * (remaining_triangles as f32).powf(-0.5);
+    cache_score + valence_score
+}
+
+/// Reorders triangles to maximize post-transform vertex cache hits, with
+/// Tom Forsyth's linear-speed algorithm.
+pub fn optimize_vertex_cache(indices: &[GLuint], vertex_count: usize) -> Vec<GLuint> {
+    let triangle_count = indices.len() / 3;
+
+    let mut vertex_triangles: Vec<Vec<usize>> = vec![Vec::new(); vertex_count];
+    for (t, triangle) in indices.chunks_exact(3).enumerate() {
+        for &i in triangle {
+            vertex_triangles[i as usize].push(t);
+        }
+    }
+    let mut remaining: Vec<usize> = vertex_triangles.iter().map(Vec::len).collect();
+    let mut scores: Vec<f32> = remaining
+        .iter()
+        .map(|&remaining| vertex_score(None, remaining))
+        .collect();
+    let triangle_score = |scores: &[f32], t: usize| -> f32 {
+        indices[t * 3..t * 3 + 3]
+            .iter()
+            .map(|&i| scores[i as usize])
+            .sum()
+    };
+
+    let mut emitted = vec![false; triangle_count];
+    let mut cache: Vec<GLuint> = Vec::with_capacity(CACHE_SIZE + 3);
+    let mut optimized = Vec::with_capacity(indices.len());
+    // Next triangle in input order to start from when nothing in the cache
+    // has triangles left.
+    let mut cursor = 0;
+
+    for _ in 0..triangle_count {
+        let candidates = cache
+            .iter()
+            .flat_map(|&i| &vertex_triangles[i as usize])
+            .filter(|&&t| !emitted[t]);
+        let best = candidates
+            .copied()
+            .max_by(|&a, &b| triangle_score(&scores, a).total_cmp(&triangle_score(&scores, b)));
+        let best = match best {
+            Some(best) => best,
+            None => {
+                while emitted[cursor] {
+                    cursor += 1;
+                }
+                cursor
+            }
+        };
+
+        emitted[best] = true;
+        let triangle = &indices[best * 3..best * 3 + 3];
+        optimized.extend_from_slice(triangle);
+
+        // Move the triangle's vertices to the front, evicting the oldest.
+        for &i in triangle.iter().rev() {
+            if let Some(position) = cache.iter().position(|&cached| cached == i) {
+                cache.remove(position);
+            }
+            cache.insert(0, i);
+            remaining[i as usize] -= 1;
+            vertex_triangles[i as usize].retain(|&t| t != best);
+        }
+        let evicted: Vec<GLuint> = cache.drain(CACHE_SIZE.min(cache.len())..).collect();
+
+        for (position, &i) in cache.iter().enumerate() {
+            scores[i as usize] = vertex_score(Some(position), remaining[i as usize]);
+        }
+        for i in evicted {
+            scores[i as usize] = vertex_score(None, remaining[i as usize]);
+        }
+    }
+
+    optimized
+}
+
+/// Reorders clusters of triangles so that outward facing ones come first,
+/// which lets the depth test reject more of what is behind them, after Sander
+/// et al.'s "Fast Triangle Reordering for Vertex Locality and Reduced
+/// Overdraw".
+///
+/// Expects vertex cache optimized indices. Clusters start wherever a
+/// triangle misses the cache with all three vertices, so the cache
+/// efficiency within each one is kept.
+pub fn optimize_overdraw(vertices: &[Vertex], indices: &[GLuint]) -> Vec<GLuint> {
+    let triangles: Vec<&[GLuint]> = indices.chunks_exact(3).collect();
+    if triangles.is_empty() {
+        return Vec::new();
+    }
+
+    let mut cluster_starts = vec![0];
+    let mut cache: Vec<GLuint> = Vec::with_capacity(OVERDRAW_CACHE_SIZE);
+    for (t, triangle) in triangles.iter().enumerate() {
+        let mut misses = 0;
+        for &i in triangle.iter() {
+            if !cache.contains(&i) {
+                misses += 1;
+                if cache.len() == OVERDRAW_CACHE_SIZE {
+                    cache.remove(0);
+                }
+                cache.push(i);
+            }
+        }
+        if misses == 3 && t > 0 {
+            cluster_starts.push(t);
+        }
+    }
+    cluster_starts.push(triangles.len());
+
+    let position = |i: GLuint| vertices[i as usize].position;
+    let mesh_centroid =
+        indices.iter().fold(Vec3::ZERO, |sum, &i| sum + position(i)) / indices.len() as f32;
+
+    let mut clusters: Vec<(f32, &[&[GLuint]])> = cluster_starts
+        .windows(2)
+        .map(|range| {
+            let cluster = &triangles[range[0]..range[1]];
+            let mut centroid = Vec3::ZERO;
+            let mut normal = Vec3::ZERO;
+            let mut area = 0.0;
+            for triangle in cluster {
+                let [a, b, c] = [0, 1, 2].map(|corner| position(triangle[corner]));
+                let triangle_normal = (b - a).cross(c - a);
+                let triangle_area = triangle_normal.length();
+                centroid += (a + b + c) / 3.0 * triangle_area;
+                normal += triangle_normal;
+                area += triangle_area;
+            }
+            let centroid = if area > 0.0 {
+                centroid / area
+            } else {
+                mesh_centroid
+            };
+            let sort_key = (centroid - mesh_centroid).dot(normal.normalize_or_zero());
+            (sort_key, cluster)
+        })
+        .collect();
+
+    clusters.sort_by(|a, b| b.0.total_cmp(&a.0));
+    clusters
+        .iter()
+        .flat_map(|(_, cluster)| cluster.iter().flat_map(|triangle| triangle.iter().copied()))
+        .collect()
+}
+
+/// Orders vertices by first use in `indices`. Returns the original index of
+/// each vertex in the new order and the remapped indices.
+pub fn optimize_vertex_fetch(
+    indices: &[GLuint],
+    vertex_count: usize,
+) -> (Vec<GLuint>, Vec<GLuint>) {
+    let mut remap = vec![GLuint::MAX; vertex_count];
+    let mut order = Vec::with_capacity(vertex_count);
+    let remapped = indices
+        .iter()
+        .map(|&i| {
+            if remap[i as usize] == GLuint::MAX {
+                remap[i as usize] = order.len() as GLuint;
+                order.push(i);
+            }
+            remap[i as usize]
+        })
+        .collect();
+    (order, remapped)
+}
+
+/// Average cache misses per triangle of `indices` with a FIFO cache of
+/// `cache_size` vertices, 0.5 being about the best possible on large meshes
+/// and 3 the worst.
+pub fn average_cache_miss_ratio(indices: &[GLuint], cache_size: usize) -> f32 {
+    if indices.len() < 3 {
+        return 0.0;
+    }
+
+    let mut cache: Vec<GLuint> = Vec::with_capacity(cache_size);
+    let mut misses = 0;
+    for &i in indices {
+        if !cache.contains(&i) {
+            misses += 1;
+            if cache.len() == cache_size {
+                cache.remove(0);
+            }
+            cache.push(i);
+        }
+    }
+    misses as f32 / (indices.len() / 3) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `size` x `size` quad grid in the XY plane, facing +Z, with its
+    /// triangles in row order.
+    fn grid(size: u32) -> (Vec<Vertex>, Vec<GLuint>) {
+        let mut vertices = Vec::new();
+        for y in 0..=size {
+            for x in 0..=size {
+                vertices.push(Vertex {
+                    position: Vec3::new(x as f32, y as f32, 0.0),
+                    normal: Vec3::Z,
+                    tex_coords: Vec2::ZERO,
+                });
+            }
+        }
+
+        let index = |x: u32, y: u32| y * (size + 1) + x;
+        let mut indices = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                let (a, b) = (index(x, y), index(x + 1, y));
+                let (c, d) = (index(x, y + 1), index(x + 1, y + 1));
+                indices.extend_from_slice(&[a, b, c, c, b, d]);
+            }
+        }
+        (vertices, indices)
+    }
+
+    fn sorted_triangles(indices: &[GLuint]) -> Vec<[GLuint; 3]> {
+        let mut triangles: Vec<[GLuint; 3]> = indices
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+            .collect();
+        triangles.sort_unstable();
+        triangles
+    }
+
+    #[test]
+    fn vertex_cache_permutes_triangles() {
+        let (vertices, indices) = grid(16);
+        let optimized = optimize_vertex_cache(&indices, vertices.len());
+        assert_eq!(sorted_triangles(&optimized), sorted_triangles(&indices));
+    }
+
+    #[test]
+    fn vertex_cache_does_not_increase_misses() {
+        let (vertices, indices) = grid(32);
+        let optimized = optimize_vertex_cache(&indices, vertices.len());
+        let before = average_cache_miss_ratio(&indices, 16);
+        let after = average_cache_miss_ratio(&optimized, 16);
+        assert!(after <= before, "{after} > {before}");
+    }
+
+    #[test]
+    fn overdraw_permutes_triangles() {
+        let (vertices, indices) = grid(16);
+        let optimized =
+            optimize_overdraw(&vertices, &optimize_vertex_cache(&indices, vertices.len()));
+        assert_eq!(sorted_triangles(&optimized), sorted_triangles(&indices));
+    }
+
+    #[test]
+    fn cache_miss_ratio_handles_degenerate_input() {
+        assert_eq!(average_cache_miss_ratio(&[], 16), 0.0);
+        assert_eq!(average_cache_miss_ratio(&[0, 1], 16), 0.0);
+    }
+
+    #[test]
+    fn vertex_fetch_permutes_vertices() {
+        let indices = [3, 1, 4, 4, 1, 0, 2, 5, 3];
+        let (order, remapped) = optimize_vertex_fetch(&indices, 6);
+
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(order, [3, 1, 4, 0, 2, 5]);
+
+        let resolved: Vec<GLuint> = remapped.iter().map(|&i| order[i as usize]).collect();
+        assert_eq!(resolved, indices);
+    }
+
+    #[test]
+    fn vertex_fetch_drops_unused_vertices() {
+        let (order, remapped) = optimize_vertex_fetch(&[2, 0, 3], 5);
+        assert_eq!(order, [2, 0, 3]);
+        assert_eq!(remapped, [0, 1, 2]);
+    }
+}
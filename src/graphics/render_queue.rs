@@ -0,0 +1,200 @@
+use glam::*;
+
+use super::frustum::Frustum;
+use super::material::{BlendMode, Material};
+use super::model::{JointBuffer, Mesh, Model};
+use super::oit::WeightedBlendedOit;
+use super::shader::Shader;
+
+struct DrawItem<'a> {
+    mesh: &'a Mesh,
+    material: &'a Material,
+    transform: Mat4,
+    /// The owning model's pose, for skinned meshes.
+    joints: &'a JointBuffer,
+    /// Squared distance from the camera, filled in by `draw`.
+    distance: f32,
+}
+
+/// Collects the meshes of every model drawn in a frame so that transparent
+/// ones can be drawn after all opaque geometry, either sorted back-to-front
+/// or through weighted blended order-independent transparency.
+///
+/// The queue borrows the models it draws, so it is usually rebuilt every
+/// frame.
+#[derive(Default)]
+pub struct RenderQueue<'a> {
+    opaque: Vec<DrawItem<'a>>,
+    transparent: Vec<DrawItem<'a>>,
+    view_projection: Option<Mat4>,
+    stats: RenderStats,
+}
+
+/// Mesh counts of the meshes submitted to a `RenderQueue`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderStats {
+    pub drawn: usize,
+    pub culled: usize,
+}
+
+#[allow(dead_code)]
+impl<'a> RenderQueue<'a> {
+    pub fn new() -> RenderQueue<'a> {
+        RenderQueue::default()
+    }
+
+    /// A queue that drops meshes outside the frustum of `view_projection`
+    /// as they are submitted.
+    pub fn with_culling(view_projection: Mat4) -> RenderQueue<'a> {
+        RenderQueue {
+            view_projection: Some(view_projection),
+            ..Default::default()
+        }
+    }
+
+    /// Queues the meshes of the model's current level of detail, see
+    /// `Model::update_lod`.
+    pub fn submit(&mut self, model: &'a Model, transform: Mat4) {
+        // Testing against model-space planes avoids transforming every
+        // mesh's bounds.
+        let frustum = self
+            .view_projection
+            .map(|view_projection| Frustum::from_matrix(&(view_projection * transform)));
+
+        if let Some(frustum) = &frustum {
+            if !frustum.intersects_sphere(model.bounding_sphere()) {
+                self.stats.culled += model.active_meshes().len();
+                return;
+            }
+        }
+
+        for mesh in model.active_meshes() {
+            if let Some(frustum) = &frustum {
+                if !frustum.intersects_sphere(mesh.bounding_sphere())
+                    || !frustum.intersects_aabb(mesh.aabb())
+                {
+                    self.stats.culled += 1;
+                    continue;
+                }
+            }
+            self.stats.drawn += 1;
+
+            let material = &model.materials()[mesh.material()];
+            let item = DrawItem {
+                mesh,
+                material,
+                transform,
+                joints: model.joint_buffer(),
+                distance: 0.0,
+            };
+            match material.blend_mode() {
+                BlendMode::Opaque | BlendMode::Mask => self.opaque.push(item),
+                BlendMode::Blend => self.transparent.push(item),
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.opaque.clear();
+        self.transparent.clear();
+        self.stats = RenderStats::default();
+    }
+
+    pub fn stats(&self) -> RenderStats {
+        self.stats
+    }
+
+    /// Number of (opaque, transparent) meshes queued.
+    pub fn len(&self) -> (usize, usize) {
+        (self.opaque.len(), self.transparent.len())
+    }
+
+    /// Draws everything queued with `shader`, which must take a `model`
+    /// matrix and understand the `material` uniforms. With `oit`, transparent
+    /// meshes are accumulated into its targets instead of being sorted.
+    pub fn draw(
+        &mut self,
+        shader: &Shader,
+        camera_position: Vec3,
+        oit: Option<&WeightedBlendedOit>,
+    ) {
+        self.draw_opaque(shader, camera_position);
+        self.draw_transparent(shader, camera_position, oit);
+    }
+
+    /// The first half of `draw`. Anything else that blends over opaque
+    /// geometry, like a ground grid, goes between the two halves.
+    pub fn draw_opaque(&mut self, shader: &Shader, camera_position: Vec3) {
+        update_distances(&mut self.opaque, camera_position);
+
+        shader.use_shader();
+        shader.set_i32("weighted_oit", 0);
+
+        // Front-to-back lets early depth testing reject hidden fragments.
+        self.opaque
+            .sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        for item in &self.opaque {
+            draw_item(shader, item);
+        }
+    }
+
+    /// The second half of `draw`.
+    pub fn draw_transparent(
+        &mut self,
+        shader: &Shader,
+        camera_position: Vec3,
+        oit: Option<&WeightedBlendedOit>,
+    ) {
+        if self.transparent.is_empty() {
+            return;
+        }
+        update_distances(&mut self.transparent, camera_position);
+
+        match oit {
+            Some(oit) => {
+                oit.begin();
+                shader.use_shader();
+                shader.set_i32("weighted_oit", 1);
+                for item in &self.transparent {
+                    draw_item(shader, item);
+                }
+                shader.set_i32("weighted_oit", 0);
+                oit.resolve();
+            }
+            None => {
+                // Whatever was drawn after the opaque pass may have switched
+                // programs.
+                shader.use_shader();
+                self.transparent
+                    .sort_by(|a, b| b.distance.total_cmp(&a.distance));
+                unsafe {
+                    gl::Enable(gl::BLEND);
+                    gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+                    gl::DepthMask(gl::FALSE);
+                }
+                for item in &self.transparent {
+                    draw_item(shader, item);
+                }
+                unsafe {
+                    gl::DepthMask(gl::TRUE);
+                    gl::Disable(gl::BLEND);
+                }
+            }
+        }
+    }
+}
+
+fn update_distances(items: &mut [DrawItem], camera_position: Vec3) {
+    for item in items {
+        let center = item.transform.transform_point3(item.mesh.aabb().center());
+        item.distance = center.distance_squared(camera_position);
+    }
+}
+
+fn draw_item(shader: &Shader, item: &DrawItem) {
+    shader.set_mat4v("model", &item.transform);
+    if item.mesh.is_skinned() {
+        item.joints.bind();
+    }
+    item.mesh.draw(shader, item.material);
+}
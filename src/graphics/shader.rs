@@ -5,6 +5,10 @@ use std::ffi::{CStr, CString};
 use std::fs::read_to_string;
 use std::ptr;
 
+use super::leak_tracker::{self, GlObject};
+use super::shader_cache::ProgramCache;
+use super::{gl_version, gl_version_at_least};
+
 pub struct Shader {
     program: GLuint,
 }
@@ -15,24 +19,10 @@ impl Shader {
         vertex_shader_src: &str,
         fragment_shader_src: &str,
     ) -> Result<Shader, Box<dyn Error>> {
-        let vertex_shader_c_str = CString::new(vertex_shader_src)?;
-        let vertex_shader = Shader::compile_shader(&vertex_shader_c_str, gl::VERTEX_SHADER)?;
-
-        let fragment_shader_c_str = CString::new(fragment_shader_src)?;
-        let fragment_shader = Shader::compile_shader(&fragment_shader_c_str, gl::FRAGMENT_SHADER)?;
-
-        let program;
-
-        unsafe {
-            program = gl::CreateProgram();
-            gl::AttachShader(program, vertex_shader);
-            gl::AttachShader(program, fragment_shader);
-            gl::LinkProgram(program);
-            gl::DeleteShader(vertex_shader);
-            gl::DeleteShader(fragment_shader);
-        }
-
-        Ok(Shader { program })
+        Shader::builder()
+            .vertex(vertex_shader_src)
+            .fragment(fragment_shader_src)
+            .build()
     }
     pub fn from_paths(
         vertex_shader_path: &str,
@@ -44,13 +34,35 @@ impl Shader {
         Shader::new(&vertex_shader_src, &fragment_shader_src)
     }
 
+    pub fn builder() -> ShaderBuilder {
+        ShaderBuilder::default()
+    }
+
+    pub fn compute(compute_shader_src: &str) -> Result<Shader, Box<dyn Error>> {
+        Shader::builder().compute(compute_shader_src).build()
+    }
+
+    pub fn compute_from_path(compute_shader_path: &str) -> Result<Shader, Box<dyn Error>> {
+        let compute_shader_src = read_to_string(compute_shader_path)?;
+        Shader::compute(&compute_shader_src)
+    }
+
     fn compile_shader(source: &CStr, shader_type: GLenum) -> Result<u32, Box<dyn Error>> {
+        let (major, minor) = required_gl_version(shader_type)
+            .ok_or_else(|| format!("Unsupported shader type {}", shader_type))?;
+        if !gl_version_at_least(major, minor) {
+            return Err(format!(
+                "{} shaders require OpenGL {}.{}, context is {:?}",
+                shader_type_name(shader_type),
+                major,
+                minor,
+                gl_version()
+            )
+            .into());
+        }
+
         let shader;
         unsafe {
-            if shader_type != gl::VERTEX_SHADER && shader_type != gl::FRAGMENT_SHADER {
-                return Err(format!("Unsupported shader type {}", shader_type).into());
-            }
-
             shader = gl::CreateShader(shader_type);
             gl::ShaderSource(shader, 1, &source.as_ptr(), ptr::null());
             gl::CompileShader(shader);
@@ -69,13 +81,70 @@ impl Shader {
                     ptr::null_mut(),
                     info_log.as_mut_ptr() as *mut i8,
                 );
-                let info_log_c_string = CString::from_vec_unchecked(info_log);
-                return Err(info_log_c_string.to_str()?.into());
+                gl::DeleteShader(shader);
+                let info_log = String::from_utf8_lossy(&info_log);
+                return Err(format!(
+                    "{} shader: {}",
+                    shader_type_name(shader_type),
+                    info_log.trim_end_matches('\0')
+                )
+                .into());
             }
         }
         Ok(shader)
     }
 
+    fn link_program(shaders: &[GLuint], retrievable: bool) -> Result<GLuint, Box<dyn Error>> {
+        let program;
+        unsafe {
+            program = gl::CreateProgram();
+            leak_tracker::created(GlObject::Program, program);
+            if retrievable {
+                gl::ProgramParameteri(
+                    program,
+                    gl::PROGRAM_BINARY_RETRIEVABLE_HINT,
+                    gl::TRUE as GLint,
+                );
+            }
+            for &shader in shaders {
+                gl::AttachShader(program, shader);
+            }
+            gl::LinkProgram(program);
+            for &shader in shaders {
+                gl::DetachShader(program, shader);
+                gl::DeleteShader(shader);
+            }
+
+            let mut success: GLint = 0;
+            gl::GetProgramiv(program, gl::LINK_STATUS, &mut success);
+            if success != gl::TRUE as GLint {
+                let mut info_log_len: GLint = 0;
+                gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut info_log_len);
+
+                let mut info_log: Vec<u8> = vec![0; info_log_len as usize];
+                gl::GetProgramInfoLog(
+                    program,
+                    info_log_len as GLsizei,
+                    ptr::null_mut(),
+                    info_log.as_mut_ptr() as *mut i8,
+                );
+                leak_tracker::deleted(GlObject::Program, program);
+                gl::DeleteProgram(program);
+                let info_log = String::from_utf8_lossy(&info_log);
+                return Err(info_log.trim_end_matches('\0').to_owned().into());
+            }
+        }
+        Ok(program)
+    }
+
+    /// Runs a compute program over `x * y * z` work groups.
+    pub fn dispatch(&self, x: u32, y: u32, z: u32) {
+        unsafe {
+            gl::UseProgram(self.program);
+            gl::DispatchCompute(x, y, z);
+        }
+    }
+
     pub fn use_shader(&self) {
         unsafe { gl::UseProgram(self.program) };
     }
@@ -88,6 +157,27 @@ impl Shader {
         }
     }
 
+    pub fn set_mat4_array(&self, name: &str, mats: &[Mat4]) {
+        unsafe {
+            let c_str = CString::new(name).unwrap();
+            let location = gl::GetUniformLocation(self.program, c_str.as_ptr());
+            gl::UniformMatrix4fv(
+                location,
+                mats.len() as GLsizei,
+                gl::FALSE,
+                mats.as_ptr() as *const GLfloat,
+            );
+        }
+    }
+
+    pub fn set_f32_array(&self, name: &str, values: &[f32]) {
+        unsafe {
+            let c_str = CString::new(name).unwrap();
+            let location = gl::GetUniformLocation(self.program, c_str.as_ptr());
+            gl::Uniform1fv(location, values.len() as GLsizei, values.as_ptr());
+        }
+    }
+
     pub fn set_vec3v(&self, name: &str, v: &Vec3) {
         unsafe {
             let c_str = CString::new(name).unwrap();
@@ -96,6 +186,14 @@ impl Shader {
         }
     }
 
+    pub fn set_vec4v(&self, name: &str, v: &Vec4) {
+        unsafe {
+            let c_str = CString::new(name).unwrap();
+            let location = gl::GetUniformLocation(self.program, c_str.as_ptr());
+            gl::Uniform4fv(location, 1, &v[0]);
+        }
+    }
+
     pub fn set_vec3(&self, name: &str, v0: f32, v1: f32, v2: f32) {
         unsafe {
             let c_str = CString::new(name).unwrap();
@@ -123,6 +221,162 @@ impl Shader {
 
 impl Drop for Shader {
     fn drop(&mut self) {
+        leak_tracker::deleted(GlObject::Program, self.program);
         unsafe { gl::DeleteProgram(self.program) };
     }
 }
+
+/// Collects the stages of a program before compiling and linking them.
+///
+/// A program is either a graphics pipeline (vertex plus any of tessellation
+/// control/evaluation, geometry and fragment) or a single compute stage.
+#[derive(Default)]
+pub struct ShaderBuilder {
+    stages: Vec<(GLenum, String)>,
+    cache: Option<ProgramCache>,
+}
+
+#[allow(dead_code)]
+impl ShaderBuilder {
+    pub fn stage(mut self, shader_type: GLenum, src: &str) -> ShaderBuilder {
+        self.stages.push((shader_type, src.to_owned()));
+        self
+    }
+
+    pub fn stage_from_path(
+        self,
+        shader_type: GLenum,
+        path: &str,
+    ) -> Result<ShaderBuilder, Box<dyn Error>> {
+        let src = read_to_string(path)?;
+        Ok(self.stage(shader_type, &src))
+    }
+
+    pub fn vertex(self, src: &str) -> ShaderBuilder {
+        self.stage(gl::VERTEX_SHADER, src)
+    }
+
+    pub fn tess_control(self, src: &str) -> ShaderBuilder {
+        self.stage(gl::TESS_CONTROL_SHADER, src)
+    }
+
+    pub fn tess_evaluation(self, src: &str) -> ShaderBuilder {
+        self.stage(gl::TESS_EVALUATION_SHADER, src)
+    }
+
+    pub fn geometry(self, src: &str) -> ShaderBuilder {
+        self.stage(gl::GEOMETRY_SHADER, src)
+    }
+
+    pub fn fragment(self, src: &str) -> ShaderBuilder {
+        self.stage(gl::FRAGMENT_SHADER, src)
+    }
+
+    pub fn compute(self, src: &str) -> ShaderBuilder {
+        self.stage(gl::COMPUTE_SHADER, src)
+    }
+
+    /// Loads the linked program from `cache` when possible and stores it
+    /// there after a source compile otherwise.
+    pub fn cache(mut self, cache: &ProgramCache) -> ShaderBuilder {
+        if ProgramCache::is_supported() {
+            self.cache = Some(cache.clone());
+        }
+        self
+    }
+
+    pub fn build(self) -> Result<Shader, Box<dyn Error>> {
+        self.validate()?;
+
+        let key = self.cache.as_ref().map(|cache| cache.key(&self.stages));
+        if let (Some(cache), Some(key)) = (&self.cache, key) {
+            if let Some(program) = cache.load(key) {
+                return Ok(Shader { program });
+            }
+        }
+
+        let mut shaders = Vec::with_capacity(self.stages.len());
+        for (shader_type, src) in &self.stages {
+            let compiled = CString::new(src.as_str())
+                .map_err(|e| e.into())
+                .and_then(|c_str| Shader::compile_shader(&c_str, *shader_type));
+
+            match compiled {
+                Ok(shader) => shaders.push(shader),
+                Err(e) => {
+                    for shader in shaders {
+                        unsafe { gl::DeleteShader(shader) };
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        let program = Shader::link_program(&shaders, self.cache.is_some())?;
+
+        if let (Some(cache), Some(key)) = (&self.cache, key) {
+            if let Err(e) = cache.store(key, program) {
+                eprintln!("program cache : failed to store binary: {}", e);
+            }
+        }
+
+        Ok(Shader { program })
+    }
+
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let count = |shader_type: GLenum| {
+            self.stages
+                .iter()
+                .filter(|(stage_type, _)| *stage_type == shader_type)
+                .count()
+        };
+
+        for (shader_type, _) in &self.stages {
+            if count(*shader_type) > 1 {
+                return Err(format!(
+                    "Duplicate {} shader stage",
+                    shader_type_name(*shader_type)
+                )
+                .into());
+            }
+        }
+
+        if count(gl::COMPUTE_SHADER) == 1 {
+            if self.stages.len() > 1 {
+                return Err("Compute shaders cannot be linked with other stages".into());
+            }
+            return Ok(());
+        }
+
+        if count(gl::VERTEX_SHADER) == 0 {
+            return Err("Program has no vertex shader stage".into());
+        }
+
+        if count(gl::TESS_CONTROL_SHADER) == 1 && count(gl::TESS_EVALUATION_SHADER) == 0 {
+            return Err("Tessellation control stage requires an evaluation stage".into());
+        }
+
+        Ok(())
+    }
+}
+
+fn required_gl_version(shader_type: GLenum) -> Option<(i32, i32)> {
+    match shader_type {
+        gl::VERTEX_SHADER | gl::GEOMETRY_SHADER | gl::FRAGMENT_SHADER => Some((3, 2)),
+        gl::TESS_CONTROL_SHADER | gl::TESS_EVALUATION_SHADER => Some((4, 0)),
+        gl::COMPUTE_SHADER => Some((4, 3)),
+        _ => None,
+    }
+}
+
+fn shader_type_name(shader_type: GLenum) -> &'static str {
+    match shader_type {
+        gl::VERTEX_SHADER => "Vertex",
+        gl::TESS_CONTROL_SHADER => "Tessellation control",
+        gl::TESS_EVALUATION_SHADER => "Tessellation evaluation",
+        gl::GEOMETRY_SHADER => "Geometry",
+        gl::FRAGMENT_SHADER => "Fragment",
+        gl::COMPUTE_SHADER => "Compute",
+        _ => "Unknown",
+    }
+}
@@ -0,0 +1,154 @@
+use gl::types::*;
+use std::error::Error;
+use std::fs;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+
+use super::gl_str_to_rust_string;
+use super::leak_tracker::{self, GlObject};
+
+/// On-disk store of linked program binaries.
+///
+/// Entries are keyed by a hash of every stage's source together with the
+/// vendor, renderer and version strings of the context, so a driver update
+/// or a GPU change simply misses the cache instead of loading a stale binary.
+#[derive(Clone)]
+pub struct ProgramCache {
+    dir: PathBuf,
+}
+
+#[allow(dead_code)]
+impl ProgramCache {
+    pub fn new<P: AsRef<Path>>(dir: P) -> ProgramCache {
+        ProgramCache {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Whether the driver can hand out program binaries at all.
+    pub fn is_supported() -> bool {
+        let mut formats = 0;
+        unsafe { gl::GetIntegerv(gl::NUM_PROGRAM_BINARY_FORMATS, &mut formats) };
+        formats > 0
+    }
+
+    pub fn key(&self, stages: &[(GLenum, String)]) -> u64 {
+        let mut hasher = Fnv1aHasher::default();
+        for name in [gl::VENDOR, gl::RENDERER, gl::VERSION] {
+            hasher.write(gl_str_to_rust_string(name).as_bytes());
+            hasher.write_u8(0);
+        }
+        for (shader_type, src) in stages {
+            hasher.write_u32(*shader_type);
+            hasher.write(src.as_bytes());
+            hasher.write_u8(0);
+        }
+        hasher.finish()
+    }
+
+    fn entry_path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.bin", key))
+    }
+
+    /// Tries to create a program from a cached binary. Returns `None` when
+    /// there is no entry or the driver rejects it; rejected entries are
+    /// removed so they are rebuilt from source.
+    pub fn load(&self, key: u64) -> Option<GLuint> {
+        let path = self.entry_path(key);
+        let data = fs::read(&path).ok()?;
+        if data.len() < 4 {
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+
+        let format = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+        let binary = &data[4..];
+
+        unsafe {
+            let program = gl::CreateProgram();
+            leak_tracker::created(GlObject::Program, program);
+            gl::ProgramBinary(
+                program,
+                format,
+                binary.as_ptr() as *const GLvoid,
+                binary.len() as GLsizei,
+            );
+
+            let mut success: GLint = 0;
+            gl::GetProgramiv(program, gl::LINK_STATUS, &mut success);
+            if success != gl::TRUE as GLint {
+                eprintln!("program cache : rejected {}", path.display());
+                leak_tracker::deleted(GlObject::Program, program);
+                gl::DeleteProgram(program);
+                let _ = fs::remove_file(&path);
+                return None;
+            }
+
+            Some(program)
+        }
+    }
+
+    pub fn store(&self, key: u64, program: GLuint) -> Result<(), Box<dyn Error>> {
+        let mut length: GLint = 0;
+        unsafe { gl::GetProgramiv(program, gl::PROGRAM_BINARY_LENGTH, &mut length) };
+        if length <= 0 {
+            return Err("Driver returned an empty program binary".into());
+        }
+
+        let mut format: GLenum = 0;
+        let mut binary: Vec<u8> = vec![0; length as usize];
+        unsafe {
+            gl::GetProgramBinary(
+                program,
+                length,
+                std::ptr::null_mut(),
+                &mut format,
+                binary.as_mut_ptr() as *mut GLvoid,
+            )
+        };
+
+        let mut data = Vec::with_capacity(binary.len() + 4);
+        data.extend_from_slice(&format.to_le_bytes());
+        data.extend_from_slice(&binary);
+
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.entry_path(key), data)?;
+        Ok(())
+    }
+
+    /// Removes every cached binary.
+    pub fn clear(&self) -> Result<(), Box<dyn Error>> {
+        if self.dir.exists() {
+            for entry in fs::read_dir(&self.dir)? {
+                let path = entry?.path();
+                if path.extension().map_or(false, |ext| ext == "bin") {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher` its output is stable between
+/// builds, which matters for anything written to disk.
+pub struct Fnv1aHasher(u64);
+
+impl Default for Fnv1aHasher {
+    fn default() -> Fnv1aHasher {
+        Fnv1aHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1aHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
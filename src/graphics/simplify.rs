@@ -0,0 +1,394 @@
+//! Mesh decimation by edge collapse with quadric error metrics (Garland &
+//! Heckbert).
+//!
+//! Edges collapse onto one of their endpoints rather than an optimal new
+//! position, so every remaining vertex is an original one and keeps its
+//! attributes, skin and morph deltas. Vertices on open borders, UV seams and
+//! normal creases never move, which keeps the result free of cracks.
+
+use gl::types::*;
+use glam::*;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use super::model::Vertex;
+
+/// Symmetric 4x4 matrix accumulating squared distances to planes, stored as
+/// its upper triangle.
+#[derive(Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn from_plane(normal: DVec3, distance: f64, weight: f64) -> Quadric {
+        let (a, b, c, d) = (normal.x, normal.y, normal.z, distance);
+        Quadric([
+            a * a * weight,
+            a * b * weight,
+            a * c * weight,
+            a * d * weight,
+            b * b * weight,
+            b * c * weight,
+            b * d * weight,
+            c * c * weight,
+            c * d * weight,
+            d * d * weight,
+        ])
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (value, other) in self.0.iter_mut().zip(other.0) {
+            *value += other;
+        }
+    }
+
+    fn error(&self, p: DVec3) -> f64 {
+        let q = &self.0;
+        let (x, y, z) = (p.x, p.y, p.z);
+        q[0] * x * x
+            + 2.0 * q[1] * x * y
+            + 2.0 * q[2] * x * z
+            + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9]
+    }
+}
+
+/// A collapse of `from` onto `to`, valid while both vertices are unchanged
+/// since it was queued.
+struct Collapse {
+    cost: f64,
+    from: usize,
+    to: usize,
+    from_version: u32,
+    to_version: u32,
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Collapse) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Collapse) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    // Reversed, so the cheapest collapse is at the top of the heap.
+    fn cmp(&self, other: &Collapse) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+fn position(vertices: &[Vertex], i: usize) -> DVec3 {
+    let position = vertices[i].position;
+    position.as_dvec3()
+}
+
+fn triangle_normal(vertices: &[Vertex], triangle: [usize; 3]) -> DVec3 {
+    let [a, b, c] = triangle.map(|i| position(vertices, i));
+    (b - a).cross(c - a)
+}
+
+/// Vertices that have to stay put: those on an edge used by a single
+/// triangle, and those sharing their position with another vertex.
+fn locked_vertices(vertices: &[Vertex], indices: &[GLuint]) -> Vec<bool> {
+    let mut locked = vec![false; vertices.len()];
+
+    let mut edges: HashMap<(GLuint, GLuint), u32> = HashMap::new();
+    for triangle in indices.chunks_exact(3) {
+        for (a, b) in [
+            (triangle[0], triangle[1]),
+            (triangle[1], triangle[2]),
+            (triangle[2], triangle[0]),
+        ] {
+            *edges.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+        }
+    }
+    for (&(a, b), &count) in &edges {
+        if count == 1 {
+            locked[a as usize] = true;
+            locked[b as usize] = true;
+        }
+    }
+
+    let mut positions: HashMap<[u32; 3], usize> = HashMap::new();
+    for (i, vertex) in vertices.iter().enumerate() {
+        let position = vertex.position;
+        let key = [
+            position.x.to_bits(),
+            position.y.to_bits(),
+            position.z.to_bits(),
+        ];
+        if let Some(&other) = positions.get(&key) {
+            locked[i] = true;
+            locked[other] = true;
+        } else {
+            positions.insert(key, i);
+        }
+    }
+
+    locked
+}
+
+/// Collapses edges until at most `target_triangles` remain or nothing can
+/// be collapsed without flipping a triangle.
+///
+/// Returns the original indices of the remaining vertices and the new index
+/// buffer, which indexes into them.
+pub fn simplify(
+    vertices: &[Vertex],
+    indices: &[GLuint],
+    target_triangles: usize,
+) -> (Vec<GLuint>, Vec<GLuint>) {
+    let mut triangles: Vec<[usize; 3]> = indices
+        .chunks_exact(3)
+        .map(|triangle| [0, 1, 2].map(|corner| triangle[corner] as usize))
+        .collect();
+    let mut alive = vec![true; triangles.len()];
+    let mut alive_count = triangles.len();
+
+    let locked = locked_vertices(vertices, indices);
+    let mut quadrics = vec![Quadric::default(); vertices.len()];
+    let mut vertex_triangles: Vec<Vec<usize>> = vec![Vec::new(); vertices.len()];
+    for (t, &triangle) in triangles.iter().enumerate() {
+        let normal = triangle_normal(vertices, triangle);
+        let area = normal.length();
+        if area > 0.0 {
+            let normal = normal / area;
+            let distance = -normal.dot(position(vertices, triangle[0]));
+            let quadric = Quadric::from_plane(normal, distance, area * 0.5);
+            for &i in &triangle {
+                quadrics[i].add(&quadric);
+            }
+        }
+        for &i in &triangle {
+            vertex_triangles[i].push(t);
+        }
+    }
+
+    let mut versions = vec![0u32; vertices.len()];
+    let mut heap = BinaryHeap::new();
+    let push = |heap: &mut BinaryHeap<Collapse>,
+                quadrics: &[Quadric],
+                versions: &[u32],
+                from: usize,
+                to: usize| {
+        if locked[from] {
+            return;
+        }
+        let mut quadric = quadrics[from];
+        quadric.add(&quadrics[to]);
+        heap.push(Collapse {
+            cost: quadric.error(position(vertices, to)),
+            from,
+            to,
+            from_version: versions[from],
+            to_version: versions[to],
+        });
+    };
+
+    for triangle in &triangles {
+        for corner in 0..3 {
+            let (a, b) = (triangle[corner], triangle[(corner + 1) % 3]);
+            push(&mut heap, &quadrics, &versions, a, b);
+            push(&mut heap, &quadrics, &versions, b, a);
+        }
+    }
+
+    while alive_count > target_triangles {
+        let collapse = match heap.pop() {
+            Some(collapse) => collapse,
+            None => break,
+        };
+        let (from, to) = (collapse.from, collapse.to);
+        if collapse.from_version != versions[from] || collapse.to_version != versions[to] {
+            continue;
+        }
+
+        // Triangles around `from` that survive must not flip or collapse
+        // to a sliver.
+        let flips = vertex_triangles[from].iter().any(|&t| {
+            let triangle = triangles[t];
+            if !alive[t] || triangle.contains(&to) {
+                return false;
+            }
+            let before = triangle_normal(vertices, triangle);
+            let after = triangle_normal(vertices, triangle.map(|i| if i == from { to } else { i }));
+            before.dot(after) <= 0.5 * before.length() * after.length()
+        });
+        if flips {
+            continue;
+        }
+
+        for t in std::mem::take(&mut vertex_triangles[from]) {
+            if !alive[t] {
+                continue;
+            }
+            if triangles[t].contains(&to) {
+                alive[t] = false;
+                alive_count -= 1;
+            } else {
+                for i in &mut triangles[t] {
+                    if *i == from {
+                        *i = to;
+                    }
+                }
+                vertex_triangles[to].push(t);
+            }
+        }
+        vertex_triangles[to].retain(|&t| alive[t]);
+
+        let from_quadric = quadrics[from];
+        quadrics[to].add(&from_quadric);
+        versions[from] += 1;
+        versions[to] += 1;
+
+        let mut neighbors: Vec<usize> = vertex_triangles[to]
+            .iter()
+            .flat_map(|&t| triangles[t])
+            .filter(|&i| i != to)
+            .collect();
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        for neighbor in neighbors {
+            push(&mut heap, &quadrics, &versions, to, neighbor);
+            push(&mut heap, &quadrics, &versions, neighbor, to);
+        }
+    }
+
+    let mut remap = vec![GLuint::MAX; vertices.len()];
+    let mut kept = Vec::new();
+    let mut simplified_indices = Vec::with_capacity(alive_count * 3);
+    for (triangle, _) in triangles.iter().zip(&alive).filter(|(_, &alive)| alive) {
+        for &i in triangle {
+            if remap[i] == GLuint::MAX {
+                remap[i] = kept.len() as GLuint;
+                kept.push(i as GLuint);
+            }
+            simplified_indices.push(remap[i]);
+        }
+    }
+
+    (kept, simplified_indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A closed torus of `rings` by `sides` quads, without seams so that no
+    /// vertex is locked.
+    fn torus(rings: u32, sides: u32) -> (Vec<Vertex>, Vec<GLuint>) {
+        let mut vertices = Vec::new();
+        for ring in 0..rings {
+            for side in 0..sides {
+                let u = ring as f32 / rings as f32 * std::f32::consts::TAU;
+                let v = side as f32 / sides as f32 * std::f32::consts::TAU;
+                let position = Vec3::new(
+                    (2.0 + v.cos()) * u.cos(),
+                    v.sin(),
+                    (2.0 + v.cos()) * u.sin(),
+                );
+                vertices.push(Vertex {
+                    position,
+                    normal: Vec3::ZERO,
+                    tex_coords: Vec2::ZERO,
+                });
+            }
+        }
+
+        let index = |ring: u32, side: u32| (ring % rings) * sides + side % sides;
+        let mut indices = Vec::new();
+        for ring in 0..rings {
+            for side in 0..sides {
+                let (a, b) = (index(ring, side), index(ring + 1, side));
+                let (c, d) = (index(ring, side + 1), index(ring + 1, side + 1));
+                indices.extend([a, c, b, b, c, d]);
+            }
+        }
+        (vertices, indices)
+    }
+
+    fn check(vertices: &[Vertex], kept: &[GLuint], indices: &[GLuint]) {
+        assert_eq!(indices.len() % 3, 0);
+        assert!(indices.iter().all(|&i| (i as usize) < kept.len()));
+        assert!(kept.iter().all(|&i| (i as usize) < vertices.len()));
+    }
+
+    #[test]
+    fn reaches_target_triangle_count() {
+        let (vertices, indices) = torus(32, 16);
+        for target in [512, 200] {
+            let (kept, simplified) = simplify(&vertices, &indices, target);
+            check(&vertices, &kept, &simplified);
+            assert!(simplified.len() / 3 <= target);
+            assert!(!simplified.is_empty());
+        }
+    }
+
+    #[test]
+    fn leaves_mesh_untouched_when_target_exceeds_triangle_count() {
+        let (vertices, indices) = torus(8, 8);
+        let triangle_count = indices.len() / 3;
+        for target in [triangle_count, triangle_count + 1] {
+            let (kept, simplified) = simplify(&vertices, &indices, target);
+            check(&vertices, &kept, &simplified);
+            assert_eq!(kept.len(), vertices.len());
+            let resolved: Vec<GLuint> = simplified.iter().map(|&i| kept[i as usize]).collect();
+            assert_eq!(resolved, indices);
+        }
+    }
+
+    #[test]
+    fn keeps_border_and_seam_vertices() {
+        // A flat grid whose right half has its own copies of the middle
+        // column, like a UV seam.
+        let (size, middle) = (12, 6);
+        let mut vertices = Vec::new();
+        let mut grid = HashMap::new();
+        for y in 0..=size {
+            for x in 0..=size {
+                for right in [false, true] {
+                    if right && x != middle {
+                        continue;
+                    }
+                    grid.insert((x, y, right), vertices.len() as GLuint);
+                    vertices.push(Vertex {
+                        position: Vec3::new(x as f32, y as f32, 0.0),
+                        normal: Vec3::Z,
+                        tex_coords: Vec2::splat(right as u32 as f32),
+                    });
+                }
+            }
+        }
+
+        let vertex = |x: u32, y: u32, right: bool| grid[&(x, y, right && x == middle)];
+        let mut indices = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                let right = x >= middle;
+                let (a, b) = (vertex(x, y, right), vertex(x + 1, y, right));
+                let (c, d) = (vertex(x, y + 1, right), vertex(x + 1, y + 1, right));
+                indices.extend([a, b, c, c, b, d]);
+            }
+        }
+
+        let (kept, simplified) = simplify(&vertices, &indices, 0);
+        check(&vertices, &kept, &simplified);
+        assert!(simplified.len() < indices.len());
+        for (&(x, y, _), i) in &grid {
+            if x == 0 || x == size || y == 0 || y == size || x == middle {
+                assert!(kept.contains(i), "vertex at ({}, {}) was removed", x, y);
+            }
+        }
+    }
+}
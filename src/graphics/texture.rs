@@ -1,7 +1,308 @@
 use gl::types::*;
-use image::{io::Reader as ImageReader, ColorType};
+use image::{io::Reader as ImageReader, ColorType, DynamicImage};
+use std::cell::OnceCell;
 use std::error::Error;
 use std::ffi::c_void;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+
+use super::compressed::{self, BlockFormat, CompressedImage};
+use super::leak_tracker::{self, GlObject};
+use super::{gl_has_extension, gl_version_at_least};
+
+// EXT/ARB_texture_filter_anisotropic, core since 4.6 and missing from our
+// bindings.
+const TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FF;
+
+// EXT_texture_compression_s3tc and EXT_texture_sRGB, also not core.
+const COMPRESSED_RGB_S3TC_DXT1: GLenum = 0x83F0;
+const COMPRESSED_RGBA_S3TC_DXT1: GLenum = 0x83F1;
+const COMPRESSED_RGBA_S3TC_DXT3: GLenum = 0x83F2;
+const COMPRESSED_RGBA_S3TC_DXT5: GLenum = 0x83F3;
+const COMPRESSED_SRGB_S3TC_DXT1: GLenum = 0x8C4C;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT1: GLenum = 0x8C4D;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT3: GLenum = 0x8C4E;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT5: GLenum = 0x8C4F;
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+    Trilinear,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Wrap {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+}
+
+impl Wrap {
+    fn gl_enum(self) -> GLenum {
+        match self {
+            Wrap::Repeat => gl::REPEAT,
+            Wrap::MirroredRepeat => gl::MIRRORED_REPEAT,
+            Wrap::ClampToEdge => gl::CLAMP_TO_EDGE,
+        }
+    }
+}
+
+/// How an image is uploaded and sampled. The defaults match what
+/// `Texture::from_path` has always done: flipped, repeating, bilinear,
+/// no mipmaps and a linear internal format.
+#[derive(Clone, Copy, Debug)]
+pub struct TextureOptions {
+    pub mipmaps: bool,
+    pub filter: Filter,
+    pub anisotropy: f32,
+    pub wrap_s: Wrap,
+    pub wrap_t: Wrap,
+    pub srgb: bool,
+    pub flip: bool,
+}
+
+// Options are used as cache keys; anisotropy compares by bit pattern so
+// that equality agrees with `Hash`, even for -0.0 and NaN.
+impl PartialEq for TextureOptions {
+    fn eq(&self, other: &TextureOptions) -> bool {
+        self.mipmaps == other.mipmaps
+            && self.filter == other.filter
+            && self.anisotropy.to_bits() == other.anisotropy.to_bits()
+            && self.wrap_s == other.wrap_s
+            && self.wrap_t == other.wrap_t
+            && self.srgb == other.srgb
+            && self.flip == other.flip
+    }
+}
+
+impl Eq for TextureOptions {}
+
+impl Hash for TextureOptions {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.mipmaps.hash(state);
+        self.filter.hash(state);
+        self.anisotropy.to_bits().hash(state);
+        self.wrap_s.hash(state);
+        self.wrap_t.hash(state);
+        self.srgb.hash(state);
+        self.flip.hash(state);
+    }
+}
+
+impl Default for TextureOptions {
+    fn default() -> TextureOptions {
+        TextureOptions {
+            mipmaps: false,
+            filter: Filter::Bilinear,
+            anisotropy: 1.0,
+            wrap_s: Wrap::Repeat,
+            wrap_t: Wrap::Repeat,
+            srgb: false,
+            flip: true,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl TextureOptions {
+    pub fn new() -> TextureOptions {
+        TextureOptions::default()
+    }
+
+    pub fn mipmaps(mut self, mipmaps: bool) -> TextureOptions {
+        self.mipmaps = mipmaps;
+        self
+    }
+
+    pub fn filter(mut self, filter: Filter) -> TextureOptions {
+        self.filter = filter;
+        self
+    }
+
+    /// Maximum anisotropy, clamped to what the driver supports. Values of
+    /// 1.0 or less disable anisotropic filtering.
+    pub fn anisotropy(mut self, anisotropy: f32) -> TextureOptions {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    pub fn wrap(mut self, wrap: Wrap) -> TextureOptions {
+        self.wrap_s = wrap;
+        self.wrap_t = wrap;
+        self
+    }
+
+    pub fn wrap_s(mut self, wrap: Wrap) -> TextureOptions {
+        self.wrap_s = wrap;
+        self
+    }
+
+    pub fn wrap_t(mut self, wrap: Wrap) -> TextureOptions {
+        self.wrap_t = wrap;
+        self
+    }
+
+    /// Store color data in an sRGB internal format so sampling returns
+    /// linear values. Only meaningful for color maps, not for data such as
+    /// specular or normal maps.
+    pub fn srgb(mut self, srgb: bool) -> TextureOptions {
+        self.srgb = srgb;
+        self
+    }
+
+    pub fn flip(mut self, flip: bool) -> TextureOptions {
+        self.flip = flip;
+        self
+    }
+
+    fn min_filter(&self) -> GLenum {
+        match (self.filter, self.mipmaps) {
+            (Filter::Nearest, false) => gl::NEAREST,
+            (Filter::Nearest, true) => gl::NEAREST_MIPMAP_NEAREST,
+            (Filter::Bilinear, false) | (Filter::Trilinear, false) => gl::LINEAR,
+            (Filter::Bilinear, true) => gl::LINEAR_MIPMAP_NEAREST,
+            (Filter::Trilinear, true) => gl::LINEAR_MIPMAP_LINEAR,
+        }
+    }
+
+    fn mag_filter(&self) -> GLenum {
+        match self.filter {
+            Filter::Nearest => gl::NEAREST,
+            Filter::Bilinear | Filter::Trilinear => gl::LINEAR,
+        }
+    }
+}
+
+// Queried once, on the thread the context is current on.
+thread_local! {
+    static MAX_ANISOTROPY: OnceCell<f32> = const { OnceCell::new() };
+}
+
+/// 1.0 when the driver has no anisotropic filtering.
+fn max_anisotropy() -> f32 {
+    MAX_ANISOTROPY.with(|max| {
+        *max.get_or_init(|| {
+            if !gl_version_at_least(4, 6)
+                && !gl_has_extension("GL_EXT_texture_filter_anisotropic")
+                && !gl_has_extension("GL_ARB_texture_filter_anisotropic")
+            {
+                return 1.0;
+            }
+            let mut max = 1.0;
+            unsafe { gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max) };
+            max
+        })
+    })
+}
+
+/// The anisotropy to set for `options`, `None` when it is disabled or not
+/// supported, in which case the parameter must not be set at all.
+fn anisotropy(options: &TextureOptions) -> Option<f32> {
+    (options.anisotropy > 1.0)
+        .then(|| options.anisotropy.min(max_anisotropy()))
+        .filter(|&anisotropy| anisotropy > 1.0)
+}
+
+/// GL upload parameters for a decoded image.
+struct PixelFormat {
+    internal_format: GLenum,
+    format: GLenum,
+    data_type: GLenum,
+    swizzle: Option<[GLint; 4]>,
+}
+
+const SWIZZLE_LUMA: [GLint; 4] = [
+    gl::RED as GLint,
+    gl::RED as GLint,
+    gl::RED as GLint,
+    gl::ONE as GLint,
+];
+const SWIZZLE_LUMA_ALPHA: [GLint; 4] = [
+    gl::RED as GLint,
+    gl::RED as GLint,
+    gl::RED as GLint,
+    gl::GREEN as GLint,
+];
+
+impl PixelFormat {
+    /// sRGB only applies to 8-bit RGB(A); single and dual channel images
+    /// are assumed to hold data (specular, roughness, ...) and float images
+    /// are already linear.
+    fn from_color_type(color_type: ColorType, srgb: bool) -> Option<PixelFormat> {
+        let (internal_format, format, data_type, swizzle) = match color_type {
+            ColorType::L8 => (gl::R8, gl::RED, gl::UNSIGNED_BYTE, Some(SWIZZLE_LUMA)),
+            ColorType::La8 => (gl::RG8, gl::RG, gl::UNSIGNED_BYTE, Some(SWIZZLE_LUMA_ALPHA)),
+            ColorType::Rgb8 if srgb => (gl::SRGB8, gl::RGB, gl::UNSIGNED_BYTE, None),
+            ColorType::Rgb8 => (gl::RGB8, gl::RGB, gl::UNSIGNED_BYTE, None),
+            ColorType::Rgba8 if srgb => (gl::SRGB8_ALPHA8, gl::RGBA, gl::UNSIGNED_BYTE, None),
+            ColorType::Rgba8 => (gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE, None),
+
+            ColorType::L16 => (gl::R16, gl::RED, gl::UNSIGNED_SHORT, Some(SWIZZLE_LUMA)),
+            ColorType::La16 => (
+                gl::RG16,
+                gl::RG,
+                gl::UNSIGNED_SHORT,
+                Some(SWIZZLE_LUMA_ALPHA),
+            ),
+            ColorType::Rgb16 => (gl::RGB16, gl::RGB, gl::UNSIGNED_SHORT, None),
+            ColorType::Rgba16 => (gl::RGBA16, gl::RGBA, gl::UNSIGNED_SHORT, None),
+
+            ColorType::Rgb32F => (gl::RGB32F, gl::RGB, gl::FLOAT, None),
+            ColorType::Rgba32F => (gl::RGBA32F, gl::RGBA, gl::FLOAT, None),
+
+            _ => return None,
+        };
+
+        Some(PixelFormat {
+            internal_format,
+            format,
+            data_type,
+            swizzle,
+        })
+    }
+}
+
+/// Largest `UNPACK_ALIGNMENT` that evenly divides a row, so odd widths of
+/// tightly packed 8-bit RGB or grayscale images upload without skew.
+fn unpack_alignment(row_bytes: usize) -> GLint {
+    match row_bytes {
+        n if n % 8 == 0 => 8,
+        n if n % 4 == 0 => 4,
+        n if n % 2 == 0 => 2,
+        _ => 1,
+    }
+}
+
+/// The contents of an image file before upload. Reading one does not touch
+/// GL, so it can happen on a worker thread.
+pub enum ImageData {
+    Decoded(DynamicImage),
+    /// Already stored bottom-up, see `compressed`.
+    Compressed(CompressedImage),
+}
+
+impl ImageData {
+    /// DDS and KTX2 files are read as compressed blocks, anything else is
+    /// decoded into pixels.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<ImageData, Box<dyn Error>> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+
+        Ok(match extension.as_deref() {
+            Some("dds") => ImageData::Compressed(compressed::parse_dds(&fs::read(path)?)?),
+            Some("ktx2") => ImageData::Compressed(compressed::parse_ktx2(&fs::read(path)?)?),
+            _ => ImageData::Decoded(ImageReader::open(path)?.decode()?),
+        })
+    }
+}
 
 #[allow(dead_code)]
 pub struct Texture {
@@ -9,58 +310,240 @@ pub struct Texture {
     width: u32,
     height: u32,
     format: GLenum,
+    size_bytes: usize,
+    has_alpha: bool,
 }
 
 #[allow(dead_code)]
 impl Texture {
     pub fn from_path(path: &str) -> Result<Texture, Box<dyn Error>> {
-        let img = ImageReader::open(path)?.decode()?.flipv();
+        Texture::from_path_with_options(path, &TextureOptions::default())
+    }
+
+    pub fn from_path_with_options(
+        path: &str,
+        options: &TextureOptions,
+    ) -> Result<Texture, Box<dyn Error>> {
+        let texture = Texture::from_image_data(ImageData::read(path)?, options)?;
         eprintln!("texture create : {}", path);
 
-        let width = img.width();
-        let height = img.height();
+        Ok(texture)
+    }
 
-        let mut texture = 0;
-        unsafe {
-            gl::GenTextures(1, &mut texture);
-            gl::BindTexture(gl::TEXTURE_2D, texture);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+    /// Compressed images ignore `options.flip`, they are stored flipped.
+    pub fn from_image_data(
+        data: ImageData,
+        options: &TextureOptions,
+    ) -> Result<Texture, Box<dyn Error>> {
+        match data {
+            ImageData::Decoded(img) => Texture::from_image(img, options),
+            ImageData::Compressed(image) => Texture::from_compressed(&image, options),
         }
-        let color_type = img.color();
+    }
 
-        let format = match color_type {
-            ColorType::Rgb8 => gl::RGB,
+    pub fn from_image(
+        mut img: DynamicImage,
+        options: &TextureOptions,
+    ) -> Result<Texture, Box<dyn Error>> {
+        if options.flip {
+            img = img.flipv();
+        }
 
-            ColorType::Rgba8 => gl::RGBA,
+        let width = img.width();
+        let height = img.height();
 
-            _ => {
-                return Err(format!("Unsupported color type: {:?}", color_type).into());
-            }
-        };
+        let color_type = img.color();
+        let pixel_format = PixelFormat::from_color_type(color_type, options.srgb)
+            .ok_or_else(|| format!("Unsupported color type: {:?}", color_type))?;
+
+        let row_bytes = width as usize * color_type.bytes_per_pixel() as usize;
 
+        let mut texture = 0;
         unsafe {
+            gl::GenTextures(1, &mut texture);
+            leak_tracker::created(GlObject::Texture, texture);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, unpack_alignment(row_bytes));
             gl::TexImage2D(
                 gl::TEXTURE_2D,
                 0,
-                format as i32,
+                pixel_format.internal_format as i32,
                 width as i32,
                 height as i32,
                 0,
-                format,
-                gl::UNSIGNED_BYTE,
+                pixel_format.format,
+                pixel_format.data_type,
                 img.as_bytes().as_ptr() as *const c_void,
-            )
+            );
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+
+            if let Some(swizzle) = pixel_format.swizzle {
+                gl::TexParameteriv(gl::TEXTURE_2D, gl::TEXTURE_SWIZZLE_RGBA, swizzle.as_ptr());
+            }
         };
 
-        Ok(Texture {
+        let mut size_bytes = img.as_bytes().len();
+        if options.mipmaps {
+            size_bytes += size_bytes / 3;
+        }
+
+        let texture = Texture {
             texture,
             width,
             height,
-            format,
-        })
+            format: pixel_format.format,
+            size_bytes,
+            has_alpha: color_type.has_alpha(),
+        };
+        texture.apply_options(options);
+
+        Ok(texture)
+    }
+
+    /// A 1x1 texture of a single color, used in place of textures that are
+    /// still loading.
+    pub fn solid_color(rgba: [u8; 4]) -> Texture {
+        // Opaque colors are uploaded without alpha so a placeholder does not
+        // switch its material to alpha testing.
+        let img = if rgba[3] == 255 {
+            let [r, g, b, _] = rgba;
+            DynamicImage::ImageRgb8(image::RgbImage::from_pixel(1, 1, image::Rgb([r, g, b])))
+        } else {
+            DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba(rgba)))
+        };
+        Texture::from_image(img, &TextureOptions::default())
+            .expect("RGB8 and RGBA8 are always supported color types")
+    }
+
+    /// Uploads pre-compressed blocks and their mip chain as-is. `flip` is
+    /// ignored since the converter already stores rows bottom-up, and
+    /// `mipmaps` only selects the filter: levels cannot be generated for
+    /// compressed data, so only those in the file are used.
+    pub fn from_compressed(
+        image: &CompressedImage,
+        options: &TextureOptions,
+    ) -> Result<Texture, Box<dyn Error>> {
+        let srgb = image.srgb || (options.srgb && image.format.has_srgb());
+        let internal_format = compressed_internal_format(image.format, srgb);
+
+        if is_s3tc(image.format) && !gl_has_extension("GL_EXT_texture_compression_s3tc") {
+            return Err(format!(
+                "{:?} textures are not supported by this driver",
+                image.format
+            )
+            .into());
+        }
+        if is_bptc(image.format)
+            && !gl_version_at_least(4, 2)
+            && !gl_has_extension("GL_ARB_texture_compression_bptc")
+        {
+            return Err(format!(
+                "{:?} textures are not supported by this driver",
+                image.format
+            )
+            .into());
+        }
+
+        let mut texture = 0;
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+            leak_tracker::created(GlObject::Texture, texture);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            for (level, data) in image.levels.iter().enumerate() {
+                let (width, height) = image.level_dimensions(level);
+                gl::CompressedTexImage2D(
+                    gl::TEXTURE_2D,
+                    level as GLint,
+                    internal_format,
+                    width as GLsizei,
+                    height as GLsizei,
+                    0,
+                    data.len() as GLsizei,
+                    data.as_ptr() as *const c_void,
+                );
+            }
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_BASE_LEVEL, 0);
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_MAX_LEVEL,
+                image.levels.len() as GLint - 1,
+            );
+            // Single channel like `ColorType::L8`, so grayscale images look
+            // the same whether stored as PNG or BC4.
+            if matches!(image.format, BlockFormat::Bc4 | BlockFormat::Bc4Signed) {
+                gl::TexParameteriv(
+                    gl::TEXTURE_2D,
+                    gl::TEXTURE_SWIZZLE_RGBA,
+                    SWIZZLE_LUMA.as_ptr(),
+                );
+            }
+        }
+
+        let texture = Texture {
+            texture,
+            width: image.width,
+            height: image.height,
+            format: internal_format,
+            size_bytes: image.size_bytes(),
+            has_alpha: image.format.has_alpha(),
+        };
+        texture.apply_options(&TextureOptions {
+            mipmaps: false,
+            ..*options
+        });
+        if options.mipmaps && image.levels.len() > 1 {
+            unsafe {
+                gl::TexParameteri(
+                    gl::TEXTURE_2D,
+                    gl::TEXTURE_MIN_FILTER,
+                    options.min_filter() as i32,
+                )
+            };
+        }
+
+        Ok(texture)
+    }
+
+    fn apply_options(&self, options: &TextureOptions) {
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.texture);
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_WRAP_S,
+                options.wrap_s.gl_enum() as i32,
+            );
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_WRAP_T,
+                options.wrap_t.gl_enum() as i32,
+            );
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_MIN_FILTER,
+                options.min_filter() as i32,
+            );
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_MAG_FILTER,
+                options.mag_filter() as i32,
+            );
+            if let Some(anisotropy) = anisotropy(options) {
+                gl::TexParameterf(gl::TEXTURE_2D, TEXTURE_MAX_ANISOTROPY, anisotropy);
+            }
+            if options.mipmaps {
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+            }
+        }
+    }
+
+    /// Whether the texture carries an alpha channel that may be below 1.
+    pub fn has_alpha(&self) -> bool {
+        self.has_alpha
+    }
+
+    /// Approximate GPU memory used, including mip levels.
+    pub fn size_bytes(&self) -> usize {
+        self.size_bytes
     }
 
     pub fn bind(&self, index: u32) {
@@ -71,8 +554,84 @@ impl Texture {
     }
 }
 
+fn is_s3tc(format: BlockFormat) -> bool {
+    matches!(
+        format,
+        BlockFormat::Bc1 | BlockFormat::Bc1a | BlockFormat::Bc2 | BlockFormat::Bc3
+    )
+}
+
+fn is_bptc(format: BlockFormat) -> bool {
+    matches!(
+        format,
+        BlockFormat::Bc6h | BlockFormat::Bc6hSigned | BlockFormat::Bc7
+    )
+}
+
+fn compressed_internal_format(format: BlockFormat, srgb: bool) -> GLenum {
+    match (format, srgb) {
+        (BlockFormat::Bc1, false) => COMPRESSED_RGB_S3TC_DXT1,
+        (BlockFormat::Bc1, true) => COMPRESSED_SRGB_S3TC_DXT1,
+        (BlockFormat::Bc1a, false) => COMPRESSED_RGBA_S3TC_DXT1,
+        (BlockFormat::Bc1a, true) => COMPRESSED_SRGB_ALPHA_S3TC_DXT1,
+        (BlockFormat::Bc2, false) => COMPRESSED_RGBA_S3TC_DXT3,
+        (BlockFormat::Bc2, true) => COMPRESSED_SRGB_ALPHA_S3TC_DXT3,
+        (BlockFormat::Bc3, false) => COMPRESSED_RGBA_S3TC_DXT5,
+        (BlockFormat::Bc3, true) => COMPRESSED_SRGB_ALPHA_S3TC_DXT5,
+        (BlockFormat::Bc4, _) => gl::COMPRESSED_RED_RGTC1,
+        (BlockFormat::Bc4Signed, _) => gl::COMPRESSED_SIGNED_RED_RGTC1,
+        (BlockFormat::Bc5, _) => gl::COMPRESSED_RG_RGTC2,
+        (BlockFormat::Bc5Signed, _) => gl::COMPRESSED_SIGNED_RG_RGTC2,
+        (BlockFormat::Bc6h, _) => gl::COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT,
+        (BlockFormat::Bc6hSigned, _) => gl::COMPRESSED_RGB_BPTC_SIGNED_FLOAT,
+        (BlockFormat::Bc7, false) => gl::COMPRESSED_RGBA_BPTC_UNORM,
+        (BlockFormat::Bc7, true) => gl::COMPRESSED_SRGB_ALPHA_BPTC_UNORM,
+    }
+}
+
 impl Drop for Texture {
     fn drop(&mut self) {
+        leak_tracker::deleted(GlObject::Texture, self.texture);
         unsafe { gl::DeleteTextures(1, &self.texture) };
     }
 }
+
+/// Sampling state that can be shared between textures. While bound to a
+/// unit it overrides the filter and wrap parameters of the texture there.
+pub struct Sampler {
+    sampler: GLuint,
+}
+
+#[allow(dead_code)]
+impl Sampler {
+    pub fn new(options: &TextureOptions) -> Sampler {
+        let mut sampler = 0;
+        unsafe {
+            gl::GenSamplers(1, &mut sampler);
+            leak_tracker::created(GlObject::Sampler, sampler);
+            gl::SamplerParameteri(sampler, gl::TEXTURE_WRAP_S, options.wrap_s.gl_enum() as i32);
+            gl::SamplerParameteri(sampler, gl::TEXTURE_WRAP_T, options.wrap_t.gl_enum() as i32);
+            gl::SamplerParameteri(sampler, gl::TEXTURE_MIN_FILTER, options.min_filter() as i32);
+            gl::SamplerParameteri(sampler, gl::TEXTURE_MAG_FILTER, options.mag_filter() as i32);
+            if let Some(anisotropy) = anisotropy(options) {
+                gl::SamplerParameterf(sampler, TEXTURE_MAX_ANISOTROPY, anisotropy);
+            }
+        }
+        Sampler { sampler }
+    }
+
+    pub fn bind(&self, index: u32) {
+        unsafe { gl::BindSampler(index, self.sampler) };
+    }
+
+    pub fn unbind(index: u32) {
+        unsafe { gl::BindSampler(index, 0) };
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        leak_tracker::deleted(GlObject::Sampler, self.sampler);
+        unsafe { gl::DeleteSamplers(1, &self.sampler) };
+    }
+}
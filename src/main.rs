@@ -79,11 +79,67 @@ fn main() {
     let gl_version = graphics::gl_str_to_rust_string(gl::VERSION);
     println!("gl_version : {}", gl_version);
 
-    let lighting_shader = Shader::from_paths("lighting_vert.glsl", "lighting_frag.glsl")
+    let mut assets = AssetManager::with_program_cache(ProgramCache::new(".shader_cache"));
+
+    let lighting_shader = assets
+        .shader(&[
+            (gl::VERTEX_SHADER, "lighting_vert.glsl"),
+            (gl::FRAGMENT_SHADER, "lighting_frag.glsl"),
+        ])
+        .expect("Shader compile error");
+
+    let light_cube_shader = assets
+        .shader(&[
+            (gl::VERTEX_SHADER, "light_cube_vert.glsl"),
+            (gl::FRAGMENT_SHADER, "light_cube_frag.glsl"),
+        ])
+        .expect("Shader compile error");
+
+    let normal_vis_shader = assets
+        .shader(&[
+            (gl::VERTEX_SHADER, "normal_vis_vert.glsl"),
+            (gl::GEOMETRY_SHADER, "normal_vis_geom.glsl"),
+            (gl::FRAGMENT_SHADER, "normal_vis_frag.glsl"),
+        ])
         .expect("Shader compile error");
 
-    let light_cube_shader = Shader::from_paths("light_cube_vert.glsl", "light_cube_frag.glsl")
+    let debug_shader = assets
+        .shader(&[
+            (gl::VERTEX_SHADER, "debug_vert.glsl"),
+            (gl::FRAGMENT_SHADER, "debug_frag.glsl"),
+        ])
         .expect("Shader compile error");
+    let mut debug_draw = DebugDraw::new(debug_shader);
+
+    let grid_shader = assets
+        .shader(&[
+            (gl::VERTEX_SHADER, "grid_vert.glsl"),
+            (gl::FRAGMENT_SHADER, "grid_frag.glsl"),
+        ])
+        .expect("Shader compile error");
+    let grid = InfiniteGrid::new(grid_shader);
+    let axes_gizmo = AxesGizmo::default();
+
+    let oit_composite_shader = assets
+        .shader(&[
+            (gl::VERTEX_SHADER, "oit_composite_vert.glsl"),
+            (gl::FRAGMENT_SHADER, "oit_composite_frag.glsl"),
+        ])
+        .expect("Shader compile error");
+
+    let mut oit = match WeightedBlendedOit::new(width as u32, height as u32, oit_composite_shader) {
+        Ok(oit) => Some(oit),
+        Err(e) => {
+            eprintln!("weighted blended OIT unavailable, sorting transparency instead: {}", e);
+            None
+        }
+    };
+    let mut use_oit = false;
+
+    // side length of the grid of instanced copies of the model, 1 draws it once
+    let mut instance_grid: usize = 1;
+    let mut instances: Option<InstanceBuffer> = None;
+    let mut instances_origin = Vec3::ZERO;
 
 
     let vertices: Vec<f32> = vec![
@@ -132,21 +188,13 @@ fn main() {
     ];
 
     //let mut cube_vao = 0;
-    let mut light_cube_vao = 0;
-    let mut vbo = 0;
+    let light_cube_vao = VertexArray::new();
+    let vbo = Buffer::new();
 
     unsafe { gl::Enable(gl::DEPTH_TEST) };
+    vbo.upload(gl::ARRAY_BUFFER, &vertices, gl::STATIC_DRAW);
     unsafe {
         //gl::GenVertexArrays(1, &mut cube_vao);
-        gl::GenBuffers(1, &mut vbo);
-
-        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-        gl::BufferData(
-            gl::ARRAY_BUFFER,
-            (vertices.len() * std::mem::size_of::<f32>()) as GLsizeiptr,
-            vertices.as_ptr() as *const std::ffi::c_void,
-            gl::STATIC_DRAW,
-        );
 /*
         gl::BindVertexArray(cube_vao);
 
@@ -180,9 +228,8 @@ fn main() {
         );
         gl::EnableVertexAttribArray(2);
 */
-        gl::GenVertexArrays(1, &mut light_cube_vao);
-        gl::BindVertexArray(light_cube_vao);
-        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        light_cube_vao.bind();
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo.id());
         gl::VertexAttribPointer(
             0,
             3,
@@ -202,6 +249,9 @@ fn main() {
  */
 
     let mut camera = Camera::new();
+    let fov_y = 45.0_f32.to_radians();
+    let aspect = (SCREEN_WIDTH as f32) / (SCREEN_HEIGHT as f32);
+    let mut frame_model = false;
     let mut first_mouse = false;
     let mut last_mouse_x = SCREEN_WIDTH as f32 / 2.0;
     let mut last_mouse_y = SCREEN_HEIGHT as f32 / 2.0;
@@ -224,9 +274,29 @@ fn main() {
 
     let mut material_shininess = 32.0;
 
+    let mut show_normals = false;
+    let mut show_bounds = false;
+    let mut show_light = false;
+    let mut show_grid = true;
+    let mut show_axes_gizmo = true;
+    let mut normal_length = 0.05;
 
 
-    let mut md = Model::new("backpack.obj").unwrap();
+
+    let mut loader = AsyncLoader::with_mesh_cache(2, MeshCache::new(".mesh_cache"));
+    let model_path = std::env::args().nth(1).unwrap_or_else(|| String::from("backpack.obj"));
+    let import_options = ImportOptions {
+        lod_ratios: vec![0.5, 0.25, 0.1],
+        weld_vertices: true,
+        optimize: true,
+        batch_meshes: true,
+        ..Default::default()
+    };
+    loader.load_model_with_options(&model_path, import_options);
+    let mut md: Option<Model> = None;
+    let mut playback = animation::Playback::default();
+    let mut auto_lod = true;
+    let mut forced_lod: usize = 0;
 
 
 
@@ -242,6 +312,23 @@ fn main() {
 
         println!("FPS: {}", 1.0 / delta_time);
 
+        for event in loader.poll(&mut assets) {
+            match event {
+                LoadEvent::ModelReady { model, .. } => {
+                    md = Some(model);
+                    playback = animation::Playback::default();
+                    frame_model = true;
+                }
+                LoadEvent::TextureReady { path, options, texture, .. } => {
+                    if let Some(md) = &mut md {
+                        md.set_texture(&path, &options, texture);
+                    }
+                }
+                LoadEvent::Failed { error, .. } => eprintln!("model load : {}", error),
+                LoadEvent::Finished { .. } => {}
+            }
+        }
+
         if window.get_key(glfw::Key::W) == glfw::Action::Press {
             camera.process_keyboard(camera::Direction::FORWARD, delta_time as f32);
         } else if window.get_key(glfw::Key::S) == glfw::Action::Press {
@@ -254,6 +341,21 @@ fn main() {
             camera.process_keyboard(camera::Direction::RIGHT, delta_time as f32);
         }
 
+        if let Some(md) = &mut md {
+            if let Some(clip) = md.animations().get(playback.clip) {
+                playback.advance(delta_time as f32, clip.duration);
+                md.animate(playback.clip, playback.time);
+            }
+        }
+
+        if frame_model {
+            if let Some(md) = &md {
+                let sphere = md.bounding_sphere().transform(&Mat4::from_translation(cube_pos));
+                camera.frame(&sphere, fov_y, aspect);
+            }
+            frame_model = false;
+        }
+
         camera.update();
 
         unsafe {
@@ -271,8 +373,8 @@ fn main() {
 
 
         let projection = Mat4::perspective_rh_gl(
-            45.0_f32.to_radians(),
-            (SCREEN_WIDTH as f32) / (SCREEN_HEIGHT as f32),
+            fov_y,
+            aspect,
             0.1,
             100.0,
         );
@@ -309,10 +411,8 @@ fn main() {
         model = model * Mat4::from_scale(Vec3::new(1.0, 1.0, 1.0));
         light_cube_shader.set_mat4v("model", &model);
 
-        unsafe {
-            gl::BindVertexArray(light_cube_vao);
-            gl::DrawArrays(gl::TRIANGLES, 0, 36);
-        }
+        light_cube_vao.bind();
+        unsafe { gl::DrawArrays(gl::TRIANGLES, 0, 36) };
         /*
         model_shader.use_shader();
         model_shader.set_mat4v("projection", &projection);
@@ -320,7 +420,82 @@ fn main() {
         model_shader.set_mat4v("model", &model);
          */
 
-        md.draw(&lighting_shader);
+        if let Some(md) = &mut md {
+            if auto_lod {
+                md.update_lod(&Mat4::from_translation(cube_pos), camera.position, fov_y);
+            } else {
+                md.set_lod(forced_lod);
+            }
+        }
+
+        let mut render_queue = RenderQueue::with_culling(projection * view);
+        if let Some(md) = &md {
+            render_queue.submit(md, Mat4::from_translation(cube_pos));
+        }
+        render_queue.draw_opaque(&lighting_shader, camera.position);
+        // Over opaque geometry but under transparent surfaces, which do not
+        // write the depth the grid would need to be hidden behind them.
+        if show_grid {
+            grid.draw(&view, &projection, camera.position);
+        }
+        let oit_pass = if use_oit { oit.as_ref() } else { None };
+        render_queue.draw_transparent(&lighting_shader, camera.position, oit_pass);
+        let render_stats = render_queue.stats();
+
+        if let (true, Some(md)) = (instance_grid > 1, &md) {
+            let count = instance_grid * instance_grid;
+            let stale = instances.as_ref().map_or(true, |instances| instances.len() != count);
+            if stale || instances_origin != cube_pos {
+                let spacing = md.aabb().size().max_element() * 1.2;
+                let mut transforms = Vec::with_capacity(count);
+                let mut colors = Vec::with_capacity(count);
+                for i in 0..count {
+                    let (x, z) = ((i % instance_grid) as f32, (i / instance_grid) as f32);
+                    let offset = Vec3::new(x + 1.0, 0.0, -z) * spacing;
+                    transforms.push(Mat4::from_translation(cube_pos + offset));
+                    colors.push(Vec4::new(
+                        0.5 + 0.5 * x / instance_grid as f32,
+                        0.5 + 0.5 * z / instance_grid as f32,
+                        1.0,
+                        1.0,
+                    ));
+                }
+                instances = Some(InstanceBuffer::new(&transforms, Some(&colors)));
+                instances_origin = cube_pos;
+            }
+            if let Some(instances) = &instances {
+                md.draw_instanced(&lighting_shader, instances);
+            }
+        }
+
+        if let (true, Some(md)) = (show_normals, &md) {
+            normal_vis_shader.use_shader();
+            normal_vis_shader.set_mat4v("projection", &projection);
+            normal_vis_shader.set_mat4v("view", &view);
+            normal_vis_shader.set_mat4v("model", &Mat4::from_translation(cube_pos));
+            normal_vis_shader.set_f32("normal_length", normal_length);
+            md.draw(&normal_vis_shader);
+        }
+
+        if let (true, Some(md)) = (show_bounds, &md) {
+            let transform = Mat4::from_translation(cube_pos);
+            for mesh in md.active_meshes() {
+                debug_draw.aabb(&mesh.aabb().transform(&transform), Vec4::new(0.5, 0.5, 0.5, 0.5));
+            }
+            debug_draw.aabb(&md.aabb().transform(&transform), Vec4::new(1.0, 1.0, 0.0, 1.0));
+            let sphere = md.bounding_sphere().transform(&transform);
+            debug_draw.sphere(sphere.center, sphere.radius, Vec4::new(0.0, 1.0, 1.0, 0.5));
+        }
+        if show_light {
+            debug_draw.set_depth_mode(DepthMode::Overlay);
+            debug_draw.axes(&Mat4::from_translation(light_pos), 0.5);
+            debug_draw.arrow(light_pos, cube_pos, (light_color * 0.5 + 0.5).extend(1.0));
+            debug_draw.set_depth_mode(DepthMode::Tested);
+        }
+        if show_axes_gizmo {
+            axes_gizmo.draw(&mut debug_draw, &camera, &(projection * view));
+        }
+        debug_draw.flush(&(projection * view));
 
         /*
         unsafe {
@@ -335,12 +510,120 @@ fn main() {
         }
         */
 
+        egui::Window::new("Stats").show(&egui_ctx, |ui| {
+            ui.label(format!("{:.0} fps", 1.0 / delta_time));
+            ui.label(format!("meshes drawn: {}", render_stats.drawn));
+            ui.label(format!("meshes culled: {}", render_stats.culled));
+            if let Some(md) = &md {
+                ui.label(format!("LOD: {} of {}", md.lod(), md.lod_count()));
+            }
+        });
+
         egui::Window::new("Controls").show(&egui_ctx, |ui| {
+            for progress in loader.progress() {
+                ui.label(format!(
+                    "Loading {}: {}/{}",
+                    progress.path.display(),
+                    progress.done,
+                    progress.total
+                ));
+            }
+
             ui.heading("Model Position");
             ui.add(egui::Slider::new(&mut cube_pos.x, -10.0..=10.0).text("x"));
             ui.add(egui::Slider::new(&mut cube_pos.y, -10.0..=10.0).text("y"));
             ui.add(egui::Slider::new(&mut cube_pos.z, -10.0..=10.0).text("z"));
 
+            ui.add(egui::Slider::new(&mut instance_grid, 1..=32).text("instance grid"));
+
+            if ui.button("Frame model").clicked() {
+                frame_model = true;
+            }
+
+            ui.checkbox(&mut show_normals, "Show normals");
+            ui.checkbox(&mut show_bounds, "Show bounds");
+            ui.checkbox(&mut show_light, "Show light direction");
+            ui.checkbox(&mut show_grid, "Show ground grid");
+            ui.checkbox(&mut show_axes_gizmo, "Show axes gizmo");
+            ui.scope(|ui| {
+                ui.set_enabled(oit.is_some());
+                ui.checkbox(&mut use_oit, "Order-independent transparency");
+            });
+            ui.add(egui::Slider::new(&mut normal_length, 0.01..=0.5).text("normal length"));
+
+            if let Some(md) = md.as_ref().filter(|md| !md.animations().is_empty()) {
+                ui.heading("Animation");
+                let clips = md.animations();
+                let previous_clip = playback.clip;
+                egui::ComboBox::from_label("clip")
+                    .selected_text(clips[playback.clip].name.clone())
+                    .show_ui(ui, |ui| {
+                        for (i, clip) in clips.iter().enumerate() {
+                            ui.selectable_value(&mut playback.clip, i, clip.name.clone());
+                        }
+                    });
+                if playback.clip != previous_clip {
+                    playback.time = 0.0;
+                }
+
+                ui.horizontal(|ui| {
+                    let label = if playback.playing { "Pause" } else { "Play" };
+                    if ui.button(label).clicked() {
+                        playback.playing = !playback.playing;
+                    }
+                    ui.checkbox(&mut playback.looping, "Loop");
+                });
+                ui.add(egui::Slider::new(&mut playback.speed, -2.0..=2.0).text("speed"));
+                let duration = clips[playback.clip].duration;
+                ui.add(egui::Slider::new(&mut playback.time, 0.0..=duration).text("time"));
+            }
+
+            if let Some(md) = md.as_ref().filter(|md| md.lod_count() > 1) {
+                ui.heading("Level of Detail");
+                ui.checkbox(&mut auto_lod, "Select by screen size");
+                ui.scope(|ui| {
+                    ui.set_enabled(!auto_lod);
+                    ui.add(egui::Slider::new(&mut forced_lod, 0..=md.lod_count() - 1).text("level"));
+                });
+            }
+
+            let has_morph_targets = |md: &&mut Model| md.meshes().iter().any(|mesh| mesh.morph_target_count() > 0);
+            if let Some(md) = md.as_mut().filter(has_morph_targets) {
+                ui.heading("Morph Targets");
+                for i in 0..md.meshes().len() {
+                    let mut weights = md.meshes()[i].morph_weights().to_vec();
+                    let mut changed = false;
+                    for (target, weight) in weights.iter_mut().enumerate() {
+                        let label = format!("mesh {} target {}", i, target);
+                        changed |= ui.add(egui::Slider::new(weight, 0.0..=1.0).text(label)).changed();
+                    }
+                    if changed {
+                        md.set_morph_weights(i, &weights);
+                    }
+                }
+            }
+
+            if let Some(md) = md.as_ref() {
+                ui.heading("Export");
+                let export_path = std::path::Path::new(&model_path).with_extension("");
+                let export_path = export_path.with_file_name(format!(
+                    "{}_export",
+                    export_path.file_name().map_or(String::new(), |name| name.to_string_lossy().into_owned())
+                ));
+                ui.horizontal(|ui| {
+                    if ui.button("OBJ").clicked() {
+                        if let Err(e) = write_obj(&ExportData::from_model(md), &export_path.with_extension("obj")) {
+                            eprintln!("obj export : {}", e);
+                        }
+                    }
+                    if ui.button("glTF").clicked() {
+                        if let Err(e) = write_gltf(&ExportData::from_model(md), &export_path.with_extension("gltf")) {
+                            eprintln!("gltf export : {}", e);
+                        }
+                    }
+                });
+            }
+
             ui.heading("Light Position");
             ui.add(egui::Slider::new(&mut light_pos.x, -10.0..=10.0).text("x"));
             ui.add(egui::Slider::new(&mut light_pos.y, -10.0..=10.0).text("y"));
@@ -379,6 +662,20 @@ fn main() {
                 ui.color_edit_button_rgb(&mut light_specular.as_mut());
             });
 
+            ui.heading("Assets");
+            let usage = assets.memory_usage();
+            ui.label(format!(
+                "{} textures: {:.1} MiB",
+                usage.texture_count,
+                usage.texture_bytes as f32 / (1024.0 * 1024.0)
+            ));
+            let mesh_bytes = usage.mesh_bytes + md.as_ref().map_or(0, |md| md.size_bytes());
+            ui.label(format!(
+                "meshes: {:.1} MiB",
+                mesh_bytes as f32 / (1024.0 * 1024.0)
+            ));
+            ui.label(format!("{} shaders", usage.shader_count));
+
             if ui.button("Quit").clicked() {
                 quit = true;
             }
@@ -414,6 +711,9 @@ fn main() {
                 }
                 glfw::WindowEvent::FramebufferSize(width, height) => {
                     unsafe { gl::Viewport(0, 0, width, height) };
+                    if let Some(oit) = &mut oit {
+                        oit.resize(width as u32, height as u32);
+                    }
                 }
                 glfw::WindowEvent::CursorPos(x, y) => {
                     let x = x as f32;
@@ -458,5 +758,21 @@ fn main() {
         }
     }
 
+    // Everything owning GL objects goes while the context is still current,
+    // so whatever the tracker still knows about has leaked.
+    drop(md);
+    drop(instances);
+    drop(debug_draw);
+    drop(grid);
+    drop(oit);
+    drop((light_cube_vao, vbo));
+    drop((lighting_shader, light_cube_shader, normal_vis_shader));
+    drop(loader);
+    drop(assets);
+    let leaks = leak_tracker::report_leaks();
+    if leaks > 0 {
+        eprintln!("gl leak : {} objects were not deleted", leaks);
+    }
+
     window.close();
 }